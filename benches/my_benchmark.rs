@@ -1,4 +1,5 @@
 use aleven::parse;
+use aleven::CraneliftCodeGen;
 use aleven::Program;
 use aleven::{CodeGen, Function, FunctionValueCache};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
@@ -25,12 +26,26 @@ fn llvm_benchmark(c: &mut Criterion) {
     let mut cache = FunctionValueCache::new();
     let context = Context::create();
     let codegen = CodeGen::new(&context);
-    let f = program.compile(0, &codegen, memory.len() as u16, &mut cache);
+    let f = program.compile(0, &codegen, memory.len() as u32, &mut cache);
 
     c.bench_function("llvm", |b| {
         b.iter(|| Function::run(&f, black_box(&mut memory)))
     });
 }
 
-criterion_group!(benches, interpreter_benchmark, llvm_benchmark);
+fn cranelift_benchmark(c: &mut Criterion) {
+    let mut memory = [0u8; 64];
+    let program = Program::new(&[(0, &parse(CODE).unwrap())]);
+    let mut codegen = CraneliftCodeGen::new();
+    let f = codegen.compile_program(&program, memory.len() as u32);
+
+    c.bench_function("cranelift", |b| b.iter(|| f.run(black_box(&mut memory))));
+}
+
+criterion_group!(
+    benches,
+    interpreter_benchmark,
+    llvm_benchmark,
+    cranelift_benchmark
+);
 criterion_main!(benches);
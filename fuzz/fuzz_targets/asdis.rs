@@ -9,4 +9,9 @@ fuzz_target!(|data: &[u8]| {
     let text = aleven::disassemble(&instructions);
     let parsed = aleven::parse(&text).unwrap();
     assert_eq!(instructions, parsed);
+
+    let options = aleven::DisassemblyOptions { hex: true };
+    let text = aleven::disassemble_with_options(&instructions, &options);
+    let parsed = aleven::parse(&text).unwrap();
+    assert_eq!(instructions, parsed);
 });
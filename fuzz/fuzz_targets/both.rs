@@ -3,6 +3,7 @@ extern crate aleven;
 use aleven::CodeGen;
 use aleven::Function;
 use aleven::FunctionValueCache;
+use aleven::Processor;
 use aleven::Program;
 use aleven::Serializer;
 use inkwell::context::Context;
@@ -14,6 +15,14 @@ fuzz_target!(|data: &[u8]| {
     let mut memory_llvm = data.to_vec();
     let mut memory_interpreter = data.to_vec();
 
+    // seed the random number generator from the data, so that both backends
+    // draw the same numbers
+    let mut seed_bytes = [0u8; 4];
+    for (byte, value) in seed_bytes.iter_mut().zip(data) {
+        *byte = *value;
+    }
+    let seed = u32::from_le_bytes(seed_bytes);
+
     let context = Context::create();
     let codegen = CodeGen::new(&context);
 
@@ -21,14 +30,27 @@ fuzz_target!(|data: &[u8]| {
     let func = program.compile(
         0,
         &codegen,
-        memory_llvm.len() as u16,
+        memory_llvm.len() as u32,
         &mut FunctionValueCache::new(),
     );
     codegen.module.verify().unwrap();
-    Function::run(&func, &mut memory_llvm);
+    let mut registers_llvm = [0i16; 32];
+    let mut rng_state_llvm = seed;
+    Function::run_with_state(
+        &func,
+        &mut memory_llvm,
+        &mut registers_llvm,
+        &mut rng_state_llvm,
+        &[],
+    );
 
-    Program::from_instructions(&instructions).interpret(&mut memory_interpreter);
+    let mut processor = Processor::new();
+    processor.set_rng_state(seed);
+    Program::from_instructions(&instructions)
+        .interpret_with_processor(&mut memory_interpreter, &mut processor);
 
     // the effect should be the same
     assert_eq!(memory_llvm, memory_interpreter);
+    assert_eq!(&registers_llvm, processor.registers());
+    assert_eq!(rng_state_llvm, processor.rng_state());
 });
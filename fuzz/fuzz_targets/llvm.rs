@@ -20,7 +20,7 @@ fuzz_target!(|data: &[u8]| {
     let func = program.compile(
         0,
         &codegen,
-        memory.len() as u16,
+        memory.len() as u32,
         &mut FunctionValueCache::new(),
     );
     codegen.module.verify().unwrap();
@@ -6,11 +6,15 @@ use crate::lang::{
     BranchTargetOpcode,
     CallId,
     CallIdOpcode,
+    Ecall,
+    EcallOpcode,
     Immediate,
     ImmediateOpcode,
     Instruction,
     Load,
     LoadOpcode,
+    Random,
+    RandomOpcode,
     Register,
     RegisterOpcode,
     Store,
@@ -19,35 +23,89 @@ use crate::lang::{
 };
 use crate::program::Program;
 use nom::branch::alt;
-use nom::bytes::complete::{is_not, tag, take_while};
-use nom::character::complete::{alpha1, alphanumeric1, char, multispace1};
-use nom::character::complete::{i16, line_ending, space0, space1, u16, u8};
-use nom::combinator::{eof, map_opt, opt, recognize, value};
-use nom::multi::{many0, many0_count};
+use nom::bytes::complete::{is_a, is_not, tag, take_while};
+use nom::character::complete::{alpha1, alphanumeric1, char, digit1, hex_digit1, multispace1};
+use nom::character::complete::{line_ending, none_of, space0, space1, u8};
+use nom::combinator::{eof, map, map_opt, not, opt, recognize, value, verify};
+use nom::multi::{many0, many0_count, separated_list0};
 use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
 use nom::IResult;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::hash::BuildHasher;
+use std::path::PathBuf;
 use strum::IntoEnumIterator;
 
 type ParseResult<'a, T> = IResult<&'a str, T>; // , VerboseError<&'a str>>;
 
 type FuncIds<'a> = FxHashMap<&'a str, usize>;
 
-#[derive(Debug, PartialEq, Eq)]
+// a number along with whether it's written in hexadecimal or binary
+type Literal = (i64, bool);
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 enum InstructionNode {
     Resolved(Instruction),
     UnresolvedCall(String),
     UnresolvedBranch(BranchOpcode, u8, u8, String),
     UnresolvedTarget(String),
     // UnresolvedSwitch(u8, String, u8),
+    // an instruction with operands given by name, which are 0 until resolved
+    Named(Box<InstructionNode>, Vec<(Field, String)>),
+    Definition(Definition),
+    Expansion(String, Vec<Argument>),
+}
+
+// the fields of an instruction an operand can give the value of. the value
+// is the immediate, the offset or the ecall number
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Field {
+    Rd,
+    Rs,
+    Rs1,
+    Rs2,
+    Value,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Operand<T> {
+    Value(T),
+    Name(String),
+}
+
+// what a parameter of a macro is given in an expansion
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Argument {
+    Register(u8),
+    Literal(Literal),
+    Name(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Definition {
+    Constant(String, Literal),
+    Alias(String, u8),
 }
 
 #[derive(Debug, PartialEq, Eq)]
 struct FunctionNode {
     name: String,
     repeat: u8,
+    definitions: Vec<Definition>,
     instruction_nodes: Vec<InstructionNode>,
+    // the file the function is in, whose definitions it can use
+    file: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct MacroNode {
+    name: String,
+    parameters: Vec<String>,
+    instruction_nodes: Vec<InstructionNode>,
+    // the file the macro is in, whose definitions its body uses wherever it's
+    // expanded
+    file: usize,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -55,37 +113,226 @@ pub enum ResolutionError {
     Call(String),
     Branch(String),
     Switch(String),
+    Constant(String),
+    Alias(String),
+    // a constant that doesn't fit where it's used
+    Range(String),
+    // a name that's defined twice in the same scope
+    Redefinition(String),
+    Macro(String),
+    // a macro expanded with the wrong number of arguments
+    Arguments(String),
+    // a parameter given an argument that doesn't fit where it's used
+    Argument(String),
+    // a macro that expands itself
+    Recursion(String),
+    // a function with more targets than a branch can tell apart
+    Targets(String),
+    // a constant, alias or macro parameter named like a register, which is
+    // read as the register wherever it's used
+    Register(String),
+}
+
+// the constants and register aliases that can be used in part of a program:
+// those of the file, along with those of a function within it
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    constants: FxHashMap<String, Literal>,
+    aliases: FxHashMap<String, u8>,
+}
+
+impl Scope {
+    // the scope within this one that adds definitions. a name can be defined
+    // again in the inner scope, but only once in each scope
+    fn inner(&self, definitions: &[Definition]) -> Result<Scope, Vec<ResolutionError>> {
+        let mut scope = self.clone();
+        let mut defined = FxHashSet::default();
+        let mut errors = Vec::new();
+        for definition in definitions {
+            let name = match definition {
+                Definition::Constant(name, literal) => {
+                    scope.constants.insert(name.clone(), *literal);
+                    name
+                }
+                Definition::Alias(name, register) => {
+                    scope.aliases.insert(name.clone(), *register);
+                    name
+                }
+            };
+            if register_like(name) {
+                errors.push(ResolutionError::Register(name.clone()));
+            }
+            if !defined.insert(name) {
+                errors.push(ResolutionError::Redefinition(name.clone()));
+            }
+        }
+        if errors.is_empty() {
+            Ok(scope)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // the node with the names of its operands filled in
+    fn resolve(&self, node: &InstructionNode) -> Result<InstructionNode, ResolutionError> {
+        let InstructionNode::Named(node, names) = node else {
+            return Ok(node.clone());
+        };
+        let mut node = (**node).clone();
+        for (field, name) in names {
+            self.resolve_name(&mut node, *field, name)?;
+        }
+        Ok(node)
+    }
+
+    // fill in the field of node that name gives
+    fn resolve_name(
+        &self,
+        node: &mut InstructionNode,
+        field: Field,
+        name: &str,
+    ) -> Result<(), ResolutionError> {
+        if field == Field::Value {
+            let literal = self
+                .constants
+                .get(name)
+                .ok_or_else(|| ResolutionError::Constant(name.to_string()))?;
+            if !set_value(node, *literal) {
+                return Err(ResolutionError::Range(name.to_string()));
+            }
+        } else {
+            let register = self
+                .aliases
+                .get(name)
+                .ok_or_else(|| ResolutionError::Alias(name.to_string()))?;
+            set_register(node, field, *register);
+        }
+        Ok(())
+    }
+}
+
+// set the value of an instruction to a constant, if it fits
+fn set_value(node: &mut InstructionNode, literal: Literal) -> bool {
+    let (value, _) = literal;
+    let set = match node {
+        InstructionNode::Resolved(Instruction::Immediate(immediate)) => {
+            immediate_value(literal).map(|value| immediate.value = value)
+        }
+        InstructionNode::Resolved(Instruction::Load(load)) => {
+            u16::try_from(value).ok().map(|value| load.offset = value)
+        }
+        InstructionNode::Resolved(Instruction::Store(store)) => {
+            u16::try_from(value).ok().map(|value| store.offset = value)
+        }
+        InstructionNode::Resolved(Instruction::Ecall(ecall)) => {
+            u16::try_from(value).ok().map(|value| ecall.number = value)
+        }
+        _ => unreachable!("only instructions with a value name one"),
+    };
+    set.is_some()
+}
+
+fn set_register(node: &mut InstructionNode, field: Field, register: u8) {
+    use Field::*;
+    use InstructionNode::{Resolved, UnresolvedBranch};
+    let target = match (node, field) {
+        (Resolved(Instruction::Immediate(immediate)), Rd) => &mut immediate.rd,
+        (Resolved(Instruction::Immediate(immediate)), Rs) => &mut immediate.rs,
+        (Resolved(Instruction::Register(register)), Rd) => &mut register.rd,
+        (Resolved(Instruction::Register(register)), Rs1) => &mut register.rs1,
+        (Resolved(Instruction::Register(register)), Rs2) => &mut register.rs2,
+        (Resolved(Instruction::Load(load)), Rd) => &mut load.rd,
+        (Resolved(Instruction::Load(load)), Rs) => &mut load.rs,
+        (Resolved(Instruction::Store(store)), Rd) => &mut store.rd,
+        (Resolved(Instruction::Store(store)), Rs) => &mut store.rs,
+        (Resolved(Instruction::Random(random)), Rd) => &mut random.rd,
+        (UnresolvedBranch(_, rs1, _, _), Rs1) => rs1,
+        (UnresolvedBranch(_, _, rs2, _), Rs2) => rs2,
+        _ => unreachable!("only registers the instruction has are named"),
+    };
+    *target = register;
+}
+
+// the operands of an instruction that are given by name, along with the field
+// each gives the value of
+#[derive(Default)]
+struct Names(Vec<(Field, String)>);
+
+impl Names {
+    // the value of an operand, or 0 if it's given by name
+    fn take<T: Default>(&mut self, field: Field, operand: Operand<T>) -> T {
+        match operand {
+            Operand::Value(value) => value,
+            Operand::Name(name) => {
+                self.0.push((field, name));
+                T::default()
+            }
+        }
+    }
+
+    fn node(self, node: InstructionNode) -> InstructionNode {
+        if self.0.is_empty() {
+            node
+        } else {
+            InstructionNode::Named(Box::new(node), self.0)
+        }
+    }
+}
+
+// the definitions among nodes, and the other nodes
+fn split_definitions(nodes: Vec<InstructionNode>) -> (Vec<Definition>, Vec<InstructionNode>) {
+    let mut definitions = Vec::new();
+    let mut instruction_nodes = Vec::new();
+    for node in nodes {
+        match node {
+            InstructionNode::Definition(definition) => definitions.push(definition),
+            node => instruction_nodes.push(node),
+        }
+    }
+    (definitions, instruction_nodes)
 }
 
 impl FunctionNode {
-    fn resolve(&self, func_ids: &FuncIds) -> Result<Function, Vec<ResolutionError>> {
-        let targets: Vec<_> = self
-            .instruction_nodes
+    // the names of the targets, in the order of their identifiers
+    fn labels(&self) -> Vec<String> {
+        self.instruction_nodes
             .iter()
             .filter_map(|node| {
                 if let InstructionNode::UnresolvedTarget(name) = node {
-                    Some(name)
+                    Some(name.clone())
                 } else {
                     None
                 }
             })
-            .collect();
+            .collect()
+    }
+
+    fn resolve(
+        &self,
+        func_ids: &FuncIds,
+        file_scope: &Scope,
+    ) -> Result<Function, Vec<ResolutionError>> {
+        let scope = file_scope.inner(&self.definitions)?;
+        let targets = self.labels();
+        if targets.len() > 256 {
+            return Err(vec![ResolutionError::Targets(self.name.clone())]);
+        }
         let target_lookup: FxHashMap<_, _> =
             targets.iter().enumerate().map(|(i, t)| (t, i)).collect();
 
         let (instructions, errors): (Vec<_>, Vec<_>) = self
             .instruction_nodes
             .iter()
-            .map(|node| match node {
-                InstructionNode::Resolved(instruction) => Ok(instruction.clone()),
+            .map(|node| match scope.resolve(node)? {
+                InstructionNode::Resolved(instruction) => Ok(instruction),
                 InstructionNode::UnresolvedBranch(opcode, rs1, rs2, name) => {
                     let identifier = target_lookup
                         .get(&name)
                         .ok_or_else(|| ResolutionError::Branch(name.to_string()))?;
                     Ok(Instruction::Branch(Branch {
-                        opcode: *opcode,
-                        rs1: *rs1,
-                        rs2: *rs2,
+                        opcode,
+                        rs1,
+                        rs2,
                         target: *identifier as u8,
                     }))
                 }
@@ -105,8 +352,13 @@ impl FunctionNode {
                             identifier: *id as u16,
                         }))
                     } else {
-                        Err(ResolutionError::Call(name.clone()))
+                        Err(ResolutionError::Call(name))
                     }
+                }
+                InstructionNode::Named(..)
+                | InstructionNode::Definition(_)
+                | InstructionNode::Expansion(..) => {
+                    unreachable!("names are resolved, definitions split off and macros expanded")
                 } // InstructionNode::UnresolvedSwitch(rs, name, amount) => {
                   //     let id = func_ids.get(&name[..]);
                   //     if let Some(id) = id {
@@ -133,21 +385,345 @@ impl FunctionNode {
 
 #[derive(Debug, PartialEq, Eq)]
 struct ProgramNode {
+    includes: Vec<String>,
+    // the definitions of each file, by the file of a function
+    definitions: Vec<Vec<Definition>>,
+    macros: Vec<MacroNode>,
     function_nodes: Vec<FunctionNode>,
 }
 
+// what a file holds at the top level
+enum Item {
+    Include(String),
+    Function(FunctionNode),
+    Definition(Definition),
+    Macro(MacroNode),
+}
+
+/// Where the files named by `include` directives come from.
+pub trait FileResolver {
+    /// The contents of the file at path, or why it can't be read.
+    fn read(&self, path: &str) -> Result<String, String>;
+}
+
+/// Files on disk, with paths relative to a directory.
+pub struct DirectoryResolver {
+    directory: PathBuf,
+}
+
+impl DirectoryResolver {
+    pub fn new(directory: impl Into<PathBuf>) -> DirectoryResolver {
+        DirectoryResolver {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl FileResolver for DirectoryResolver {
+    fn read(&self, path: &str) -> Result<String, String> {
+        std::fs::read_to_string(self.directory.join(path)).map_err(|e| e.to_string())
+    }
+}
+
+/// Files in memory, by path.
+impl<S: BuildHasher> FileResolver for HashMap<String, String, S> {
+    fn read(&self, path: &str) -> Result<String, String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| "no such file".to_string())
+    }
+}
+
+// what parse_program uses, so that programs can't include files
+struct NoFiles;
+
+impl FileResolver for NoFiles {
+    fn read(&self, _path: &str) -> Result<String, String> {
+        Err("no files to include from".to_string())
+    }
+}
+
+impl ProgramNode {
+    // the program of the file at path with the files it includes merged in
+    // after it, and those they include in turn. a file that's included already
+    // isn't included again. the definitions of each file stay its own
+    fn include(
+        mut self,
+        path: &str,
+        opcodes: &AllOpcodes,
+        resolver: &dyn FileResolver,
+        included: &mut FxHashSet<String>,
+    ) -> Result<ProgramNode, ParseProgramError> {
+        for include in std::mem::take(&mut self.includes) {
+            let include = include_path(path, &include);
+            if !included.insert(include.clone()) {
+                continue;
+            }
+            let input = resolver
+                .read(&include)
+                .map_err(|e| ParseProgramError::IncludeError(format!("{}: {}", include, e)))?;
+            let (_, program_node) = terminated(program(opcodes), eof)(&input)
+                .map_err(|e| ParseProgramError::ParseError(format!("{}: {}", include, e)))?;
+            let mut program_node = program_node.include(&include, opcodes, resolver, included)?;
+            let files = self.definitions.len();
+            for function_node in &mut program_node.function_nodes {
+                function_node.file += files;
+            }
+            for macro_node in &mut program_node.macros {
+                macro_node.file += files;
+            }
+            self.definitions.extend(program_node.definitions);
+            self.macros.extend(program_node.macros);
+            self.function_nodes.extend(program_node.function_nodes);
+        }
+        Ok(self)
+    }
+
+    // the scope of each file
+    fn scopes(&self) -> Result<Vec<Scope>, Vec<ResolutionError>> {
+        self.definitions
+            .iter()
+            .map(|definitions| Scope::default().inner(definitions))
+            .collect()
+    }
+
+    // the program with the macros in its functions expanded
+    fn expand(mut self) -> Result<ProgramNode, Vec<ResolutionError>> {
+        let scopes = self.scopes()?;
+        let mut expander = Expander::new(&self.macros, &scopes)?;
+        let mut errors = Vec::new();
+        for function_node in &mut self.function_nodes {
+            match expander.expand(&function_node.instruction_nodes, &mut Vec::new()) {
+                Ok(nodes) => function_node.instruction_nodes = nodes,
+                Err(expansion_errors) => errors.extend(expansion_errors),
+            }
+        }
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+struct Expander<'a> {
+    macros: FxHashMap<&'a str, &'a MacroNode>,
+    // the scope of each file, which the body of a macro is resolved in
+    scopes: &'a [Scope],
+    // the number of expansions so far, which makes the targets of each unique
+    expansions: usize,
+}
+
+impl<'a> Expander<'a> {
+    fn new(
+        macros: &'a [MacroNode],
+        scopes: &'a [Scope],
+    ) -> Result<Expander<'a>, Vec<ResolutionError>> {
+        let mut lookup = FxHashMap::default();
+        let mut errors = Vec::new();
+        for macro_node in macros {
+            if lookup.insert(&macro_node.name[..], macro_node).is_some() {
+                errors.push(ResolutionError::Redefinition(macro_node.name.clone()));
+            }
+            for parameter in &macro_node.parameters {
+                if register_like(parameter) {
+                    errors.push(ResolutionError::Register(parameter.clone()));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(Expander {
+                macros: lookup,
+                scopes,
+                expansions: 0,
+            })
+        } else {
+            Err(errors)
+        }
+    }
+
+    // the nodes with each expansion replaced by the body of its macro. active
+    // holds the macros that are being expanded
+    fn expand(
+        &mut self,
+        nodes: &[InstructionNode],
+        active: &mut Vec<&'a str>,
+    ) -> Result<Vec<InstructionNode>, Vec<ResolutionError>> {
+        let mut expanded = Vec::new();
+        let mut errors = Vec::new();
+        for node in nodes {
+            match node {
+                InstructionNode::Expansion(name, arguments) => {
+                    match self.expand_macro(name, arguments, active) {
+                        Ok(nodes) => expanded.extend(nodes),
+                        Err(expansion_errors) => errors.extend(expansion_errors),
+                    }
+                }
+                node => expanded.push(node.clone()),
+            }
+        }
+        if errors.is_empty() {
+            Ok(expanded)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn expand_macro(
+        &mut self,
+        name: &str,
+        arguments: &[Argument],
+        active: &mut Vec<&'a str>,
+    ) -> Result<Vec<InstructionNode>, Vec<ResolutionError>> {
+        let macro_node = *self
+            .macros
+            .get(name)
+            .ok_or_else(|| vec![ResolutionError::Macro(name.to_string())])?;
+        if active.contains(&&macro_node.name[..]) {
+            return Err(vec![ResolutionError::Recursion(name.to_string())]);
+        }
+        if arguments.len() != macro_node.parameters.len() {
+            return Err(vec![ResolutionError::Arguments(name.to_string())]);
+        }
+        self.expansions += 1;
+        let expansion = Expansion {
+            bindings: macro_node.parameters.iter().zip(arguments).collect(),
+            targets: macro_node
+                .instruction_nodes
+                .iter()
+                .filter_map(|node| match node {
+                    InstructionNode::UnresolvedTarget(target) => Some(target),
+                    _ => None,
+                })
+                .collect(),
+            scope: &self.scopes[macro_node.file],
+            number: self.expansions,
+        };
+        let nodes = macro_node
+            .instruction_nodes
+            .iter()
+            .map(|node| expansion.substitute(node))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| vec![error])?;
+        active.push(&macro_node.name);
+        let expanded = self.expand(&nodes, active);
+        active.pop();
+        expanded
+    }
+}
+
+// a single expansion of a macro
+struct Expansion<'a> {
+    bindings: FxHashMap<&'a String, &'a Argument>,
+    // the targets of the macro, which are renamed in each expansion so that
+    // they don't clash with those around it
+    targets: FxHashSet<&'a String>,
+    // the scope of the file of the macro
+    scope: &'a Scope,
+    number: usize,
+}
+
+impl<'a> Expansion<'a> {
+    // what a name the macro passes on to another macro stands for: a
+    // constant or an alias of the file of the macro, or else a target or a
+    // function
+    fn argument(&self, name: &String) -> Argument {
+        if let Some(literal) = self.scope.constants.get(name) {
+            Argument::Literal(*literal)
+        } else if let Some(register) = self.scope.aliases.get(name) {
+            Argument::Register(*register)
+        } else {
+            Argument::Name(name.clone())
+        }
+    }
+
+    // the name a parameter, a target of the macro or some other name stands
+    // for. a name has no dots, so the new names of targets are unique
+    fn rename(&self, name: &String) -> Result<String, ResolutionError> {
+        match self.bindings.get(name) {
+            Some(Argument::Name(argument)) => Ok(argument.clone()),
+            Some(_) => Err(ResolutionError::Argument(name.clone())),
+            None if self.targets.contains(name) => Ok(format!("{}.{}", name, self.number)),
+            None => Ok(name.clone()),
+        }
+    }
+
+    // the node with the parameters replaced by their arguments, and the other
+    // names resolved in the scope of the macro. names given as arguments are
+    // left to the scope they come from
+    fn substitute(&self, node: &InstructionNode) -> Result<InstructionNode, ResolutionError> {
+        Ok(match node {
+            InstructionNode::UnresolvedTarget(name) => {
+                InstructionNode::UnresolvedTarget(self.rename(name)?)
+            }
+            InstructionNode::UnresolvedBranch(opcode, rs1, rs2, target) => {
+                InstructionNode::UnresolvedBranch(*opcode, *rs1, *rs2, self.rename(target)?)
+            }
+            InstructionNode::UnresolvedCall(name) => {
+                InstructionNode::UnresolvedCall(self.rename(name)?)
+            }
+            InstructionNode::Named(node, names) => {
+                let mut node = self.substitute(node)?;
+                let mut remaining = Names::default();
+                for (field, name) in names {
+                    match (self.bindings.get(name), field) {
+                        (None, _) => self.scope.resolve_name(&mut node, *field, name)?,
+                        (Some(Argument::Name(argument)), _) => {
+                            remaining.0.push((*field, argument.clone()))
+                        }
+                        (Some(Argument::Literal(literal)), Field::Value) => {
+                            if !set_value(&mut node, *literal) {
+                                return Err(ResolutionError::Range(name.clone()));
+                            }
+                        }
+                        (Some(Argument::Register(register)), field) if *field != Field::Value => {
+                            set_register(&mut node, *field, *register)
+                        }
+                        (Some(_), _) => return Err(ResolutionError::Argument(name.clone())),
+                    }
+                }
+                remaining.node(node)
+            }
+            InstructionNode::Expansion(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| match argument {
+                        Argument::Name(name) => match self.bindings.get(name) {
+                            Some(bound) => Ok((*bound).clone()),
+                            None if self.targets.contains(name) => {
+                                self.rename(name).map(Argument::Name)
+                            }
+                            None => Ok(self.argument(name)),
+                        },
+                        argument => Ok(argument.clone()),
+                    })
+                    .collect::<Result<_, _>>()?;
+                InstructionNode::Expansion(name.clone(), arguments)
+            }
+            node => node.clone(),
+        })
+    }
+}
+
 impl TryFrom<ProgramNode> for Program {
     type Error = Vec<ResolutionError>;
 
     fn try_from(program_node: ProgramNode) -> Result<Program, Vec<ResolutionError>> {
+        let scopes = program_node.scopes()?;
         let mut func_ids = FuncIds::default();
+        let mut errors = Vec::new();
         for (id, function_node) in program_node.function_nodes.iter().enumerate() {
-            func_ids.insert(&function_node.name, id);
+            if func_ids.insert(&function_node.name, id).is_some() {
+                errors.push(ResolutionError::Redefinition(function_node.name.clone()));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
         }
         let (functions, errors): (Vec<_>, Vec<_>) = program_node
             .function_nodes
             .iter()
-            .map(|function_node| function_node.resolve(&func_ids))
+            .map(|function_node| function_node.resolve(&func_ids, &scopes[function_node.file]))
             .partition(Result::is_ok);
         let errors: Vec<_> = errors.into_iter().flat_map(Result::unwrap_err).collect();
         if errors.is_empty() {
@@ -167,6 +743,8 @@ struct AllOpcodes {
     branch_opcodes: Opcodes<BranchOpcode>,
     branch_target_opcodes: Opcodes<BranchTargetOpcode>,
     call_id_opcodes: Opcodes<CallIdOpcode>,
+    ecall_opcodes: Opcodes<EcallOpcode>,
+    random_opcodes: Opcodes<RandomOpcode>,
     // switch_opcodes: Opcodes<SwitchOpcode>,
 }
 
@@ -180,6 +758,8 @@ impl AllOpcodes {
             branch_opcodes: Opcodes::new(),
             branch_target_opcodes: Opcodes::new(),
             call_id_opcodes: Opcodes::new(),
+            ecall_opcodes: Opcodes::new(),
+            random_opcodes: Opcodes::new(),
             // switch_opcodes: Opcodes::new(),
         }
     }
@@ -207,6 +787,121 @@ fn register(input: &str) -> ParseResult<u8> {
     preceded(tag("r"), u8)(input)
 }
 
+// whether a name is written like a register, r followed by digits
+fn register_like(name: &str) -> bool {
+    name.strip_prefix('r')
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+// a number in decimal, in hexadecimal after 0x or in binary after 0b, with
+// an optional minus sign, or a character in single quotes. along with the
+// value comes whether it's written in hexadecimal or binary
+fn literal(input: &str) -> ParseResult<'_, (i64, bool)> {
+    let (input, minus) = opt(char('-'))(input)?;
+    let (input, (value, bits)) = alt((
+        map_opt(preceded(tag("0x"), hex_digit1), |digits| {
+            i64::from_str_radix(digits, 16)
+                .ok()
+                .map(|value| (value, true))
+        }),
+        map_opt(preceded(tag("0b"), is_a("01")), |digits| {
+            i64::from_str_radix(digits, 2)
+                .ok()
+                .map(|value| (value, true))
+        }),
+        map(delimited(char('\''), character, char('\'')), |c| {
+            (c as i64, false)
+        }),
+        map_opt(digit1, |digits: &str| {
+            digits.parse::<i64>().ok().map(|value| (value, false))
+        }),
+    ))(input)?;
+    let value = if minus.is_some() { -value } else { value };
+    Ok((input, (value, bits)))
+}
+
+// a character, where a backslash escapes a quote, a backslash, a newline, a
+// tab or a zero byte
+fn character(input: &str) -> ParseResult<'_, char> {
+    alt((
+        preceded(
+            char('\\'),
+            alt((
+                value('\'', char('\'')),
+                value('\\', char('\\')),
+                value('\n', char('n')),
+                value('\t', char('t')),
+                value('\0', char('0')),
+            )),
+        ),
+        none_of("\\'"),
+    ))(input)
+}
+
+// a literal that fits in T
+fn number<T: TryFrom<i64>>(input: &str) -> ParseResult<'_, T> {
+    map_opt(literal, |(value, _)| T::try_from(value).ok())(input)
+}
+
+// an immediate value. as these are 16 bits, in hexadecimal or binary they may
+// also be the bits of a negative value, as in 0xffff for -1
+fn immediate(input: &str) -> ParseResult<'_, i16> {
+    map_opt(literal, immediate_value)(input)
+}
+
+fn immediate_value((value, bits): Literal) -> Option<i16> {
+    match i16::try_from(value) {
+        Ok(value) => Some(value),
+        Err(_) if bits => u16::try_from(value).ok().map(|value| value as i16),
+        Err(_) => None,
+    }
+}
+
+fn operand_name<T>(input: &str) -> ParseResult<'_, Operand<T>> {
+    map(identifier, |name| Operand::Name(name.to_string()))(input)
+}
+
+// a register that isn't the start of a longer name
+fn whole_register(input: &str) -> ParseResult<'_, u8> {
+    terminated(register, not(alt((alphanumeric1, tag("_")))))(input)
+}
+
+// a register, or the name of an alias of one
+fn register_operand(input: &str) -> ParseResult<'_, Operand<u8>> {
+    alt((map(whole_register, Operand::Value), operand_name))(input)
+}
+
+// an immediate, or the name of a constant
+fn immediate_operand(input: &str) -> ParseResult<'_, Operand<i16>> {
+    alt((map(immediate, Operand::Value), operand_name))(input)
+}
+
+// a number, or the name of a constant
+fn number_operand<T: TryFrom<i64>>(input: &str) -> ParseResult<'_, Operand<T>> {
+    alt((map(number, Operand::Value), operand_name))(input)
+}
+
+// const NAME = value, or alias NAME = register
+fn definition(input: &str) -> ParseResult<'_, Definition> {
+    let equals = || delimited(space0, tag("="), space0);
+    alt((
+        map(
+            preceded(
+                pair(tag("const"), space1),
+                separated_pair(identifier, equals(), literal),
+            ),
+            |(name, literal)| Definition::Constant(name.to_string(), literal),
+        ),
+        map(
+            preceded(
+                pair(tag("alias"), space1),
+                separated_pair(identifier, equals(), register),
+            ),
+            |(name, register)| Definition::Alias(name.to_string(), register),
+        ),
+    ))(input)
+}
+
 fn opcode<'a, T: Display + IntoEnumIterator + Copy>(
     opcodes: &'a Opcodes<T>,
 ) -> impl Fn(&'a str) -> ParseResult<'a, T> {
@@ -222,23 +917,22 @@ fn instruction_immediate<'a>(
 ) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
     move |input: &'a str| {
         let (input, (rd, (opcode, rs, value))) = separated_pair(
-            register,
+            register_operand,
             delimited(space0, tag("="), space0),
             tuple((
                 opcode(opcodes),
-                preceded(space1, register),
-                preceded(space1, i16),
+                preceded(space1, register_operand),
+                preceded(space1, immediate_operand),
             )),
         )(input)?;
-        Ok((
-            input,
-            (InstructionNode::Resolved(Instruction::Immediate(Immediate {
-                opcode,
-                rd,
-                rs,
-                value,
-            }))),
-        ))
+        let mut names = Names::default();
+        let instruction = Instruction::Immediate(Immediate {
+            opcode,
+            rd: names.take(Field::Rd, rd),
+            rs: names.take(Field::Rs, rs),
+            value: names.take(Field::Value, value),
+        });
+        Ok((input, names.node(InstructionNode::Resolved(instruction))))
     }
 }
 
@@ -247,23 +941,22 @@ fn instruction_register<'a>(
 ) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
     move |input: &'a str| {
         let (input, (rd, (opcode, rs1, rs2))) = separated_pair(
-            register,
+            register_operand,
             delimited(space0, tag("="), space0),
             tuple((
                 opcode(opcodes),
-                preceded(space1, register),
-                preceded(space1, register),
+                preceded(space1, register_operand),
+                preceded(space1, register_operand),
             )),
         )(input)?;
-        Ok((
-            input,
-            (InstructionNode::Resolved(Instruction::Register(Register {
-                opcode,
-                rd,
-                rs1,
-                rs2,
-            }))),
-        ))
+        let mut names = Names::default();
+        let instruction = Instruction::Register(Register {
+            opcode,
+            rd: names.take(Field::Rd, rd),
+            rs1: names.take(Field::Rs1, rs1),
+            rs2: names.take(Field::Rs2, rs2),
+        });
+        Ok((input, names.node(InstructionNode::Resolved(instruction))))
     }
 }
 
@@ -272,23 +965,22 @@ fn instruction_load<'a>(
 ) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
     move |input: &'a str| {
         let (input, (rd, (opcode, rs, offset))) = separated_pair(
-            register,
+            register_operand,
             delimited(space0, tag("="), space0),
             tuple((
                 opcode(opcodes),
-                preceded(space1, register),
-                preceded(space1, u16),
+                preceded(space1, register_operand),
+                preceded(space1, number_operand),
             )),
         )(input)?;
-        Ok((
-            input,
-            InstructionNode::Resolved(Instruction::Load(Load {
-                opcode,
-                rd,
-                rs,
-                offset,
-            })),
-        ))
+        let mut names = Names::default();
+        let instruction = Instruction::Load(Load {
+            opcode,
+            rd: names.take(Field::Rd, rd),
+            rs: names.take(Field::Rs, rs),
+            offset: names.take(Field::Value, offset),
+        });
+        Ok((input, names.node(InstructionNode::Resolved(instruction))))
     }
 }
 
@@ -299,21 +991,20 @@ fn instruction_store<'a>(
         let (input, ((opcode, rd, offset), rs)) = separated_pair(
             tuple((
                 opcode(opcodes),
-                preceded(space1, register),
-                preceded(space1, u16),
+                preceded(space1, register_operand),
+                preceded(space1, number_operand),
             )),
             delimited(space0, tag("="), space0),
-            register,
+            register_operand,
         )(input)?;
-        Ok((
-            input,
-            InstructionNode::Resolved(Instruction::Store(Store {
-                opcode,
-                rd,
-                rs,
-                offset,
-            })),
-        ))
+        let mut names = Names::default();
+        let instruction = Instruction::Store(Store {
+            opcode,
+            rd: names.take(Field::Rd, rd),
+            rs: names.take(Field::Rs, rs),
+            offset: names.take(Field::Value, offset),
+        });
+        Ok((input, names.node(InstructionNode::Resolved(instruction))))
     }
 }
 
@@ -323,13 +1014,21 @@ fn instruction_branch<'a>(
     move |input: &'a str| {
         let (input, (opcode, rs1, rs2, target)) = tuple((
             opcode(opcodes),
-            preceded(space1, register),
-            preceded(space1, register),
+            preceded(space1, register_operand),
+            preceded(space1, register_operand),
             preceded(space1, identifier),
         ))(input)?;
+        let mut names = Names::default();
+        let rs1 = names.take(Field::Rs1, rs1);
+        let rs2 = names.take(Field::Rs2, rs2);
         Ok((
             input,
-            InstructionNode::UnresolvedBranch(opcode, rs1, rs2, target.to_string()),
+            names.node(InstructionNode::UnresolvedBranch(
+                opcode,
+                rs1,
+                rs2,
+                target.to_string(),
+            )),
         ))
     }
 }
@@ -356,6 +1055,39 @@ fn instruction_call<'a>(
     }
 }
 
+fn instruction_ecall<'a>(
+    opcodes: &'a Opcodes<EcallOpcode>,
+) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
+    move |input: &'a str| {
+        let (input, (opcode, number)) =
+            tuple((opcode(opcodes), preceded(space1, number_operand)))(input)?;
+        let mut names = Names::default();
+        let instruction = Instruction::Ecall(Ecall {
+            opcode,
+            number: names.take(Field::Value, number),
+        });
+        Ok((input, names.node(InstructionNode::Resolved(instruction))))
+    }
+}
+
+fn instruction_random<'a>(
+    opcodes: &'a Opcodes<RandomOpcode>,
+) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
+    move |input: &'a str| {
+        let (input, (rd, opcode)) = separated_pair(
+            register_operand,
+            delimited(space0, tag("="), space0),
+            opcode(opcodes),
+        )(input)?;
+        let mut names = Names::default();
+        let instruction = Instruction::Random(Random {
+            opcode,
+            rd: names.take(Field::Rd, rd),
+        });
+        Ok((input, names.node(InstructionNode::Resolved(instruction))))
+    }
+}
+
 // fn instruction_switch<'a>(
 //     opcodes: &'a Opcodes<SwitchOpcode>,
 // ) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
@@ -402,6 +1134,8 @@ fn instruction<'a>(
 ) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
     move |input: &'a str| {
         alt((
+            map(definition, InstructionNode::Definition),
+            instruction_expansion,
             instruction_immediate(&opcodes.immediate_opcodes),
             instruction_register(&opcodes.register_opcodes),
             instruction_load(&opcodes.load_opcodes),
@@ -409,11 +1143,40 @@ fn instruction<'a>(
             instruction_branch(&opcodes.branch_opcodes),
             instruction_target(&opcodes.branch_target_opcodes),
             instruction_call(&opcodes.call_id_opcodes),
+            instruction_ecall(&opcodes.ecall_opcodes),
+            instruction_random(&opcodes.random_opcodes),
             // instruction_switch(&opcodes.switch_opcodes),
         ))(input)
     }
 }
 
+fn argument(input: &str) -> ParseResult<'_, Argument> {
+    alt((
+        map(whole_register, Argument::Register),
+        map(literal, Argument::Literal),
+        map(identifier, |name| Argument::Name(name.to_string())),
+    ))(input)
+}
+
+// a list in parentheses, separated by commas
+fn parenthesized<'a, T>(
+    item: impl FnMut(&'a str) -> ParseResult<'a, T>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<T>> {
+    delimited(
+        pair(char('('), space0),
+        separated_list0(tuple((space0, char(','), space0)), item),
+        pair(space0, char(')')),
+    )
+}
+
+// name(arguments), which is replaced by the body of the macro
+fn instruction_expansion(input: &str) -> ParseResult<'_, InstructionNode> {
+    map(
+        pair(identifier, parenthesized(argument)),
+        |(name, arguments)| InstructionNode::Expansion(name.to_string(), arguments),
+    )(input)
+}
+
 fn instruction_with_optional_comment<'a>(
     opcodes: &'a AllOpcodes,
 ) -> impl Fn(&'a str) -> ParseResult<'a, InstructionNode> {
@@ -451,7 +1214,7 @@ fn func_header<'a>(input: &'a str) -> ParseResult<&'a str> {
 fn repeat_header<'a>(input: &'a str) -> ParseResult<(&'a str, u8)> {
     preceded(
         pair(tag("repeat"), space1),
-        separated_pair(identifier, space1, u8),
+        separated_pair(identifier, space1, number),
     )(input)
 }
 
@@ -462,20 +1225,51 @@ fn func_body<'a>(
         delimited(
             tuple((space0, tag("{"), space0)),
             instructions(opcodes),
-            tuple((space0, tag("}"), space0)),
+            // a body can be empty, or hold only comments
+            tuple((whitespace_and_comments, tag("}"), space0)),
         )(input)
     }
 }
 
+// macro name(parameters) { body }. a body can't define constants or
+// aliases, as expanding it twice would define them twice
+fn macro_definition<'a>(opcodes: &'a AllOpcodes) -> impl Fn(&'a str) -> ParseResult<'a, MacroNode> {
+    move |input: &'a str| {
+        let (input, ((name, parameters), instruction_nodes)) = pair(
+            preceded(
+                pair(tag("macro"), space1),
+                pair(identifier, preceded(space0, parenthesized(identifier))),
+            ),
+            verify(func_body(opcodes), |nodes: &Vec<InstructionNode>| {
+                !nodes
+                    .iter()
+                    .any(|node| matches!(node, InstructionNode::Definition(_)))
+            }),
+        )(input)?;
+        Ok((
+            input,
+            MacroNode {
+                name: name.to_string(),
+                parameters: parameters.into_iter().map(str::to_string).collect(),
+                instruction_nodes,
+                file: 0,
+            },
+        ))
+    }
+}
+
 fn func<'a>(opcodes: &'a AllOpcodes) -> impl Fn(&'a str) -> ParseResult<'a, FunctionNode> {
     move |input: &'a str| {
-        let (input, (name, instructions)) = pair(func_header, func_body(opcodes))(input)?;
+        let (input, (name, nodes)) = pair(func_header, func_body(opcodes))(input)?;
+        let (definitions, instruction_nodes) = split_definitions(nodes);
         Ok((
             input,
             FunctionNode {
                 name: name.to_string(),
-                instruction_nodes: instructions,
+                definitions,
+                instruction_nodes,
                 repeat: 0,
+                file: 0,
             },
         ))
     }
@@ -483,68 +1277,185 @@ fn func<'a>(opcodes: &'a AllOpcodes) -> impl Fn(&'a str) -> ParseResult<'a, Func
 
 fn repeat<'a>(opcodes: &'a AllOpcodes) -> impl Fn(&'a str) -> ParseResult<'a, FunctionNode> {
     move |input: &'a str| {
-        let (input, ((name, repeat), instructions)) =
-            pair(repeat_header, func_body(opcodes))(input)?;
+        let (input, ((name, repeat), nodes)) = pair(repeat_header, func_body(opcodes))(input)?;
+        let (definitions, instruction_nodes) = split_definitions(nodes);
         Ok((
             input,
             FunctionNode {
                 name: name.to_string(),
-                instruction_nodes: instructions,
+                definitions,
+                instruction_nodes,
                 repeat,
+                file: 0,
             },
         ))
     }
 }
 
+// include "path", which adds the file at path to the program. the path is
+// relative to the directory of the file that includes it
+fn include(input: &str) -> ParseResult<'_, String> {
+    map(
+        preceded(
+            pair(tag("include"), space1),
+            terminated(delimited(char('"'), is_not("\"\n"), char('"')), space0),
+        ),
+        str::to_string,
+    )(input)
+}
+
+// the path of the file that include names in the file at path, which is in
+// the directory of that file unless it's absolute. the program itself is at
+// the empty path, so that its includes are relative to the root
+fn include_path(path: &str, include: &str) -> String {
+    if include.starts_with('/') {
+        return include.to_string();
+    }
+    let mut components: Vec<&str> = path.split('/').collect();
+    components.pop();
+    for component in include.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
 fn program<'a>(opcodes: &'a AllOpcodes) -> impl Fn(&'a str) -> ParseResult<'a, ProgramNode> {
     move |input: &'a str| {
-        let (input, function_nodes) = terminated(
+        let (input, items) = terminated(
             many0(delimited(
                 whitespace_and_comments,
-                alt((func(opcodes), repeat(opcodes))),
+                alt((
+                    map(include, Item::Include),
+                    map(func(opcodes), Item::Function),
+                    map(repeat(opcodes), Item::Function),
+                    map(macro_definition(opcodes), Item::Macro),
+                    map(terminated(definition, space0), Item::Definition),
+                )),
                 whitespace_and_comments,
             )),
             eof,
         )(input)?;
-        Ok((input, ProgramNode { function_nodes }))
+        let mut includes = Vec::new();
+        let mut definitions = Vec::new();
+        let mut macros = Vec::new();
+        let mut function_nodes = Vec::new();
+        for item in items {
+            match item {
+                Item::Include(path) => includes.push(path),
+                Item::Function(function_node) => function_nodes.push(function_node),
+                Item::Definition(definition) => definitions.push(definition),
+                Item::Macro(macro_node) => macros.push(macro_node),
+            }
+        }
+        Ok((
+            input,
+            ProgramNode {
+                includes,
+                definitions: vec![definitions],
+                macros,
+                function_nodes,
+            },
+        ))
     }
 }
 
 /// Parse a vector of instructions from a string
 pub fn parse(input: &str) -> Result<Vec<Instruction>, String> {
     let opcodes = AllOpcodes::new();
-    let (_, instruction_nodes) =
-        terminated(instructions(&opcodes), eof)(input).map_err(|e| e.to_string())?;
-    let instructions = instruction_nodes
+    let (_, nodes) = terminated(instructions(&opcodes), eof)(input).map_err(|e| e.to_string())?;
+    let (definitions, instruction_nodes) = split_definitions(nodes);
+    let scope = Scope::default()
+        .inner(&definitions)
+        .map_err(|errors| format!("{:?}", errors))?;
+    instruction_nodes
         .iter()
-        .map(|node| match node {
-            InstructionNode::Resolved(instruction) => instruction.clone(),
-            _ => {
-                panic!("Unresolved node");
-            }
+        .map(|node| {
+            // calls, branches and macros need a program around the instructions
+            let error = match scope.resolve(node) {
+                Ok(InstructionNode::Resolved(instruction)) => return Ok(instruction),
+                Ok(InstructionNode::UnresolvedCall(name)) => ResolutionError::Call(name),
+                Ok(InstructionNode::UnresolvedBranch(_, _, _, name))
+                | Ok(InstructionNode::UnresolvedTarget(name)) => ResolutionError::Branch(name),
+                Ok(InstructionNode::Expansion(name, _)) => ResolutionError::Macro(name),
+                Ok(node) => unreachable!("{:?} is resolved or split off", node),
+                Err(error) => error,
+            };
+            Err(format!("{:?}", error))
         })
-        .collect();
-    Ok(instructions)
+        .collect()
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ParseProgramError {
     ParseError(String),
     ResolutionErrors(Vec<ResolutionError>),
+    // a file that's included but can't be read
+    IncludeError(String),
 }
 
 pub fn parse_program(input: &str) -> Result<Program, ParseProgramError> {
+    parse_program_with_labels(input).map(|(program, _)| program)
+}
+
+/// Parse a program whose `include` directives name files that resolver reads.
+/// The program is the file at path, where resolver would read it. The path
+/// of a file included by another file is relative to the directory of that
+/// file, and a file that's included already, the program included, isn't
+/// included again. The functions of included files come after those of the
+/// program, so main is still its first. Functions and macros are shared by
+/// all files, but constants and aliases are only seen in their own file, so
+/// the body of a macro uses those of the file it's in wherever it's expanded.
+pub fn parse_program_with_resolver(
+    input: &str,
+    path: &str,
+    resolver: &dyn FileResolver,
+) -> Result<Program, ParseProgramError> {
+    parse_program_with_labels_and_resolver(input, path, resolver).map(|(program, _)| program)
+}
+
+/// Parse a program, along with the target labels of each function. The label
+/// of target identifier `i` in function `id` is `labels[id][i]`.
+pub fn parse_program_with_labels(
+    input: &str,
+) -> Result<(Program, Vec<Vec<String>>), ParseProgramError> {
+    parse_program_with_labels_and_resolver(input, "", &NoFiles)
+}
+
+pub fn parse_program_with_labels_and_resolver(
+    input: &str,
+    path: &str,
+    resolver: &dyn FileResolver,
+) -> Result<(Program, Vec<Vec<String>>), ParseProgramError> {
     let opcodes = AllOpcodes::new();
     let (_, program_node) = terminated(program(&opcodes), eof)(input)
         .map_err(|e| ParseProgramError::ParseError(e.to_string()))?;
-    program_node
+    let mut included = FxHashSet::default();
+    included.insert(path.to_string());
+    let program_node = program_node.include(path, &opcodes, resolver, &mut included)?;
+    let program_node = program_node
+        .expand()
+        .map_err(ParseProgramError::ResolutionErrors)?;
+    let labels = program_node
+        .function_nodes
+        .iter()
+        .map(FunctionNode::labels)
+        .collect();
+    let program = program_node
         .try_into()
-        .map_err(ParseProgramError::ResolutionErrors)
+        .map_err(ParseProgramError::ResolutionErrors)?;
+    Ok((program, labels))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lang::Processor;
     use nom_test_helpers::assert_error;
     use InstructionNode::Resolved;
 
@@ -555,6 +1466,39 @@ mod tests {
         assert_eq!(register("r10 "), Ok((" ", 10)));
     }
 
+    #[test]
+    fn test_literal() {
+        assert_eq!(literal("42"), Ok(("", (42, false))));
+        assert_eq!(literal("-42"), Ok(("", (-42, false))));
+        assert_eq!(literal("0x2a"), Ok(("", (42, true))));
+        assert_eq!(literal("0X2a"), Ok(("X2a", (0, false))));
+        assert_eq!(literal("-0x2A"), Ok(("", (-42, true))));
+        assert_eq!(literal("0b101010"), Ok(("", (42, true))));
+        assert_eq!(literal("'*'"), Ok(("", (42, false))));
+        assert_eq!(literal("'\\''"), Ok(("", (39, false))));
+        assert_eq!(literal("'\\n'"), Ok(("", (10, false))));
+        assert_error!(literal("''"));
+        assert_error!(literal("'ab'"));
+    }
+
+    #[test]
+    fn test_number() {
+        assert_eq!(number::<u16>("0xffff"), Ok(("", 65535)));
+        assert_eq!(number::<u8>("'A'"), Ok(("", 65)));
+        assert_error!(number::<u16>("0x10000"));
+        assert_error!(number::<u16>("-1"));
+        assert_error!(number::<u8>("99999999999999999999"));
+    }
+
+    #[test]
+    fn test_immediate() {
+        assert_eq!(immediate("-0x8000"), Ok(("", i16::MIN)));
+        assert_eq!(immediate("0xffff"), Ok(("", -1)));
+        assert_eq!(immediate("0b1000000000000000"), Ok(("", i16::MIN)));
+        assert_error!(immediate("65535"));
+        assert_error!(immediate("-0x8001"));
+    }
+
     #[test]
     fn test_opcodes() {
         let opcodes = Opcodes::new();
@@ -616,7 +1560,31 @@ mod tests {
                     opcode: ImmediateOpcode::Addi,
                     rd: 1,
                     rs: 2,
-                    value: -5
+                    value: -5
+                }))
+            ))
+        );
+        assert_eq!(
+            instruction_immediate(&opcodes)("r1 = andi r2 0xff00"),
+            Ok((
+                "",
+                Resolved(Instruction::Immediate(Immediate {
+                    opcode: ImmediateOpcode::Andi,
+                    rd: 1,
+                    rs: 2,
+                    value: -256
+                }))
+            ))
+        );
+        assert_eq!(
+            instruction_immediate(&opcodes)("r1 = xori r2 'a'"),
+            Ok((
+                "",
+                Resolved(Instruction::Immediate(Immediate {
+                    opcode: ImmediateOpcode::Xori,
+                    rd: 1,
+                    rs: 2,
+                    value: 97
                 }))
             ))
         );
@@ -654,6 +1622,18 @@ mod tests {
                 }))
             ))
         );
+        assert_eq!(
+            instruction_store(&opcodes)("sh r2 0x100 = r1"),
+            Ok((
+                "",
+                Resolved(Instruction::Store(Store {
+                    opcode: StoreOpcode::Sh,
+                    rd: 2,
+                    rs: 1,
+                    offset: 256
+                }))
+            ))
+        );
     }
 
     #[test]
@@ -686,6 +1666,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_instruction_ecall() {
+        let opcodes = Opcodes::new();
+        assert_eq!(
+            instruction_ecall(&opcodes)("ecall 3"),
+            Ok((
+                "",
+                InstructionNode::Resolved(Instruction::Ecall(Ecall {
+                    opcode: EcallOpcode::Ecall,
+                    number: 3
+                }))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_instruction_random() {
+        let opcodes = Opcodes::new();
+        assert_eq!(
+            instruction_random(&opcodes)("r3 = rand"),
+            Ok((
+                "",
+                InstructionNode::Resolved(Instruction::Random(Random {
+                    opcode: RandomOpcode::Rand,
+                    rd: 3
+                }))
+            ))
+        );
+    }
+
     #[test]
     fn test_instruction_broken() {
         let opcodes = AllOpcodes::new();
@@ -836,7 +1846,10 @@ mod tests {
     #[test]
     fn test_repeat_header() {
         let r = repeat_header("repeat foo 3");
-        assert_eq!(r, Ok(("", ("foo", 3))))
+        assert_eq!(r, Ok(("", ("foo", 3))));
+        let r = repeat_header("repeat foo 0x10");
+        assert_eq!(r, Ok(("", ("foo", 16))));
+        assert_error!(repeat_header("repeat foo 256"));
     }
 
     #[test]
@@ -898,6 +1911,14 @@ mod tests {
         assert_eq!(instructions, expected_instructions);
     }
 
+    #[test]
+    fn test_parse_unresolved() {
+        assert_eq!(parse("foo(1)"), Err("Macro(\"foo\")".to_string()));
+        assert_eq!(parse("call foo"), Err("Call(\"foo\")".to_string()));
+        assert_eq!(parse("beq r1 r2 foo"), Err("Branch(\"foo\")".to_string()));
+        assert_eq!(parse("r1 = addi r1 x"), Err("Constant(\"x\")".to_string()));
+    }
+
     #[test]
     fn test_parse_trailing_space() {
         let instructions = parse(
@@ -953,6 +1974,7 @@ mod tests {
                 "",
                 FunctionNode {
                     name: "foo".to_string(),
+                    definitions: vec![],
                     instruction_nodes: vec![
                         Resolved(Instruction::Immediate(Immediate {
                             opcode: ImmediateOpcode::Addi,
@@ -967,7 +1989,8 @@ mod tests {
                             rs2: 3
                         }))
                     ],
-                    repeat: 0
+                    repeat: 0,
+                    file: 0
                 }
             ))
         )
@@ -983,6 +2006,7 @@ mod tests {
                 "",
                 FunctionNode {
                     name: "foo".to_string(),
+                    definitions: vec![],
                     instruction_nodes: vec![
                         Resolved(Instruction::Immediate(Immediate {
                             opcode: ImmediateOpcode::Addi,
@@ -997,7 +2021,8 @@ mod tests {
                             rs2: 3
                         }))
                     ],
-                    repeat: 3
+                    repeat: 3,
+                    file: 0
                 }
             ))
         )
@@ -1014,9 +2039,13 @@ mod tests {
             Ok((
                 "",
                 ProgramNode {
+                    includes: vec![],
+                    definitions: vec![vec![]],
+                    macros: vec![],
                     function_nodes: vec![
                         FunctionNode {
                             name: "foo".to_string(),
+                            definitions: vec![],
                             instruction_nodes: vec![
                                 InstructionNode::UnresolvedCall("bar".to_string()),
                                 Resolved(Instruction::Register(Register {
@@ -1026,17 +2055,20 @@ mod tests {
                                     rs2: 3
                                 }))
                             ],
-                            repeat: 0
+                            repeat: 0,
+                            file: 0
                         },
                         FunctionNode {
                             name: "bar".to_string(),
+                            definitions: vec![],
                             instruction_nodes: vec![Resolved(Instruction::Register(Register {
                                 opcode: RegisterOpcode::Add,
                                 rd: 1,
                                 rs1: 2,
                                 rs2: 5
                             }))],
-                            repeat: 0
+                            repeat: 0,
+                            file: 0
                         }
                     ]
                 }
@@ -1162,6 +2194,18 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_program_with_empty_bodies() {
+        let r = parse_program("func foo {\n}\n func bar {\n # nothing yet\n }");
+        assert_eq!(
+            r,
+            Ok(Program::from_functions(vec![
+                Function::new("foo".to_string(), &[], 0),
+                Function::new("bar".to_string(), &[], 0)
+            ]))
+        )
+    }
+
     #[test]
     fn test_parse_program_with_branch() {
         let r = parse_program(
@@ -1268,4 +2312,574 @@ mod tests {
             ]))
         )
     }
+
+    #[test]
+    fn test_definition() {
+        assert_eq!(
+            definition("const size = 0x10"),
+            Ok(("", Definition::Constant("size".to_string(), (16, true))))
+        );
+        assert_eq!(
+            definition("alias sp = r31"),
+            Ok(("", Definition::Alias("sp".to_string(), 31)))
+        );
+        assert_error!(definition("alias sp = 31"));
+    }
+
+    #[test]
+    fn test_instruction_immediate_with_names() {
+        let opcodes = Opcodes::new();
+        assert_eq!(
+            instruction_immediate(&opcodes)("sp = addi r2 size"),
+            Ok((
+                "",
+                InstructionNode::Named(
+                    Box::new(Resolved(Instruction::Immediate(Immediate {
+                        opcode: ImmediateOpcode::Addi,
+                        rd: 0,
+                        rs: 2,
+                        value: 0
+                    }))),
+                    vec![
+                        (Field::Rd, "sp".to_string()),
+                        (Field::Value, "size".to_string())
+                    ]
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_with_definitions() {
+        let r = parse("alias sp = r31\nconst four = 4\nr1 = lh sp four\nsp = addi sp -1");
+        assert_eq!(
+            r,
+            Ok(vec![
+                Instruction::Load(Load {
+                    opcode: LoadOpcode::Lh,
+                    rd: 1,
+                    rs: 31,
+                    offset: 4
+                }),
+                Instruction::Immediate(Immediate {
+                    opcode: ImmediateOpcode::Addi,
+                    rd: 31,
+                    rs: 31,
+                    value: -1
+                })
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_definitions() {
+        let r = parse_program(
+            "
+            alias sp = r31
+            const size = 2
+
+            func foo {
+                const size = 0xffff
+                r1 = addi sp size
+                call bar
+            }
+
+            func bar {
+                alias top = r1
+                beq top sp end
+                sh sp size = top
+                target end
+            }
+            ",
+        );
+        assert_eq!(
+            r,
+            parse_program(
+                "
+            func foo {
+                r1 = addi r31 -1
+                call bar
+            }
+
+            func bar {
+                beq r1 r31 end
+                sh r31 2 = r1
+                target end
+            }
+            "
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_program_definition_errors() {
+        let r = parse_program(
+            "
+            const big = 70000
+            const big = 1
+
+            func foo {
+                alias top = r1
+                r1 = lb r0 big
+                top = addi r0 big
+            }
+
+            func bar {
+                r2 = add top r1
+            }
+
+            func baz {
+                const small = 1
+                alias small = r2
+            }
+            ",
+        );
+        assert_eq!(
+            r,
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Redefinition("big".to_string())
+            ]))
+        );
+
+        let r = parse_program(
+            "
+            const big = 70000
+
+            func foo {
+                alias top = r1
+                r1 = lb r0 big
+                top = addi r0 missing
+            }
+
+            func bar {
+                r2 = add top r1
+            }
+
+            func baz {
+                const small = 1
+                alias small = r2
+            }
+            ",
+        );
+        assert_eq!(
+            r,
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Range("big".to_string()),
+                ResolutionError::Constant("missing".to_string()),
+                ResolutionError::Alias("top".to_string()),
+                ResolutionError::Redefinition("small".to_string())
+            ]))
+        );
+
+        assert_eq!(
+            parse_program("alias r5 = r1\nfunc foo {\n}"),
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Register("r5".to_string())
+            ]))
+        );
+        let r = parse_program(
+            "
+            func foo {
+                const r3 = 1
+                alias r10x = r2
+                r10x = addi r0 1
+            }
+            ",
+        );
+        assert_eq!(
+            r,
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Register("r3".to_string())
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_macro_definition() {
+        let opcodes = AllOpcodes::new();
+        assert_eq!(
+            macro_definition(&opcodes)("macro skip(a, to) {\nbeq a r0 to\nf(1, r2, x)\n}"),
+            Ok((
+                "",
+                MacroNode {
+                    name: "skip".to_string(),
+                    parameters: vec!["a".to_string(), "to".to_string()],
+                    instruction_nodes: vec![
+                        InstructionNode::Named(
+                            Box::new(InstructionNode::UnresolvedBranch(
+                                BranchOpcode::Beq,
+                                0,
+                                0,
+                                "to".to_string()
+                            )),
+                            vec![(Field::Rs1, "a".to_string())]
+                        ),
+                        InstructionNode::Expansion(
+                            "f".to_string(),
+                            vec![
+                                Argument::Literal((1, false)),
+                                Argument::Register(2),
+                                Argument::Name("x".to_string())
+                            ]
+                        )
+                    ],
+                    file: 0
+                }
+            ))
+        );
+        assert!(macro_definition(&opcodes)("macro f() {\nconst a = 1\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_program_with_macros() {
+        let r = parse_program(
+            "
+            alias top = r3
+
+            macro increment(register, amount) {
+                register = addi register amount
+            }
+
+            macro skip_if(a, b, function) {
+                beq a b skip
+                call function
+                increment(top, 1)
+                target skip
+            }
+
+            func foo {
+                skip_if(r1, r2, bar)
+                skip_if(r0, top, bar)
+                increment(r4, 0x10)
+            }
+
+            func bar {
+            }
+            ",
+        );
+        assert_eq!(
+            r,
+            parse_program(
+                "
+            func foo {
+                beq r1 r2 a
+                call bar
+                r3 = addi r3 1
+                target a
+                beq r0 r3 b
+                call bar
+                r3 = addi r3 1
+                target b
+                r4 = addi r4 16
+            }
+
+            func bar {
+            }
+            "
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_program_macro_errors() {
+        let errors = |input| match parse_program(input) {
+            Err(ParseProgramError::ResolutionErrors(errors)) => errors,
+            r => panic!("expected resolution errors, got {:?}", r),
+        };
+        assert_eq!(
+            errors(
+                "
+            macro f(a) {
+                r1 = addi r1 a
+            }
+
+            macro g() {
+                h()
+            }
+
+            macro h() {
+                g()
+            }
+
+            func foo {
+                f(r2)
+                f(1, 2)
+                f(70000)
+                missing()
+                g()
+            }
+            "
+            ),
+            vec![
+                ResolutionError::Argument("a".to_string()),
+                ResolutionError::Arguments("f".to_string()),
+                ResolutionError::Range("a".to_string()),
+                ResolutionError::Macro("missing".to_string()),
+                ResolutionError::Recursion("g".to_string())
+            ]
+        );
+        assert_eq!(
+            errors("macro f() {\n}\nmacro f() {\n}\nfunc foo {\n}"),
+            vec![ResolutionError::Redefinition("f".to_string())]
+        );
+        assert_eq!(
+            errors("macro f(r1, to) {\n    r2 = addi r1 1\n}\nfunc foo {\n}"),
+            vec![ResolutionError::Register("r1".to_string())]
+        );
+        // each expansion adds a target of its own
+        let incrementing = |times| {
+            format!(
+                "macro inc(r) {{\n    beq r0 r0 done\n    target done\n    r = addi r 1\n}}\nfunc foo {{\n{}}}",
+                "    inc(r1)\n".repeat(times)
+            )
+        };
+        assert_eq!(
+            errors(&incrementing(257)),
+            vec![ResolutionError::Targets("foo".to_string())]
+        );
+        let program = parse_program(&incrementing(256)).unwrap();
+        let mut processor = Processor::new();
+        program.interpret_with_processor(&mut [0u8; 64], &mut processor);
+        assert_eq!(processor.registers()[1], 256);
+    }
+
+    fn files(files: &[(&str, &str)]) -> FxHashMap<String, String> {
+        files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), contents.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_include() {
+        assert_eq!(
+            include("include \"lib/stack.ale\""),
+            Ok(("", "lib/stack.ale".to_string()))
+        );
+        assert_error!(include("include lib/stack.ale"));
+        assert_error!(include("include \"lib\nstack.ale\""));
+    }
+
+    #[test]
+    fn test_include_path() {
+        assert_eq!(include_path("", "lib/stack.ale"), "lib/stack.ale");
+        assert_eq!(include_path("lib/stack.ale", "util.ale"), "lib/util.ale");
+        assert_eq!(include_path("lib/stack.ale", "./a/b.ale"), "lib/a/b.ale");
+        assert_eq!(include_path("lib/stack.ale", "../main.ale"), "main.ale");
+        assert_eq!(include_path("stack.ale", "../main.ale"), "../main.ale");
+        assert_eq!(include_path("lib/stack.ale", "/main.ale"), "/main.ale");
+    }
+
+    #[test]
+    fn test_parse_program_with_includes() {
+        let resolver = files(&[
+            (
+                "lib/stack.ale",
+                "
+            include \"util.ale\"
+            alias sp = r31
+
+            func push {
+                sh sp 0 = r1
+                sp = addi sp 2
+            }
+            ",
+            ),
+            (
+                "lib/util.ale",
+                "
+            include \"stack.ale\"
+            const one = 1
+
+            func inc {
+                r1 = addi r1 one
+            }
+            ",
+            ),
+        ]);
+        let r = parse_program_with_resolver(
+            "
+            include \"lib/stack.ale\"
+            include \"lib/util.ale\"
+            alias sp = r31
+            const one = 2
+
+            func main {
+                r1 = addi r0 one
+                call push
+                call inc
+                sp = addi sp -2
+            }
+            ",
+            "main.ale",
+            &resolver,
+        );
+        assert_eq!(
+            r,
+            parse_program(
+                "
+            func main {
+                r1 = addi r0 2
+                call push
+                call inc
+                r31 = addi r31 -2
+            }
+
+            func push {
+                sh r31 0 = r1
+                r31 = addi r31 2
+            }
+
+            func inc {
+                r1 = addi r1 1
+            }
+            "
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_include_cycle() {
+        let main = "include \"lib/lib.ale\"\nfunc main {\n    call inc\n}";
+        let resolver = files(&[
+            ("src/main.ale", main),
+            (
+                "src/lib/lib.ale",
+                "include \"../main.ale\"\nfunc inc {\n    r1 = addi r1 1\n}",
+            ),
+        ]);
+        assert_eq!(
+            parse_program_with_resolver(main, "src/main.ale", &resolver),
+            parse_program("func main {\n    call inc\n}\nfunc inc {\n    r1 = addi r1 1\n}")
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_included_macros() {
+        let resolver = files(&[(
+            "lib.ale",
+            "
+            const ONE = 1
+            alias sp = r31
+
+            macro inc(r) {
+                r = addi r ONE
+            }
+
+            macro push(r) {
+                sh sp 0 = r
+                sp = addi sp 2
+            }
+
+            macro add(r, n) {
+                r = addi r n
+            }
+
+            macro bump(r) {
+                add(r, ONE)
+            }
+            ",
+        )]);
+        let expected = parse_program(
+            "
+            func main {
+                r1 = addi r1 1
+                sh r31 0 = r2
+                r31 = addi r31 2
+                r3 = addi r3 1
+            }
+            ",
+        );
+        assert_eq!(
+            parse_program_with_resolver(
+                "
+            include \"lib.ale\"
+
+            func main {
+                inc(r1)
+                push(r2)
+                bump(r3)
+            }
+            ",
+                "main.ale",
+                &resolver,
+            ),
+            expected
+        );
+        // the names of main don't change what those of the macros stand for,
+        // while the names main passes in are its own
+        assert_eq!(
+            parse_program_with_resolver(
+                "
+            include \"lib.ale\"
+            const ONE = 7
+            alias sp = r30
+            alias value = r2
+
+            func main {
+                inc(r1)
+                push(value)
+                bump(r3)
+            }
+            ",
+                "main.ale",
+                &resolver,
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_program_include_errors() {
+        let resolver = files(&[
+            ("a.ale", "const size = 1\nmacro f() {\n}\nfunc foo {\n}"),
+            ("b.ale", "const size = 2\nmacro f() {\n}\nfunc foo {\n}"),
+            ("broken.ale", "func foo {"),
+        ]);
+        assert_eq!(
+            parse_program_with_resolver(
+                "include \"a.ale\"\nfunc main {\n}\nfunc foo {\n}",
+                "main.ale",
+                &resolver
+            ),
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Redefinition("foo".to_string())
+            ]))
+        );
+        assert_eq!(
+            parse_program_with_resolver(
+                "include \"a.ale\"\ninclude \"b.ale\"\nfunc main {\n}",
+                "main.ale",
+                &resolver
+            ),
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Redefinition("f".to_string())
+            ]))
+        );
+        assert_eq!(
+            parse_program_with_resolver("include \"c.ale\"\nfunc main {\n}", "main.ale", &resolver),
+            Err(ParseProgramError::IncludeError(
+                "c.ale: no such file".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse_program_with_resolver("include \"broken.ale\"\nfunc main {\n}", "main.ale", &resolver),
+            Err(ParseProgramError::ParseError(error)) if error.starts_with("broken.ale: ")
+        ));
+        // an included file doesn't see the definitions of the one including it
+        assert_eq!(
+            parse_program_with_resolver(
+                "include \"a.ale\"\nconst top = 1\nfunc main {\n}\nfunc bar {\n}",
+                "main.ale",
+                &files(&[("a.ale", "func foo {\n    r1 = addi r0 top\n}")])
+            ),
+            Err(ParseProgramError::ResolutionErrors(vec![
+                ResolutionError::Constant("top".to_string())
+            ]))
+        );
+        assert!(matches!(
+            parse_program("include \"a.ale\"\nfunc main {\n}"),
+            Err(ParseProgramError::IncludeError(_))
+        ));
+    }
 }
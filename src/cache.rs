@@ -28,7 +28,7 @@ impl<'ctx> FunctionValueCache<'ctx> {
         call_id: CallId,
         program: &'ctx Program,
         codegen: &'ctx CodeGen,
-        memory_size: u16,
+        memory_size: u32,
     ) -> FxHashMap<CallId, FunctionValue<'ctx>> {
         FunctionValueCache::convert_dependencies(&self.compile_internal(
             call_id,
@@ -43,7 +43,7 @@ impl<'ctx> FunctionValueCache<'ctx> {
         call_id: CallId,
         program: &'ctx Program,
         codegen: &'ctx CodeGen,
-        memory_size: u16,
+        memory_size: u32,
     ) -> FxHashMap<CallId, (FunctionValueId, FunctionValue<'ctx>)> {
         // given everything this function calls, compile dependencies
         let function = &program.get_function(call_id);
@@ -0,0 +1,257 @@
+use crate::disassembler::disassemble;
+use crate::function::Function;
+use crate::lang::{Branch, CallId, CallIdOpcode, Instruction};
+use crate::program::Program;
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+/// The instructions `start..end` of a function, which always execute in
+/// sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// A branch to its target.
+    Branch,
+    /// Falling through to the next block, also when a branch isn't taken.
+    FallThrough,
+    /// From the last block back to the first, for a function that repeats.
+    Repeat,
+}
+
+/// An edge between the blocks at indexes `from` and `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// The control-flow graph of a single function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+impl FunctionGraph {
+    pub fn new(function: &Function) -> FunctionGraph {
+        let instructions = function.get_instructions();
+        // a block starts at a target and after a branch
+        let mut leaders = vec![false; instructions.len()];
+        for (index, instruction) in instructions.iter().enumerate() {
+            match instruction {
+                Instruction::BranchTarget(_) => leaders[index] = true,
+                Instruction::Branch(_) if index + 1 < instructions.len() => {
+                    leaders[index + 1] = true
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = leaders.first_mut() {
+            *first = true;
+        }
+        let starts: Vec<usize> = (0..instructions.len())
+            .filter(|index| leaders[*index])
+            .collect();
+        let blocks: Vec<BasicBlock> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| BasicBlock {
+                start: *start,
+                end: starts.get(i + 1).copied().unwrap_or(instructions.len()),
+            })
+            .collect();
+
+        let targets = Function::targets(instructions);
+        let block_of = |pc: usize| blocks.iter().position(|block| block.end > pc);
+        let mut edges = Vec::new();
+        for (index, block) in blocks.iter().enumerate() {
+            if let Instruction::Branch(Branch { target, .. }) = &instructions[block.end - 1] {
+                if let Some(to) = targets.get(target).and_then(|pc| block_of(*pc)) {
+                    edges.push(Edge {
+                        from: index,
+                        to,
+                        kind: EdgeKind::Branch,
+                    });
+                }
+            }
+            if index + 1 < blocks.len() {
+                edges.push(Edge {
+                    from: index,
+                    to: index + 1,
+                    kind: EdgeKind::FallThrough,
+                });
+            }
+        }
+        if function.get_repeat() > 1 && !blocks.is_empty() {
+            edges.push(Edge {
+                from: blocks.len() - 1,
+                to: 0,
+                kind: EdgeKind::Repeat,
+            });
+        }
+        FunctionGraph { blocks, edges }
+    }
+
+    /// The index of the block the instruction at pc is in.
+    pub fn block_of(&self, pc: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.start <= pc && pc < block.end)
+    }
+}
+
+/// How often a function calls another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallEdge {
+    pub caller: u16,
+    pub callee: u16,
+    /// The amount of times the callee is called by one call of the caller,
+    /// including the repeats of the caller.
+    pub calls: u64,
+    /// The amount of times the body of the callee runs for one call of the
+    /// caller, which is `calls` times the repeat of the callee.
+    pub multiplicity: u64,
+}
+
+/// The control-flow graphs of the functions of a program, and the calls
+/// between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub functions: Vec<FunctionGraph>,
+    pub calls: Vec<CallEdge>,
+}
+
+impl Cfg {
+    pub fn new(program: &Program) -> Cfg {
+        let functions = program.functions();
+        let mut calls = Vec::new();
+        for (caller, function) in functions.iter().enumerate() {
+            let mut call_sites: FxHashMap<u16, u64> = FxHashMap::default();
+            for callee in function.get_call_ids() {
+                if (callee as usize) < functions.len() {
+                    *call_sites.entry(callee).or_default() += 1;
+                }
+            }
+            let mut callees: Vec<_> = call_sites.into_iter().collect();
+            callees.sort_unstable();
+            for (callee, sites) in callees {
+                let calls_per_call = sites * function.get_repeat() as u64;
+                calls.push(CallEdge {
+                    caller: caller as u16,
+                    callee,
+                    calls: calls_per_call,
+                    multiplicity: calls_per_call * functions[callee as usize].get_repeat() as u64,
+                });
+            }
+        }
+        Cfg {
+            functions: functions.iter().map(FunctionGraph::new).collect(),
+            calls,
+        }
+    }
+
+    /// Render the control-flow graphs as DOT, with a cluster per function
+    /// and the calls as dashed edges to the first block of the callee.
+    pub fn to_dot(&self, program: &Program) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph program {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=monospace];").unwrap();
+        for (id, graph) in self.functions.iter().enumerate() {
+            let function = &program.functions()[id];
+            let instructions = function.get_instructions();
+            writeln!(dot, "    subgraph cluster_{} {{", id).unwrap();
+            writeln!(dot, "        label=\"{}\";", function_label(function)).unwrap();
+            for (index, block) in graph.blocks.iter().enumerate() {
+                let lines: String = (block.start..block.end)
+                    .map(|pc| {
+                        let instruction = std::slice::from_ref(&instructions[pc]);
+                        format!("{}: {}\\l", pc, disassemble(instruction))
+                    })
+                    .collect();
+                writeln!(dot, "        f{}_b{} [label=\"{}\"];", id, index, lines).unwrap();
+            }
+            for edge in graph.edges.iter() {
+                let attributes = match edge.kind {
+                    EdgeKind::Branch => {
+                        let branch = &instructions[graph.blocks[edge.from].end - 1];
+                        format!(" [label=\"{}\"]", branch.opcode_str().to_lowercase())
+                    }
+                    EdgeKind::FallThrough => String::new(),
+                    EdgeKind::Repeat => " [style=dotted, label=\"repeat\"]".to_string(),
+                };
+                writeln!(
+                    dot,
+                    "        f{}_b{} -> f{}_b{}{};",
+                    id, edge.from, id, edge.to, attributes
+                )
+                .unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+        for (id, graph) in self.functions.iter().enumerate() {
+            let instructions = program.functions()[id].get_instructions();
+            for (pc, instruction) in instructions.iter().enumerate() {
+                if let Instruction::CallId(CallId {
+                    opcode: CallIdOpcode::Call,
+                    identifier,
+                }) = instruction
+                {
+                    let callee = self.functions.get(*identifier as usize);
+                    if let (Some(block), Some(callee)) = (graph.block_of(pc), callee) {
+                        if !callee.blocks.is_empty() {
+                            writeln!(
+                                dot,
+                                "    f{}_b{} -> f{}_b0 [style=dashed];",
+                                id, block, identifier
+                            )
+                            .unwrap();
+                        }
+                    }
+                }
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// Render the call graph as DOT, with each edge labelled with the calls
+    /// and the repeat of the callee.
+    pub fn call_graph_to_dot(&self, program: &Program) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph calls {{").unwrap();
+        writeln!(dot, "    node [shape=box];").unwrap();
+        for (id, function) in program.functions().iter().enumerate() {
+            writeln!(dot, "    f{} [label=\"{}\"];", id, function_label(function)).unwrap();
+        }
+        for call in self.calls.iter() {
+            let repeat = program.functions()[call.callee as usize].get_repeat();
+            let label = if repeat > 1 {
+                format!("{} x {}", call.calls, repeat)
+            } else {
+                call.calls.to_string()
+            };
+            writeln!(
+                dot,
+                "    f{} -> f{} [label=\"{}\"];",
+                call.caller, call.callee, label
+            )
+            .unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+fn function_label(function: &Function) -> String {
+    if function.get_repeat() > 1 {
+        format!("{} (repeat {})", function.get_name(), function.get_repeat())
+    } else {
+        function.get_name().to_string()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::function::Function;
+use crate::lang::{Branch, CallId, CallIdOpcode, Instruction};
+use crate::program::Program;
+
+/// Bounds on the instructions a call of a function executes, counted like the
+/// budget of `Program::interpret_with_budget` does: every instruction is one,
+/// including targets and calls, and a call adds what the called function
+/// executes. A call executes at least `min_bound` and at most `max_bound`
+/// instructions. Counts that don't fit in a u64 saturate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostBounds {
+    pub min_bound: u64,
+    pub max_bound: u64,
+}
+
+/// Bounds on the costs of the functions of a program. Since branches only go
+/// forward and there's no recursion, these are found by considering every
+/// path through each function. The analysis doesn't look at the branch
+/// conditions, so a path whose conditions can't hold together still counts,
+/// and the bounds are only exact when every path can be taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostAnalysis {
+    functions: Vec<Option<CostBounds>>,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Unvisited,
+    Visiting,
+    Done(Option<CostBounds>),
+}
+
+impl CostAnalysis {
+    pub fn new(program: &Program) -> CostAnalysis {
+        let functions = program.functions();
+        let mut states = vec![State::Unvisited; functions.len()];
+        for id in 0..functions.len() {
+            function_cost(functions, id, &mut states);
+        }
+        CostAnalysis {
+            functions: states
+                .into_iter()
+                .map(|state| match state {
+                    State::Done(cost) => cost,
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// The bounds on a call of function id. This is None for a function that
+    /// can't be run, because its calls recurse or go to a function that
+    /// doesn't exist, which only happens for functions main doesn't reach.
+    pub fn function(&self, id: u16) -> Option<CostBounds> {
+        self.functions.get(id as usize).copied().flatten()
+    }
+
+    pub fn functions(&self) -> &[Option<CostBounds>] {
+        &self.functions
+    }
+
+    /// The bounds on a run of the program, which are those of its main function.
+    pub fn program(&self) -> CostBounds {
+        self.function(0)
+            .expect("the calls of main are cleaned up, so it can always run")
+    }
+}
+
+fn function_cost(functions: &[Function], id: usize, states: &mut [State]) -> Option<CostBounds> {
+    match states[id] {
+        State::Done(cost) => return cost,
+        State::Visiting => return None,
+        State::Unvisited => {}
+    }
+    states[id] = State::Visiting;
+    let cost = iteration_cost(functions, &functions[id], states).map(|cost| {
+        let repeat = functions[id].get_repeat() as u64;
+        CostBounds {
+            min_bound: cost.min_bound.saturating_mul(repeat),
+            max_bound: cost.max_bound.saturating_mul(repeat),
+        }
+    });
+    states[id] = State::Done(cost);
+    cost
+}
+
+// the cost of a single iteration of function, going backwards from the end so
+// that the cost of the rest of the function is known at each branch
+fn iteration_cost(
+    functions: &[Function],
+    function: &Function,
+    states: &mut [State],
+) -> Option<CostBounds> {
+    let instructions = function.get_instructions();
+    let targets = Function::targets(instructions);
+    let mut rest = vec![
+        CostBounds {
+            min_bound: 0,
+            max_bound: 0
+        };
+        instructions.len() + 1
+    ];
+    for (pc, instruction) in instructions.iter().enumerate().rev() {
+        let own = match instruction {
+            Instruction::CallId(CallId {
+                opcode: CallIdOpcode::Call,
+                identifier,
+            }) => {
+                let identifier = *identifier as usize;
+                if identifier >= functions.len() {
+                    return None;
+                }
+                let called = function_cost(functions, identifier, states)?;
+                CostBounds {
+                    min_bound: called.min_bound.saturating_add(1),
+                    max_bound: called.max_bound.saturating_add(1),
+                }
+            }
+            _ => CostBounds {
+                min_bound: 1,
+                max_bound: 1,
+            },
+        };
+        let next = rest[pc + 1];
+        let after = match instruction {
+            Instruction::Branch(Branch { target, .. }) => match targets.get(target) {
+                Some(index) if *index > pc => CostBounds {
+                    min_bound: next.min_bound.min(rest[*index].min_bound),
+                    max_bound: next.max_bound.max(rest[*index].max_bound),
+                },
+                Some(_) => return None,
+                None => next,
+            },
+            _ => next,
+        };
+        rest[pc] = CostBounds {
+            min_bound: own.min_bound.saturating_add(after.min_bound),
+            max_bound: own.max_bound.saturating_add(after.max_bound),
+        };
+    }
+    Some(rest[0])
+}
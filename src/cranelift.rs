@@ -0,0 +1,915 @@
+use crate::function::Function;
+use crate::lang::{
+    index_bound, Branch, BranchOpcode, BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode,
+    Ecall, EcallOpcode, HostFunc, Immediate, ImmediateOpcode, Instruction, Load, LoadOpcode,
+    MachineOptions, MemoryProtection, Processor, Random, RandomOpcode, Register, RegisterOpcode,
+    Store, StoreOpcode, RNG_INCREMENT, RNG_MULTIPLIER,
+};
+use crate::program::Program;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, InstBuilder, MemFlags, Signature, Type, UserFuncName, Value,
+};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+use rustc_hash::FxHashMap;
+use std::marker::PhantomData;
+
+/// A compiled program, which gets the memory, the registers, the state of the
+/// random number generator, a table of host functions with its length, the
+/// base address of the window and the count of protection violations, as a
+/// `ProgramFunc` of `CodeGen` does.
+pub type CraneliftProgramFunc =
+    unsafe extern "C" fn(*mut u8, *mut i16, *mut u32, *const Option<HostFunc>, u32, u32, *mut u64);
+
+pub struct CraneliftCodeGen {
+    module: JITModule,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+    program_count: usize,
+    options: MachineOptions,
+    protection: MemoryProtection,
+}
+
+pub struct CraneliftFunction<'a> {
+    func: CraneliftProgramFunc,
+    codegen: PhantomData<&'a CraneliftCodeGen>,
+}
+
+impl<'a> CraneliftFunction<'a> {
+    pub fn run(&self, memory: &mut [u8]) {
+        self.run_with_registers(memory, &mut [0; 32]);
+    }
+
+    pub fn run_with_registers(&self, memory: &mut [u8], registers: &mut [i16; 32]) {
+        self.run_with_state(memory, registers, &mut 0, &[]);
+    }
+
+    /// Run with the given state of the random number generator, which is
+    /// updated, where `ecall n` calls `host_functions[n]`.
+    pub fn run_with_state(
+        &self,
+        memory: &mut [u8],
+        registers: &mut [i16; 32],
+        rng_state: &mut u32,
+        host_functions: &[Option<HostFunc>],
+    ) {
+        unsafe {
+            (self.func)(
+                memory.as_mut_ptr(),
+                registers.as_mut_ptr(),
+                rng_state,
+                host_functions.as_ptr(),
+                host_functions.len() as u32,
+                0,
+                &mut 0,
+            );
+        }
+    }
+
+    /// Run with the registers, the random number generator state and the base
+    /// address of the processor, where `ecall n` calls `host_functions[n]`.
+    /// The violations of the processor are those of this run.
+    pub fn run_with_processor(
+        &self,
+        memory: &mut [u8],
+        processor: &mut Processor,
+        host_functions: &[Option<HostFunc>],
+    ) {
+        let mut rng_state = processor.rng_state();
+        processor.start_run(memory.len());
+        let base = processor.window_base() as u32;
+        unsafe {
+            (self.func)(
+                memory.as_mut_ptr(),
+                processor.registers_mut().as_mut_ptr(),
+                &mut rng_state,
+                host_functions.as_ptr(),
+                host_functions.len() as u32,
+                base,
+                processor.violations_mut(),
+            );
+        }
+        processor.set_rng_state(rng_state);
+    }
+}
+
+// the state needed while translating the instructions of a single function
+struct FunctionTranslator<'a, 'b> {
+    builder: &'a mut FunctionBuilder<'b>,
+    pointer_type: Type,
+    memory_ptr: Value,
+    registers_ptr: Value,
+    rng_ptr: Value,
+    host_table: Value,
+    host_table_len: Value,
+    base: Value,
+    violations_ptr: Value,
+    memory_size: u32,
+    options: MachineOptions,
+    protection: &'a MemoryProtection,
+    call_conv: CallConv,
+}
+
+impl CraneliftCodeGen {
+    pub fn new() -> CraneliftCodeGen {
+        CraneliftCodeGen::build(MachineOptions::default())
+    }
+
+    /// Compile code for a machine with these options. Unlike `CodeGen`, the
+    /// Cranelift backend doesn't profile memory or execution, so options
+    /// that ask for a profile are an error.
+    pub fn with_options(options: MachineOptions) -> Result<CraneliftCodeGen, String> {
+        if options.profile_memory {
+            return Err("the Cranelift backend doesn't profile memory".to_string());
+        }
+        if options.profile_execution {
+            return Err("the Cranelift backend doesn't profile execution".to_string());
+        }
+        Ok(CraneliftCodeGen::build(options))
+    }
+
+    fn build(options: MachineOptions) -> CraneliftCodeGen {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("use_colocated_libcalls", "false").unwrap();
+        flag_builder.set("is_pic", "false").unwrap();
+        flag_builder.set("opt_level", "speed").unwrap();
+        let isa_builder = cranelift_native::builder().expect("Host machine is not supported");
+        let isa = isa_builder
+            .finish(settings::Flags::new(flag_builder))
+            .expect("Unable to build target isa");
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        CraneliftCodeGen {
+            context: module.make_context(),
+            module,
+            builder_context: FunctionBuilderContext::new(),
+            program_count: 0,
+            options,
+            protection: MemoryProtection::new(),
+        }
+    }
+
+    /// Compile memory access to follow the protection, as
+    /// `CodeGen::set_protection` does.
+    pub fn set_protection(&mut self, protection: MemoryProtection) {
+        self.protection = protection;
+    }
+
+    pub fn compile_program(
+        &mut self,
+        program: &Program,
+        memory_size: u32,
+    ) -> CraneliftFunction<'_> {
+        let program_id = self.program_count;
+        self.program_count += 1;
+
+        let mut func_ids = FxHashMap::default();
+        self.compile_function(program_id, 0, program, memory_size, &mut func_ids);
+
+        let signature = self.program_signature();
+        let func_id = self
+            .module
+            .declare_function(&format!("func-{}", program_id), Linkage::Export, &signature)
+            .expect("Unable to declare program function");
+
+        self.context.func.signature = signature;
+        self.context.func.name = UserFuncName::user(0, func_id.as_u32());
+        {
+            let mut builder =
+                FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+            let entry_block = builder.create_block();
+            builder.append_block_params_for_function_params(entry_block);
+            builder.switch_to_block(entry_block);
+            let params = builder.block_params(entry_block).to_vec();
+            let registers_ptr = params[1];
+            if self.options.zero_register {
+                let zero = builder.ins().iconst(types::I16, 0);
+                builder
+                    .ins()
+                    .store(MemFlags::trusted(), zero, registers_ptr, 0);
+            }
+
+            let inner_function = self.module.declare_func_in_func(func_ids[&0], builder.func);
+            builder.ins().call(inner_function, &params);
+            builder.ins().return_(&[]);
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        self.module
+            .define_function(func_id, &mut self.context)
+            .expect("Unable to define program function");
+        self.module.clear_context(&mut self.context);
+        self.module
+            .finalize_definitions()
+            .expect("Unable to finalize program");
+
+        let code = self.module.get_finalized_function(func_id);
+        CraneliftFunction {
+            func: unsafe { std::mem::transmute::<*const u8, CraneliftProgramFunc>(code) },
+            codegen: PhantomData,
+        }
+    }
+
+    fn compile_function(
+        &mut self,
+        program_id: usize,
+        call_id: u16,
+        program: &Program,
+        memory_size: u32,
+        func_ids: &mut FxHashMap<u16, FuncId>,
+    ) -> FuncId {
+        if let Some(func_id) = func_ids.get(&call_id) {
+            return *func_id;
+        }
+        let function = program.get_function(call_id);
+        // compile dependencies first; there is no recursion so this terminates
+        for dependency_call_id in function.get_call_id_set() {
+            self.compile_function(
+                program_id,
+                dependency_call_id,
+                program,
+                memory_size,
+                func_ids,
+            );
+        }
+
+        let pointer_type = self.module.target_config().pointer_type();
+        let call_conv = self.module.target_config().default_call_conv;
+        let signature = self.program_signature();
+        let func_id = self
+            .module
+            .declare_function(
+                &format!("inner-{}-{}", program_id, call_id),
+                Linkage::Local,
+                &signature,
+            )
+            .expect("Unable to declare function");
+
+        self.context.func.signature = signature;
+        self.context.func.name = UserFuncName::user(0, func_id.as_u32());
+        {
+            let mut builder =
+                FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+            let functions = func_ids
+                .iter()
+                .map(|(call_id, func_id)| {
+                    (
+                        *call_id,
+                        self.module.declare_func_in_func(*func_id, builder.func),
+                    )
+                })
+                .collect::<FxHashMap<_, _>>();
+
+            let entry_block = builder.create_block();
+            builder.append_block_params_for_function_params(entry_block);
+            builder.switch_to_block(entry_block);
+            let memory_ptr = builder.block_params(entry_block)[0];
+            let registers_ptr = builder.block_params(entry_block)[1];
+            let rng_ptr = builder.block_params(entry_block)[2];
+            let host_table = builder.block_params(entry_block)[3];
+            let host_table_len = builder.block_params(entry_block)[4];
+            let base = builder.block_params(entry_block)[5];
+            let violations_ptr = builder.block_params(entry_block)[6];
+
+            let repeat = function.get_repeat();
+            let loop_info = if repeat > 1 {
+                let loop_counter = Variable::from_u32(0);
+                builder.declare_var(loop_counter, types::I8);
+                let zero = builder.ins().iconst(types::I8, 0);
+                builder.def_var(loop_counter, zero);
+                let loop_block = builder.create_block();
+                builder.ins().jump(loop_block, &[]);
+                builder.switch_to_block(loop_block);
+                Some((loop_counter, loop_block))
+            } else {
+                None
+            };
+
+            let mut translator = FunctionTranslator {
+                builder: &mut builder,
+                pointer_type,
+                memory_ptr,
+                registers_ptr,
+                rng_ptr,
+                host_table,
+                host_table_len,
+                base,
+                violations_ptr,
+                memory_size,
+                options: self.options,
+                protection: &self.protection,
+                call_conv,
+            };
+            translator.translate_instructions(function, &|call_id| functions[&call_id]);
+
+            if let Some((loop_counter, loop_block)) = loop_info {
+                let end_block = builder.create_block();
+                let counter = builder.use_var(loop_counter);
+                let counter = builder.ins().iadd_imm(counter, 1);
+                builder.def_var(loop_counter, counter);
+                let loop_continue =
+                    builder
+                        .ins()
+                        .icmp_imm(IntCC::UnsignedLessThan, counter, repeat as i64);
+                builder
+                    .ins()
+                    .brif(loop_continue, loop_block, &[], end_block, &[]);
+                builder.switch_to_block(end_block);
+            }
+            builder.ins().return_(&[]);
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        self.module
+            .define_function(func_id, &mut self.context)
+            .expect("Unable to define function");
+        self.module.clear_context(&mut self.context);
+        func_ids.insert(call_id, func_id);
+        func_id
+    }
+
+    // the signature of compiled functions, as in `CraneliftProgramFunc`
+    fn program_signature(&self) -> Signature {
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(types::I32));
+        signature.params.push(AbiParam::new(types::I32));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature
+    }
+}
+
+impl Default for CraneliftCodeGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, 'b> FunctionTranslator<'a, 'b> {
+    fn translate_instructions<F>(&mut self, function: &Function, functions: &F)
+    where
+        F: Fn(u16) -> cranelift_codegen::ir::FuncRef,
+    {
+        let instructions = function.get_instructions();
+        // only the last target with a given identifier is jumped to
+        let mut targets = FxHashMap::default();
+        for (index, instruction) in instructions.iter().enumerate() {
+            if let Instruction::BranchTarget(BranchTarget {
+                opcode: _,
+                identifier,
+            }) = instruction
+            {
+                targets.insert(*identifier, index);
+            }
+        }
+        let target_blocks: FxHashMap<u8, (usize, Block)> = targets
+            .into_iter()
+            .map(|(identifier, index)| (identifier, (index, self.builder.create_block())))
+            .collect();
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Immediate(immediate) => self.translate_immediate(immediate),
+                Instruction::Register(register) => self.translate_register(register),
+                Instruction::Load(load) => self.translate_load(load),
+                Instruction::Store(store) => self.translate_store(store),
+                Instruction::Branch(branch) => self.translate_branch(branch, &target_blocks),
+                Instruction::BranchTarget(branch_target) => {
+                    use BranchTargetOpcode::*;
+                    match branch_target.opcode {
+                        Target => {
+                            if let Some((target_index, block)) =
+                                target_blocks.get(&branch_target.identifier)
+                            {
+                                if *target_index == index {
+                                    self.builder.ins().jump(*block, &[]);
+                                    self.builder.switch_to_block(*block);
+                                }
+                            }
+                        }
+                    }
+                }
+                Instruction::CallId(call_id) => self.translate_call(call_id, functions),
+                Instruction::Ecall(ecall) => self.translate_ecall(ecall),
+                Instruction::Random(random) => self.translate_random(random),
+            }
+        }
+    }
+
+    fn get_register(&mut self, index: u8) -> Value {
+        self.builder.ins().load(
+            types::I16,
+            MemFlags::trusted(),
+            self.registers_ptr,
+            index as i32 * 2,
+        )
+    }
+
+    fn set_register(&mut self, index: u8, value: Value) {
+        if self.options.zero_register && index == 0 {
+            return;
+        }
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            value,
+            self.registers_ptr,
+            index as i32 * 2,
+        );
+    }
+
+    fn bool_to_i16(&mut self, value: Value) -> Value {
+        self.builder.ins().uextend(types::I16, value)
+    }
+
+    fn translate_immediate(&mut self, immediate: &Immediate) {
+        use ImmediateOpcode::*;
+        let rs_value = self.get_register(immediate.rs);
+        let value = immediate.value;
+        let result = match immediate.opcode {
+            Addi => self.builder.ins().iadd_imm(rs_value, value as i64),
+            Slti => {
+                let cmp =
+                    self.builder
+                        .ins()
+                        .icmp_imm(IntCC::SignedLessThan, rs_value, value as i64);
+                self.bool_to_i16(cmp)
+            }
+            Sltiu => {
+                let cmp = self.builder.ins().icmp_imm(
+                    IntCC::UnsignedLessThan,
+                    rs_value,
+                    value as u16 as i64,
+                );
+                self.bool_to_i16(cmp)
+            }
+            Andi => self.builder.ins().band_imm(rs_value, value as i64),
+            Ori => self.builder.ins().bor_imm(rs_value, value as i64),
+            Xori => self.builder.ins().bxor_imm(rs_value, value as i64),
+            Slli => self
+                .builder
+                .ins()
+                .ishl_imm(rs_value, Self::immediate_shift(value)),
+            Srli => self
+                .builder
+                .ins()
+                .ushr_imm(rs_value, Self::immediate_shift(value)),
+            Srai => self
+                .builder
+                .ins()
+                .sshr_imm(rs_value, Self::immediate_shift(value)),
+        };
+        self.set_register(immediate.rd, result);
+    }
+
+    fn immediate_shift(value: i16) -> i64 {
+        // shifting by 16 or more is a no-op, just like in the other backends
+        if (value as u16) < 16 {
+            value as i64
+        } else {
+            0
+        }
+    }
+
+    fn translate_register(&mut self, register: &Register) {
+        use RegisterOpcode::*;
+        let rs1_value = self.get_register(register.rs1);
+        let rs2_value = self.get_register(register.rs2);
+        let result = match register.opcode {
+            Add => self.builder.ins().iadd(rs1_value, rs2_value),
+            Sub => self.builder.ins().isub(rs1_value, rs2_value),
+            Slt => {
+                let cmp = self
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedLessThan, rs1_value, rs2_value);
+                self.bool_to_i16(cmp)
+            }
+            Sltu => {
+                let cmp = self
+                    .builder
+                    .ins()
+                    .icmp(IntCC::UnsignedLessThan, rs1_value, rs2_value);
+                self.bool_to_i16(cmp)
+            }
+            And => self.builder.ins().band(rs1_value, rs2_value),
+            Or => self.builder.ins().bor(rs1_value, rs2_value),
+            Xor => self.builder.ins().bxor(rs1_value, rs2_value),
+            Sll => {
+                let shift = self.register_shift(rs2_value);
+                self.builder.ins().ishl(rs1_value, shift)
+            }
+            Srl => {
+                let shift = self.register_shift(rs2_value);
+                self.builder.ins().ushr(rs1_value, shift)
+            }
+            Sra => {
+                let shift = self.register_shift(rs2_value);
+                self.builder.ins().sshr(rs1_value, shift)
+            }
+        };
+        self.set_register(register.rd, result);
+    }
+
+    fn register_shift(&mut self, value: Value) -> Value {
+        let too_large = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, value, 16);
+        let zero = self.builder.ins().iconst(types::I16, 0);
+        self.builder.ins().select(too_large, zero, value)
+    }
+
+    // calculate the address of an access of size bytes and a flag to indicate
+    // whether it's in bounds
+    fn address(&mut self, rs: u8, offset: u16, size: u32) -> (Value, Value) {
+        let rs_value = self.get_register(rs);
+        let index = self.builder.ins().iadd_imm(rs_value, offset as i64);
+        let index = self.builder.ins().uextend(self.pointer_type, index);
+        let in_bounds = self.builder.ins().icmp_imm(
+            IntCC::UnsignedLessThan,
+            index,
+            index_bound(self.memory_size, size) as i64,
+        );
+        let index = self.builder.ins().imul_imm(index, size as i64);
+        let address = self.builder.ins().iadd(self.memory_ptr, index);
+        (address, in_bounds)
+    }
+
+    // whether loads, or stores if write is set, are translated byte by byte. a
+    // halfword in a window may wrap around the end of memory, and memory
+    // protection is checked for each byte
+    fn bytewise(&self, write: bool) -> bool {
+        self.options.window_size.is_some() || self.protection.forbidding(write).next().is_some()
+    }
+
+    // the memory indexes of the bytes accessed at rs + offset, and whether the
+    // access is in bounds, which is inside of the window if there is one
+    fn byte_indexes(&mut self, rs: u8, offset: u16, size: u32) -> (Vec<Value>, Value) {
+        let window_size = self
+            .options
+            .window_size
+            .map(u32::from)
+            .unwrap_or(self.memory_size);
+        let rs_value = self.get_register(rs);
+        let index = self.builder.ins().iadd_imm(rs_value, offset as i64);
+        let index = self.builder.ins().uextend(types::I64, index);
+        let in_bounds = if self.memory_size == 0 {
+            self.builder.ins().iconst(types::I8, 0)
+        } else {
+            self.builder.ins().icmp_imm(
+                IntCC::UnsignedLessThan,
+                index,
+                index_bound(window_size, size) as i64,
+            )
+        };
+        let index = self.builder.ins().imul_imm(index, size as i64);
+        let base = if self.options.window_size.is_some() {
+            self.builder.ins().uextend(types::I64, self.base)
+        } else {
+            self.builder.ins().iconst(types::I64, 0)
+        };
+        let address = self.builder.ins().iadd(base, index);
+        // avoid a division by zero, though nothing is accessed then
+        let memory_size = self.memory_size.max(1) as i64;
+        let indexes = (0..size)
+            .map(|byte| {
+                let address = self.builder.ins().iadd_imm(address, byte as i64);
+                self.builder.ins().urem_imm(address, memory_size)
+            })
+            .collect();
+        (indexes, in_bounds)
+    }
+
+    // the address of the byte at a memory index
+    fn byte_address(&mut self, index: Value) -> Value {
+        let index = if self.pointer_type == types::I64 {
+            index
+        } else {
+            self.builder.ins().ireduce(self.pointer_type, index)
+        };
+        self.builder.ins().iadd(self.memory_ptr, index)
+    }
+
+    // whether an access to the bytes at indexes goes ahead, which is when it's
+    // in bounds and memory protection permits it. an access that's in bounds
+    // but forbidden is counted as a violation
+    fn permitted(&mut self, in_bounds: Value, indexes: &[Value], write: bool) -> Value {
+        let mut permitted = self.builder.ins().iconst(types::I8, 1);
+        for region in self.protection.forbidding(write) {
+            for index in indexes {
+                let before = self.builder.ins().icmp_imm(
+                    IntCC::UnsignedLessThan,
+                    *index,
+                    region.start as i64,
+                );
+                let after = self.builder.ins().icmp_imm(
+                    IntCC::UnsignedGreaterThanOrEqual,
+                    *index,
+                    region.end as i64,
+                );
+                let outside = self.builder.ins().bor(before, after);
+                permitted = self.builder.ins().band(permitted, outside);
+            }
+        }
+        if self.options.count_violations {
+            let forbidden = self.builder.ins().bxor_imm(permitted, 1);
+            let violation = self.builder.ins().band(in_bounds, forbidden);
+            let violation = self.builder.ins().uextend(types::I64, violation);
+            let flags = MemFlags::trusted();
+            let violations = self
+                .builder
+                .ins()
+                .load(types::I64, flags, self.violations_ptr, 0);
+            let violations = self.builder.ins().iadd(violations, violation);
+            self.builder
+                .ins()
+                .store(flags, violations, self.violations_ptr, 0);
+        }
+        self.builder.ins().band(in_bounds, permitted)
+    }
+
+    fn translate_load_bytes(&mut self, load: &Load) {
+        use LoadOpcode::*;
+        let size = match load.opcode {
+            Lh => 2,
+            Lb | Lbu => 1,
+        };
+        let (indexes, in_bounds) = self.byte_indexes(load.rs, load.offset, size);
+        let permitted = self.permitted(in_bounds, &indexes, false);
+
+        let then_block = self.builder.create_block();
+        let else_block = self.builder.create_block();
+        let end_block = self.builder.create_block();
+        self.builder.append_block_param(end_block, types::I16);
+        self.builder
+            .ins()
+            .brif(permitted, then_block, &[], else_block, &[]);
+
+        self.builder.switch_to_block(then_block);
+        let addresses: Vec<Value> = indexes
+            .into_iter()
+            .map(|index| self.byte_address(index))
+            .collect();
+        let flags = MemFlags::new().with_notrap();
+        let value = match load.opcode {
+            Lh => {
+                let low = self
+                    .builder
+                    .ins()
+                    .uload8(types::I16, flags, addresses[0], 0);
+                let high = self
+                    .builder
+                    .ins()
+                    .uload8(types::I16, flags, addresses[1], 0);
+                let high = self.builder.ins().ishl_imm(high, 8);
+                self.builder.ins().bor(low, high)
+            }
+            Lb => self
+                .builder
+                .ins()
+                .sload8(types::I16, flags, addresses[0], 0),
+            Lbu => self
+                .builder
+                .ins()
+                .uload8(types::I16, flags, addresses[0], 0),
+        };
+        self.builder.ins().jump(end_block, &[value]);
+
+        self.builder.switch_to_block(else_block);
+        let zero = self.builder.ins().iconst(types::I16, 0);
+        self.builder.ins().jump(end_block, &[zero]);
+
+        self.builder.switch_to_block(end_block);
+        let result = self.builder.block_params(end_block)[0];
+        self.set_register(load.rd, result);
+    }
+
+    fn translate_store_bytes(&mut self, store: &Store) {
+        use StoreOpcode::*;
+        let size = match store.opcode {
+            Sh => 2,
+            Sb => 1,
+        };
+        let (indexes, in_bounds) = self.byte_indexes(store.rd, store.offset, size);
+        let permitted = self.permitted(in_bounds, &indexes, true);
+
+        let then_block = self.builder.create_block();
+        let end_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(permitted, then_block, &[], end_block, &[]);
+
+        self.builder.switch_to_block(then_block);
+        let value = self.get_register(store.rs);
+        let flags = MemFlags::new().with_notrap();
+        for (byte, index) in indexes.into_iter().enumerate() {
+            let address = self.byte_address(index);
+            let shifted = self.builder.ins().ushr_imm(value, byte as i64 * 8);
+            self.builder.ins().istore8(flags, shifted, address, 0);
+        }
+        self.builder.ins().jump(end_block, &[]);
+
+        self.builder.switch_to_block(end_block);
+    }
+
+    fn translate_load(&mut self, load: &Load) {
+        if self.bytewise(false) {
+            self.translate_load_bytes(load);
+            return;
+        }
+        use LoadOpcode::*;
+        let size = match load.opcode {
+            Lh => 2,
+            Lb | Lbu => 1,
+        };
+        let (address, in_bounds) = self.address(load.rs, load.offset, size);
+
+        let then_block = self.builder.create_block();
+        let else_block = self.builder.create_block();
+        let end_block = self.builder.create_block();
+        self.builder.append_block_param(end_block, types::I16);
+        self.builder
+            .ins()
+            .brif(in_bounds, then_block, &[], else_block, &[]);
+
+        self.builder.switch_to_block(then_block);
+        let flags = MemFlags::new().with_notrap();
+        let value = match load.opcode {
+            Lh => self.builder.ins().load(types::I16, flags, address, 0),
+            Lb => self.builder.ins().sload8(types::I16, flags, address, 0),
+            Lbu => self.builder.ins().uload8(types::I16, flags, address, 0),
+        };
+        self.builder.ins().jump(end_block, &[value]);
+
+        self.builder.switch_to_block(else_block);
+        let zero = self.builder.ins().iconst(types::I16, 0);
+        self.builder.ins().jump(end_block, &[zero]);
+
+        self.builder.switch_to_block(end_block);
+        let result = self.builder.block_params(end_block)[0];
+        self.set_register(load.rd, result);
+    }
+
+    fn translate_store(&mut self, store: &Store) {
+        if self.bytewise(true) {
+            self.translate_store_bytes(store);
+            return;
+        }
+        use StoreOpcode::*;
+        let size = match store.opcode {
+            Sh => 2,
+            Sb => 1,
+        };
+        let (address, in_bounds) = self.address(store.rd, store.offset, size);
+
+        let then_block = self.builder.create_block();
+        let end_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(in_bounds, then_block, &[], end_block, &[]);
+
+        self.builder.switch_to_block(then_block);
+        let value = self.get_register(store.rs);
+        let flags = MemFlags::new().with_notrap();
+        match store.opcode {
+            Sh => self.builder.ins().store(flags, value, address, 0),
+            Sb => self.builder.ins().istore8(flags, value, address, 0),
+        };
+        self.builder.ins().jump(end_block, &[]);
+
+        self.builder.switch_to_block(end_block);
+    }
+
+    fn translate_branch(&mut self, branch: &Branch, targets: &FxHashMap<u8, (usize, Block)>) {
+        use BranchOpcode::*;
+        let condition = match branch.opcode {
+            Beq => IntCC::Equal,
+            Bne => IntCC::NotEqual,
+            Blt => IntCC::SignedLessThan,
+            Bltu => IntCC::UnsignedLessThan,
+            Bge => IntCC::SignedGreaterThanOrEqual,
+            Bgeu => IntCC::UnsignedGreaterThanOrEqual,
+        };
+        if let Some((_, target_block)) = targets.get(&branch.target) {
+            let rs1_value = self.get_register(branch.rs1);
+            let rs2_value = self.get_register(branch.rs2);
+            let cond = self.builder.ins().icmp(condition, rs1_value, rs2_value);
+            let next_block = self.builder.create_block();
+            self.builder
+                .ins()
+                .brif(cond, *target_block, &[], next_block, &[]);
+            self.builder.switch_to_block(next_block);
+        }
+    }
+
+    fn translate_call<F>(&mut self, call: &CallId, functions: &F)
+    where
+        F: Fn(u16) -> cranelift_codegen::ir::FuncRef,
+    {
+        use CallIdOpcode::*;
+        match call.opcode {
+            Call => {
+                let function = functions(call.identifier);
+                self.builder.ins().call(
+                    function,
+                    &[
+                        self.memory_ptr,
+                        self.registers_ptr,
+                        self.rng_ptr,
+                        self.host_table,
+                        self.host_table_len,
+                        self.base,
+                        self.violations_ptr,
+                    ],
+                );
+            }
+        }
+    }
+
+    // call the host function of the number in the table, if there is one
+    fn translate_ecall(&mut self, ecall: &Ecall) {
+        use EcallOpcode::*;
+        match ecall.opcode {
+            Ecall => {
+                let lookup_block = self.builder.create_block();
+                let call_block = self.builder.create_block();
+                let end_block = self.builder.create_block();
+
+                let in_table = self.builder.ins().icmp_imm(
+                    IntCC::UnsignedGreaterThan,
+                    self.host_table_len,
+                    ecall.number as i64,
+                );
+                self.builder
+                    .ins()
+                    .brif(in_table, lookup_block, &[], end_block, &[]);
+
+                self.builder.switch_to_block(lookup_block);
+                let offset = ecall.number as i32 * self.pointer_type.bytes() as i32;
+                let host_function = self.builder.ins().load(
+                    self.pointer_type,
+                    MemFlags::trusted(),
+                    self.host_table,
+                    offset,
+                );
+                self.builder
+                    .ins()
+                    .brif(host_function, call_block, &[], end_block, &[]);
+
+                self.builder.switch_to_block(call_block);
+                let mut signature = Signature::new(self.call_conv);
+                signature.params.push(AbiParam::new(self.pointer_type));
+                signature.params.push(AbiParam::new(self.pointer_type));
+                signature.params.push(AbiParam::new(types::I32));
+                let signature = self.builder.import_signature(signature);
+                let memory_size = self
+                    .builder
+                    .ins()
+                    .iconst(types::I32, self.memory_size as i64);
+                self.builder.ins().call_indirect(
+                    signature,
+                    host_function,
+                    &[self.registers_ptr, self.memory_ptr, memory_size],
+                );
+                // the host function may have written to a hardwired zero register
+                if self.options.zero_register {
+                    let zero = self.builder.ins().iconst(types::I16, 0);
+                    self.builder
+                        .ins()
+                        .store(MemFlags::trusted(), zero, self.registers_ptr, 0);
+                }
+                self.builder.ins().jump(end_block, &[]);
+
+                self.builder.switch_to_block(end_block);
+            }
+        }
+    }
+
+    fn translate_random(&mut self, random: &Random) {
+        use RandomOpcode::*;
+        match random.opcode {
+            Rand => {
+                let state =
+                    self.builder
+                        .ins()
+                        .load(types::I32, MemFlags::trusted(), self.rng_ptr, 0);
+                let state = self.builder.ins().imul_imm(state, RNG_MULTIPLIER as i64);
+                let state = self.builder.ins().iadd_imm(state, RNG_INCREMENT as i64);
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), state, self.rng_ptr, 0);
+                let high = self.builder.ins().ushr_imm(state, 16);
+                let result = self.builder.ins().ireduce(types::I16, high);
+                self.set_register(random.rd, result);
+            }
+        }
+    }
+}
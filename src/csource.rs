@@ -0,0 +1,354 @@
+use crate::function::Function;
+use crate::lang::{
+    index_bound, Branch, BranchOpcode, BranchTargetOpcode, CallId, CallIdOpcode, Immediate,
+    ImmediateOpcode, Instruction, Load, LoadOpcode, MachineOptions, Random, RandomOpcode, Register,
+    RegisterOpcode, Store, StoreOpcode, RNG_INCREMENT, RNG_MULTIPLIER,
+};
+use crate::program::Program;
+use std::fmt::Write;
+
+/// Translate a program into portable C source.
+///
+/// Every reachable aleven function becomes a static C function that holds the
+/// registers in locals, and the program is run by calling
+/// `void <name>(uint8_t *memory)` with `memory_size` bytes of memory, or
+/// `void <name>_seeded(uint8_t *memory, uint32_t *rng)` to pass in the state
+/// of the random number generator, which is otherwise 0. Like the
+/// other backends the generated code does bounds checks on all memory access
+/// and does not recurse, and it avoids undefined and implementation defined
+/// behavior, so it can be compiled by any C99 compiler. There are no host
+/// functions in the generated source, so a program that does an `ecall` is
+/// rejected.
+///
+/// Of the `options`, only `zero_register` is supported; windows, violation
+/// counting and profiling are rejected.
+pub fn compile_c(
+    program: &Program,
+    name: &str,
+    memory_size: u32,
+    options: MachineOptions,
+) -> Result<String, String> {
+    if program.calls_host_functions() {
+        return Err("host functions aren't supported by the C backend".to_string());
+    }
+    if options.window_size.is_some()
+        || options.count_violations
+        || options.profile_memory
+        || options.profile_execution
+    {
+        return Err("only the zero register option is supported by the C backend".to_string());
+    }
+    let mut output = String::new();
+    writeln!(output, "#include <stdint.h>").unwrap();
+    writeln!(output).unwrap();
+    write_helpers(&mut output, name);
+    for call_id in program.reachable_call_ids() {
+        write_function(
+            &mut output,
+            program,
+            name,
+            call_id,
+            memory_size,
+            options.zero_register,
+        );
+    }
+    writeln!(
+        output,
+        "void {}_seeded(uint8_t *memory, uint32_t *rng) {{",
+        name
+    )
+    .unwrap();
+    writeln!(output, "    int16_t registers[32] = {{0}};").unwrap();
+    writeln!(output, "    {}_f0(memory, registers, rng);", name).unwrap();
+    writeln!(output, "}}").unwrap();
+    writeln!(output).unwrap();
+    writeln!(output, "void {}(uint8_t *memory) {{", name).unwrap();
+    writeln!(output, "    uint32_t rng = 0;").unwrap();
+    writeln!(output, "    {}_seeded(memory, &rng);", name).unwrap();
+    writeln!(output, "}}").unwrap();
+    Ok(output)
+}
+
+// converting an out of range value to a signed type is implementation
+// defined in C, so we do the wrapping ourselves
+fn write_helpers(output: &mut String, name: &str) {
+    writeln!(output, "static int16_t {}_wrap(int32_t value) {{", name).unwrap();
+    writeln!(output, "    uint16_t bits = (uint16_t)value;").unwrap();
+    writeln!(
+        output,
+        "    return bits < 0x8000 ? (int16_t)bits : (int16_t)((int32_t)bits - 0x10000);"
+    )
+    .unwrap();
+    writeln!(output, "}}").unwrap();
+    writeln!(output).unwrap();
+    writeln!(
+        output,
+        "static int16_t {}_sra(int16_t value, int shift) {{",
+        name
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "    return value < 0 ? (int16_t)~(~value >> shift) : (int16_t)(value >> shift);"
+    )
+    .unwrap();
+    writeln!(output, "}}").unwrap();
+    writeln!(output).unwrap();
+}
+
+fn write_function(
+    output: &mut String,
+    program: &Program,
+    name: &str,
+    call_id: u16,
+    memory_size: u32,
+    zero_register: bool,
+) {
+    let function = program.get_function(call_id);
+    let instructions = function.get_instructions();
+    let repeat = function.get_repeat();
+
+    // only the last target with a given identifier is used
+    let targets = Function::targets(instructions);
+
+    writeln!(
+        output,
+        "static void {}_f{}(uint8_t *memory, int16_t *registers, uint32_t *rng) {{",
+        name, call_id
+    )
+    .unwrap();
+    for register in 0..32 {
+        writeln!(
+            output,
+            "    int16_t r{} = registers[{}];",
+            register, register
+        )
+        .unwrap();
+    }
+    writeln!(output, "    uint16_t i;").unwrap();
+    writeln!(output, "    uint8_t repeat;").unwrap();
+    writeln!(
+        output,
+        "    for (repeat = 0; repeat < {}; repeat++) {{",
+        repeat
+    )
+    .unwrap();
+
+    let mut writer = FunctionWriter {
+        output,
+        name,
+        memory_size,
+        zero_register,
+    };
+    for (index, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            Instruction::Immediate(immediate) => writer.write_immediate(immediate),
+            Instruction::Register(register) => writer.write_register(register),
+            Instruction::Load(load) => writer.write_load(load),
+            Instruction::Store(store) => writer.write_store(store),
+            Instruction::Branch(branch) => {
+                if targets.contains_key(&branch.target) {
+                    writer.write_branch(branch);
+                }
+            }
+            Instruction::BranchTarget(branch_target) => {
+                use BranchTargetOpcode::*;
+                match branch_target.opcode {
+                    Target => {
+                        if targets.get(&branch_target.identifier) == Some(&index) {
+                            writer.line(&format!("t{}:;", branch_target.identifier));
+                        }
+                    }
+                }
+            }
+            Instruction::CallId(call_id) => writer.write_call(call_id),
+            Instruction::Ecall(_) => unreachable!("ecall is rejected before compilation"),
+            Instruction::Random(random) => writer.write_random(random),
+        }
+    }
+
+    writeln!(output, "    }}").unwrap();
+    write_save_registers(output, "    ");
+    // silence unused variable warnings in functions without memory access
+    writeln!(output, "    (void)i;").unwrap();
+    writeln!(output, "    (void)memory;").unwrap();
+    writeln!(output, "    (void)rng;").unwrap();
+    writeln!(output, "}}").unwrap();
+    writeln!(output).unwrap();
+}
+
+fn write_save_registers(output: &mut String, indent: &str) {
+    for register in 0..32 {
+        writeln!(output, "{}registers[{}] = r{};", indent, register, register).unwrap();
+    }
+}
+
+struct FunctionWriter<'a> {
+    output: &'a mut String,
+    name: &'a str,
+    memory_size: u32,
+    zero_register: bool,
+}
+
+impl<'a> FunctionWriter<'a> {
+    fn line(&mut self, line: &str) {
+        writeln!(self.output, "        {}", line).unwrap();
+    }
+
+    fn assign_wrapped(&mut self, rd: u8, expression: &str) {
+        let expression = format!("{}_wrap({})", self.name, expression);
+        self.assign(rd, &expression);
+    }
+
+    // expressions have no side effects, so with a zero register a write to r0
+    // can be left out
+    fn assign(&mut self, rd: u8, expression: &str) {
+        if self.zero_register && rd == 0 {
+            return;
+        }
+        let line = format!("r{} = {};", rd, expression);
+        self.line(&line);
+    }
+
+    fn write_immediate(&mut self, immediate: &Immediate) {
+        use ImmediateOpcode::*;
+        let rd = immediate.rd;
+        let rs = immediate.rs;
+        let value = immediate.value;
+        // shifting by 16 or more is a no-op
+        let shift = if (value as u16) < 16 { value } else { 0 };
+        match immediate.opcode {
+            Addi => self.assign_wrapped(rd, &format!("(int32_t)r{} + {}", rs, value)),
+            Slti => self.assign(rd, &format!("r{} < {}", rs, value)),
+            Sltiu => self.assign(rd, &format!("(uint16_t)r{} < {}", rs, value as u16)),
+            Andi => self.assign(rd, &format!("(int16_t)(r{} & {})", rs, value)),
+            Ori => self.assign(rd, &format!("(int16_t)(r{} | {})", rs, value)),
+            Xori => self.assign(rd, &format!("(int16_t)(r{} ^ {})", rs, value)),
+            Slli => self.assign_wrapped(rd, &format!("(int32_t)(uint16_t)r{} << {}", rs, shift)),
+            Srli => self.assign_wrapped(rd, &format!("(uint16_t)r{} >> {}", rs, shift)),
+            Srai => self.assign(rd, &format!("{}_sra(r{}, {})", self.name, rs, shift)),
+        }
+    }
+
+    fn write_register(&mut self, register: &Register) {
+        use RegisterOpcode::*;
+        let rd = register.rd;
+        let rs1 = register.rs1;
+        let rs2 = register.rs2;
+        // shifting by 16 or more is a no-op
+        let shift = format!("((uint16_t)r{} < 16 ? r{} : 0)", rs2, rs2);
+        match register.opcode {
+            Add => self.assign_wrapped(rd, &format!("(int32_t)r{} + r{}", rs1, rs2)),
+            Sub => self.assign_wrapped(rd, &format!("(int32_t)r{} - r{}", rs1, rs2)),
+            Slt => self.assign(rd, &format!("r{} < r{}", rs1, rs2)),
+            Sltu => self.assign(rd, &format!("(uint16_t)r{} < (uint16_t)r{}", rs1, rs2)),
+            And => self.assign(rd, &format!("(int16_t)(r{} & r{})", rs1, rs2)),
+            Or => self.assign(rd, &format!("(int16_t)(r{} | r{})", rs1, rs2)),
+            Xor => self.assign(rd, &format!("(int16_t)(r{} ^ r{})", rs1, rs2)),
+            Sll => self.assign_wrapped(rd, &format!("(int32_t)(uint16_t)r{} << {}", rs1, shift)),
+            Srl => self.assign_wrapped(rd, &format!("(uint16_t)r{} >> {}", rs1, shift)),
+            Sra => self.assign(rd, &format!("{}_sra(r{}, {})", self.name, rs1, shift)),
+        }
+    }
+
+    fn write_index(&mut self, rs: u8, offset: u16) {
+        let line = format!("i = (uint16_t)((int32_t)r{} + {});", rs, offset);
+        self.line(&line);
+    }
+
+    fn write_load(&mut self, load: &Load) {
+        use LoadOpcode::*;
+        let rd = load.rd;
+        self.write_index(load.rs, load.offset);
+        match load.opcode {
+            Lh => self.assign_wrapped(
+                rd,
+                &format!(
+                    "i < {} ? memory[i * 2] | memory[i * 2 + 1] << 8 : 0",
+                    index_bound(self.memory_size, 2)
+                ),
+            ),
+            Lb => self.assign(
+                rd,
+                &format!(
+                    "i < {} ? (int16_t)(memory[i] < 128 ? memory[i] : memory[i] - 256) : 0",
+                    index_bound(self.memory_size, 1)
+                ),
+            ),
+            Lbu => {
+                let bound = index_bound(self.memory_size, 1);
+                self.assign(rd, &format!("i < {} ? memory[i] : 0", bound))
+            }
+        }
+    }
+
+    fn write_store(&mut self, store: &Store) {
+        use StoreOpcode::*;
+        let rs = store.rs;
+        self.write_index(store.rd, store.offset);
+        let line = match store.opcode {
+            Sh => format!(
+                "if (i < {}) {{ memory[i * 2] = (uint8_t)r{}; memory[i * 2 + 1] = (uint8_t)((uint16_t)r{} >> 8); }}",
+                index_bound(self.memory_size, 2),
+                rs,
+                rs
+            ),
+            Sb => format!(
+                "if (i < {}) memory[i] = (uint8_t)r{};",
+                index_bound(self.memory_size, 1),
+                rs
+            ),
+        };
+        self.line(&line);
+    }
+
+    fn write_branch(&mut self, branch: &Branch) {
+        use BranchOpcode::*;
+        let rs1 = branch.rs1;
+        let rs2 = branch.rs2;
+        let condition = match branch.opcode {
+            Beq => format!("r{} == r{}", rs1, rs2),
+            Bne => format!("r{} != r{}", rs1, rs2),
+            Blt => format!("r{} < r{}", rs1, rs2),
+            Bltu => format!("(uint16_t)r{} < (uint16_t)r{}", rs1, rs2),
+            Bge => format!("r{} >= r{}", rs1, rs2),
+            Bgeu => format!("(uint16_t)r{} >= (uint16_t)r{}", rs1, rs2),
+        };
+        let line = format!("if ({}) goto t{};", condition, branch.target);
+        self.line(&line);
+    }
+
+    fn write_random(&mut self, random: &Random) {
+        use RandomOpcode::*;
+        match random.opcode {
+            Rand => {
+                // unsigned long has at least 32 bits, so this wraps as intended
+                let line = format!(
+                    "*rng = (uint32_t)(*rng * {}UL + {}UL);",
+                    RNG_MULTIPLIER, RNG_INCREMENT
+                );
+                self.line(&line);
+                self.assign_wrapped(random.rd, "(int32_t)(*rng >> 16)");
+            }
+        }
+    }
+
+    // the called function shares the registers, so we hand them over
+    fn write_call(&mut self, call: &CallId) {
+        use CallIdOpcode::*;
+        match call.opcode {
+            Call => {
+                write_save_registers(self.output, "        ");
+                let line = format!(
+                    "{}_f{}(memory, registers, rng);",
+                    self.name, call.identifier
+                );
+                self.line(&line);
+                for register in 0..32 {
+                    let line = format!("r{} = registers[{}];", register, register);
+                    self.line(&line);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use crate::assembler::{parse_program_with_labels, ParseProgramError};
+use crate::disassembler::disassemble;
+use crate::lang::{BranchTarget, Instruction, Processor};
+use crate::program::{Program, RunStatus};
+use std::fmt::Write;
+
+const HELP: &str = "\
+break <location>     stop before the instruction at location
+delete <location>    remove a breakpoint
+breakpoints          list the breakpoints
+step [n]             execute n instructions, 1 by default
+continue             execute until a breakpoint or the end of the program
+regs                 print the registers
+set r<n> <value>     set a register
+mem <start> <len>    print a range of memory
+write <start> <byte>...  write bytes to memory
+stack                print the call stack
+list [function]      print the instructions of a function
+restart              run the program again from the start
+quit                 leave the debugger
+
+A location is <function>:<index> or <function>:<label>, or just <function>
+for its first instruction.";
+
+/// Steps through a program with the interpreter, one instruction at a time.
+///
+/// Instruction indexes are those of the program as it's run, which can
+/// differ from the source when the assembler removed backward branches.
+pub struct Debugger {
+    program: Program,
+    labels: Vec<Vec<String>>,
+    initial_memory: Vec<u8>,
+    memory: Vec<u8>,
+    processor: Processor,
+    breakpoints: Vec<(u16, usize)>,
+    finished: bool,
+}
+
+impl Debugger {
+    pub fn new(program: Program, labels: Vec<Vec<String>>, memory: Vec<u8>) -> Debugger {
+        let mut debugger = Debugger {
+            program,
+            labels,
+            initial_memory: memory.clone(),
+            memory,
+            processor: Processor::new(),
+            breakpoints: Vec::new(),
+            finished: false,
+        };
+        debugger.restart();
+        debugger
+    }
+
+    pub fn from_source(input: &str, memory: Vec<u8>) -> Result<Debugger, ParseProgramError> {
+        let (program, labels) = parse_program_with_labels(input)?;
+        Ok(Debugger::new(program, labels, memory))
+    }
+
+    /// Start again with a fresh processor and the memory the debugger was
+    /// created with, keeping the breakpoints.
+    pub fn restart(&mut self) {
+        self.memory = self.initial_memory.clone();
+        self.processor = Processor::new();
+        // a budget of 0 only sets up the frame of the main function
+        self.finished =
+            self.program
+                .interpret_with_budget(&mut self.memory, &mut self.processor, 0)
+                == RunStatus::Finished;
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    pub fn processor(&self) -> &Processor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut Processor {
+        &mut self.processor
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The function id and index of the instruction that executes next, or
+    /// None once the program is finished.
+    pub fn location(&self) -> Option<(u16, usize)> {
+        self.processor
+            .call_stack()
+            .last()
+            .map(|frame| (frame.function, frame.pc))
+    }
+
+    /// Resolve `<function>:<index>`, `<function>:<label>` or `<function>` to
+    /// a function id and instruction index.
+    pub fn resolve(&self, location: &str) -> Result<(u16, usize), String> {
+        let (name, position) = location.split_once(':').unwrap_or((location, "0"));
+        let id = self
+            .program
+            .functions()
+            .iter()
+            .position(|function| function.get_name() == name)
+            .ok_or_else(|| format!("unknown function {}", name))?;
+        let instructions = self.program.functions()[id].get_instructions();
+        let pc = match position.parse::<usize>() {
+            Ok(pc) => pc,
+            Err(_) => self.label_index(id, position)?,
+        };
+        if pc >= instructions.len() {
+            return Err(format!("{} has {} instructions", name, instructions.len()));
+        }
+        Ok((id as u16, pc))
+    }
+
+    fn label_index(&self, id: usize, label: &str) -> Result<usize, String> {
+        // the assembler resolves a label declared twice to the last target
+        let identifier = self
+            .labels
+            .get(id)
+            .and_then(|labels| labels.iter().rposition(|name| name == label))
+            .ok_or_else(|| format!("unknown label {}", label))?;
+        self.program.functions()[id]
+            .get_instructions()
+            .iter()
+            .position(|instruction| match instruction {
+                Instruction::BranchTarget(BranchTarget {
+                    identifier: target, ..
+                }) => *target as usize == identifier,
+                _ => false,
+            })
+            .ok_or_else(|| format!("unknown label {}", label))
+    }
+
+    pub fn add_breakpoint(&mut self, function: u16, pc: usize) {
+        if !self.breakpoints.contains(&(function, pc)) {
+            self.breakpoints.push((function, pc));
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, function: u16, pc: usize) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints
+            .retain(|breakpoint| *breakpoint != (function, pc));
+        self.breakpoints.len() != len
+    }
+
+    pub fn breakpoints(&self) -> &[(u16, usize)] {
+        &self.breakpoints
+    }
+
+    /// Execute a single instruction.
+    pub fn step(&mut self) -> RunStatus {
+        if self.finished {
+            return RunStatus::Finished;
+        }
+        let status = self
+            .program
+            .interpret_with_budget(&mut self.memory, &mut self.processor, 1);
+        self.finished = status == RunStatus::Finished;
+        status
+    }
+
+    /// Execute until the next instruction has a breakpoint, or the program
+    /// is finished.
+    pub fn cont(&mut self) -> RunStatus {
+        loop {
+            if self.step() == RunStatus::Finished {
+                return RunStatus::Finished;
+            }
+            if let Some(location) = self.location() {
+                if self.breakpoints.contains(&location) {
+                    return RunStatus::Paused;
+                }
+            }
+        }
+    }
+
+    /// Execute a line of input, returning what to print.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return Ok(String::new()),
+        };
+        match (command, arguments) {
+            ("help" | "h", []) => Ok(HELP.to_string()),
+            ("break" | "b", [location]) => {
+                let (function, pc) = self.resolve(location)?;
+                self.add_breakpoint(function, pc);
+                Ok(format!("breakpoint at {}", self.describe(function, pc)))
+            }
+            ("delete" | "d", [location]) => {
+                let (function, pc) = self.resolve(location)?;
+                if self.remove_breakpoint(function, pc) {
+                    Ok(format!(
+                        "deleted breakpoint at {}",
+                        self.describe(function, pc)
+                    ))
+                } else {
+                    Err(format!("no breakpoint at {}", self.describe(function, pc)))
+                }
+            }
+            ("breakpoints", []) => Ok(self
+                .breakpoints
+                .iter()
+                .map(|(function, pc)| self.describe(*function, *pc))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            ("step" | "s", []) => {
+                self.step();
+                Ok(self.current())
+            }
+            ("step" | "s", [amount]) => {
+                let amount = parse_number::<usize>(amount)?;
+                for _ in 0..amount {
+                    if self.step() == RunStatus::Finished {
+                        break;
+                    }
+                }
+                Ok(self.current())
+            }
+            ("continue" | "c", []) => {
+                self.cont();
+                Ok(self.current())
+            }
+            ("regs" | "r", []) => Ok(self.registers()),
+            ("set", [register, value]) => {
+                let register = register
+                    .strip_prefix('r')
+                    .and_then(|register| register.parse::<usize>().ok())
+                    .filter(|register| *register < 32)
+                    .ok_or_else(|| format!("invalid register {}", register))?;
+                self.processor.registers_mut()[register] = parse_number::<i16>(value)?;
+                Ok(String::new())
+            }
+            ("mem" | "x", [start, len]) => {
+                let start = parse_number::<usize>(start)?;
+                let len = parse_number::<usize>(len)?;
+                self.dump(start, len)
+            }
+            ("write" | "w", [start, bytes @ ..]) if !bytes.is_empty() => {
+                let start = parse_number::<usize>(start)?;
+                let bytes = bytes
+                    .iter()
+                    .map(|byte| parse_number::<u8>(byte))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let len = self.memory.len();
+                let range = self
+                    .memory
+                    .get_mut(start..start.saturating_add(bytes.len()))
+                    .ok_or_else(|| format!("memory is {} bytes", len))?;
+                range.copy_from_slice(&bytes);
+                Ok(String::new())
+            }
+            ("stack" | "bt", []) => Ok(self.stack()),
+            ("list" | "l", []) => match self.location() {
+                Some((function, _)) => Ok(self.list(function)),
+                None => Ok(self.list(0)),
+            },
+            ("list" | "l", [function]) => {
+                let (function, _) = self.resolve(function)?;
+                Ok(self.list(function))
+            }
+            ("restart", []) => {
+                self.restart();
+                Ok(self.current())
+            }
+            _ => Err(format!("invalid command {}, try help", line.trim())),
+        }
+    }
+
+    fn describe(&self, function: u16, pc: usize) -> String {
+        format!("{}:{}", self.program.get_function(function).get_name(), pc)
+    }
+
+    // the instruction that executes next
+    fn current(&self) -> String {
+        match self.location() {
+            Some((function, pc)) => {
+                let instruction = &self.program.get_function(function).get_instructions()[pc];
+                format!(
+                    "{}  {}",
+                    self.describe(function, pc),
+                    disassemble(std::slice::from_ref(instruction))
+                )
+            }
+            None => "program finished".to_string(),
+        }
+    }
+
+    fn registers(&self) -> String {
+        self.processor
+            .registers()
+            .chunks(8)
+            .enumerate()
+            .map(|(row, registers)| {
+                registers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| format!("r{:<2} {:>6}", row * 8 + i, value))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn dump(&self, start: usize, len: usize) -> Result<String, String> {
+        let bytes = self
+            .memory
+            .get(start..start.saturating_add(len))
+            .ok_or_else(|| format!("memory is {} bytes", self.memory.len()))?;
+        let mut output = String::new();
+        for (row, bytes) in bytes.chunks(16).enumerate() {
+            write!(output, "{:04x}:", start + row * 16).unwrap();
+            for byte in bytes {
+                write!(output, " {:02x}", byte).unwrap();
+            }
+            output.push('\n');
+        }
+        output.pop();
+        Ok(output)
+    }
+
+    // the innermost function first, with callers at their call instruction
+    fn stack(&self) -> String {
+        let call_stack = self.processor.call_stack();
+        if call_stack.is_empty() {
+            return "program finished".to_string();
+        }
+        call_stack
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, frame)| {
+                let pc = if i + 1 == call_stack.len() {
+                    frame.pc
+                } else {
+                    frame.pc - 1
+                };
+                let function = self.program.get_function(frame.function);
+                let mut line = format!(
+                    "#{} {}",
+                    call_stack.len() - 1 - i,
+                    self.describe(frame.function, pc)
+                );
+                if function.get_repeat() > 1 {
+                    write!(
+                        line,
+                        " iteration {}/{}",
+                        frame.iteration + 1,
+                        function.get_repeat()
+                    )
+                    .unwrap();
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn list(&self, function: u16) -> String {
+        let location = self.location();
+        self.program
+            .get_function(function)
+            .get_instructions()
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| {
+                let current = if location == Some((function, pc)) {
+                    '>'
+                } else {
+                    ' '
+                };
+                let breakpoint = if self.breakpoints.contains(&(function, pc)) {
+                    '*'
+                } else {
+                    ' '
+                };
+                format!(
+                    "{}{} {:>3}  {}",
+                    current,
+                    breakpoint,
+                    pc,
+                    disassemble(std::slice::from_ref(instruction))
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(input: &str) -> Result<T, String> {
+    input
+        .parse()
+        .map_err(|_| format!("invalid number {}", input))
+}
@@ -1,27 +1,63 @@
+use crate::function::Function;
 use crate::lang::Instruction;
+use crate::program::Program;
+
+/// How the disassembler writes instructions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisassemblyOptions {
+    /// Write immediates, offsets and ecall numbers in hexadecimal, with a
+    /// minus sign for negative immediates.
+    pub hex: bool,
+}
+
+impl DisassemblyOptions {
+    fn number(&self, value: impl Into<i64>) -> String {
+        let value = value.into();
+        match (self.hex, value < 0) {
+            (false, _) => value.to_string(),
+            (true, false) => format!("{:#x}", value),
+            (true, true) => format!("-{:#x}", value.unsigned_abs()),
+        }
+    }
+}
 
 trait Disassembler {
-    fn disassemble(&self) -> String;
+    fn disassemble(&self, options: &DisassemblyOptions) -> String;
 }
 
 impl Disassembler for Instruction {
-    fn disassemble(&self) -> String {
+    fn disassemble(&self, options: &DisassemblyOptions) -> String {
         use Instruction::*;
         let opcode = self.opcode_str().to_lowercase();
         match self {
             Immediate(immediate) => format!(
                 "r{} = {} r{} {}",
-                immediate.rd, opcode, immediate.rs, immediate.value
+                immediate.rd,
+                opcode,
+                immediate.rs,
+                options.number(immediate.value)
             ),
             Register(register) => format!(
                 "r{} = {} r{} r{}",
                 register.rd, opcode, register.rs1, register.rs2
             ),
             Load(load) => {
-                format!("r{} = {} r{} {}", load.rd, opcode, load.rs, load.offset)
+                format!(
+                    "r{} = {} r{} {}",
+                    load.rd,
+                    opcode,
+                    load.rs,
+                    options.number(load.offset)
+                )
             }
             Store(store) => {
-                format!("{} r{} {} = r{}", opcode, store.rd, store.offset, store.rs)
+                format!(
+                    "{} r{} {} = r{}",
+                    opcode,
+                    store.rd,
+                    options.number(store.offset),
+                    store.rs
+                )
             }
             Branch(branch) => {
                 format!(
@@ -33,6 +69,8 @@ impl Disassembler for Instruction {
                 format!("{} t{}", opcode, branch_target.identifier)
             }
             CallId(call_id) => format!("{} f{}", opcode, call_id.identifier),
+            Ecall(ecall) => format!("{} {}", opcode, options.number(ecall.number)),
+            Random(random) => format!("r{} = {}", random.rd, opcode),
             // Switch(switch) => format!(
             //     "{} r{} f{} {}",
             //     opcode, switch.rs, switch.identifier, switch.amount
@@ -42,9 +80,59 @@ impl Disassembler for Instruction {
 }
 
 pub fn disassemble(instructions: &[Instruction]) -> String {
+    disassemble_with_options(instructions, &DisassemblyOptions::default())
+}
+
+pub fn disassemble_with_options(
+    instructions: &[Instruction],
+    options: &DisassemblyOptions,
+) -> String {
     instructions
         .iter()
-        .map(|instruction| instruction.disassemble())
+        .map(|instruction| instruction.disassemble(options))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Disassemble a whole program into source the assembler accepts, naming
+/// function `id` as `f{id}` to match the calls. Branches to targets and calls
+/// to functions that don't exist do nothing, and become comments.
+pub fn disassemble_program(program: &Program) -> String {
+    disassemble_program_with_options(program, &DisassemblyOptions::default())
+}
+
+pub fn disassemble_program_with_options(program: &Program, options: &DisassemblyOptions) -> String {
+    let functions = program.functions();
+    functions
+        .iter()
+        .enumerate()
+        .map(|(id, function)| {
+            let targets = Function::targets(function.get_instructions());
+            let header = if function.get_repeat() > 1 {
+                format!("repeat f{} {} {{", id, function.get_repeat())
+            } else {
+                format!("func f{} {{", id)
+            };
+            let body: String = function
+                .get_instructions()
+                .iter()
+                .map(|instruction| {
+                    let exists = match instruction {
+                        Instruction::Branch(branch) => targets.contains_key(&branch.target),
+                        Instruction::CallId(call_id) => {
+                            (call_id.identifier as usize) < functions.len()
+                        }
+                        _ => true,
+                    };
+                    if exists {
+                        format!("    {}\n", instruction.disassemble(options))
+                    } else {
+                        format!("    # {}\n", instruction.disassemble(options))
+                    }
+                })
+                .collect();
+            format!("{}\n{}}}\n", header, body)
+        })
         .collect::<Vec<_>>()
         .join("\n")
 }
@@ -75,4 +163,26 @@ mod tests {
         let disassembled = disassemble(&instructions);
         assert_eq!(disassembled, "r0 = add r1 r2\nr0 = addi r1 10");
     }
+
+    #[test]
+    fn test_disassemble_hex() {
+        let instructions = vec![
+            Instruction::Immediate(Immediate {
+                opcode: ImmediateOpcode::Addi,
+                rd: 0,
+                rs: 1,
+                value: 255,
+            }),
+            Instruction::Immediate(Immediate {
+                opcode: ImmediateOpcode::Addi,
+                rd: 0,
+                rs: 1,
+                value: i16::MIN,
+            }),
+        ];
+
+        let disassembled =
+            disassemble_with_options(&instructions, &DisassemblyOptions { hex: true });
+        assert_eq!(disassembled, "r0 = addi r1 0xff\nr0 = addi r1 -0x8000");
+    }
 }
@@ -0,0 +1,115 @@
+use crate::lang::{next_random, Branch, BranchTarget, Instruction};
+use crate::program::Program;
+use rustc_hash::FxHashMap;
+
+/// What `check_equivalence` found out about two programs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Equivalence {
+    /// The programs are the same after simplification, so they're equivalent
+    /// for memories of any length.
+    Structural,
+    /// The programs transform every memory of the length checked in the same
+    /// way, as there were few enough of them to try all.
+    Exhaustive,
+    /// The programs transform this many random memories in the same way, so
+    /// they're likely equivalent.
+    Tested(usize),
+    /// The programs transform this memory differently.
+    Different(Vec<u8>),
+}
+
+impl Equivalence {
+    pub fn is_different(&self) -> bool {
+        matches!(self, Equivalence::Different(_))
+    }
+}
+
+/// Check whether two programs transform memories of `memory_len` bytes in the
+/// same way. As for `Program::simplify`, the registers a run ends with don't
+/// count. Unless the programs are the same after simplification, they're run
+/// on up to `samples` memories: every possible one if there are that few, and
+/// otherwise memories of all zero bytes, all 255 bytes and random bytes drawn
+/// from seed.
+pub fn check_equivalence(
+    a: &Program,
+    b: &Program,
+    memory_len: usize,
+    samples: usize,
+    seed: u32,
+) -> Equivalence {
+    if normalize(a) == normalize(b) {
+        return Equivalence::Structural;
+    }
+    let differ = |memory: &[u8]| {
+        let mut memory_a = memory.to_vec();
+        a.interpret(&mut memory_a);
+        let mut memory_b = memory.to_vec();
+        b.interpret(&mut memory_b);
+        memory_a != memory_b
+    };
+
+    let exhaustive = 256usize
+        .checked_pow(memory_len as u32)
+        .filter(|memories| *memories <= samples);
+    if let Some(memories) = exhaustive {
+        return (0..memories)
+            .map(|index| {
+                (0..memory_len)
+                    .map(|byte| (index >> (byte * 8)) as u8)
+                    .collect::<Vec<u8>>()
+            })
+            .find(|memory| differ(memory))
+            .map(Equivalence::Different)
+            .unwrap_or(Equivalence::Exhaustive);
+    }
+
+    let mut state = seed;
+    let memories = [vec![0; memory_len], vec![255; memory_len]]
+        .into_iter()
+        .chain(std::iter::repeat_with(|| {
+            (0..memory_len)
+                .map(|_| next_random(&mut state) as u8)
+                .collect()
+        }))
+        .take(samples);
+    for memory in memories {
+        if differ(&memory) {
+            return Equivalence::Different(memory);
+        }
+    }
+    Equivalence::Tested(samples)
+}
+
+// the simplified functions, with only what matters to a run: their repeat and
+// their instructions, with target identifiers numbered in the order they're
+// first mentioned
+fn normalize(program: &Program) -> Vec<(u8, Vec<Instruction>)> {
+    let (simplified, _) = program.simplify();
+    simplified
+        .functions()
+        .iter()
+        .map(|function| {
+            let mut targets: FxHashMap<u8, u8> = FxHashMap::default();
+            let mut renumber = |identifier: u8| {
+                let next = targets.len() as u8;
+                *targets.entry(identifier).or_insert(next)
+            };
+            let instructions = function
+                .get_instructions()
+                .iter()
+                .map(|instruction| match instruction {
+                    Instruction::Branch(branch) => Instruction::Branch(Branch {
+                        target: renumber(branch.target),
+                        ..branch.clone()
+                    }),
+                    Instruction::BranchTarget(target) => Instruction::BranchTarget(BranchTarget {
+                        identifier: renumber(target.identifier),
+                        ..target.clone()
+                    }),
+                    instruction => instruction.clone(),
+                })
+                .collect();
+            (function.get_repeat(), instructions)
+        })
+        .collect()
+}
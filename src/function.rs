@@ -1,8 +1,12 @@
+#[cfg(feature = "llvm")]
+use crate::lang::HostFunc;
 use crate::lang::Instruction;
 use crate::lang::{BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode, Processor};
-use crate::llvm::CodeGen;
-use crate::llvm::ProgramFunc;
+#[cfg(feature = "llvm")]
+use crate::llvm::{CodeGen, Counters, ProgramFunc};
+#[cfg(feature = "llvm")]
 use inkwell::execution_engine::JitFunction;
+#[cfg(feature = "llvm")]
 use inkwell::values::FunctionValue;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
@@ -31,11 +35,12 @@ impl Function {
         }
     }
 
+    #[cfg(feature = "llvm")]
     pub fn compile<'ctx>(
         &self,
         id: usize,
         codegen: &'ctx CodeGen,
-        memory_len: u16,
+        memory_len: u32,
         functions: &FxHashMap<u16, FunctionValue<'ctx>>,
     ) -> FunctionValue<'ctx> {
         codegen.compile_function(
@@ -47,10 +52,11 @@ impl Function {
         )
     }
 
+    #[cfg(feature = "llvm")]
     pub fn compile_as_program<'ctx>(
         &self,
         codegen: &'ctx CodeGen,
-        memory_len: u16,
+        memory_len: u32,
     ) -> JitFunction<'ctx, ProgramFunc> {
         let inner_function = self.compile(0, codegen, memory_len, &FxHashMap::default());
         let mut functions = FxHashMap::default();
@@ -62,12 +68,131 @@ impl Function {
         llvm_program
     }
 
+    #[cfg(feature = "llvm")]
     pub fn run(func: &JitFunction<ProgramFunc>, memory: &mut [u8]) {
+        Function::run_with_registers(func, memory, &mut [0; 32]);
+    }
+
+    #[cfg(feature = "llvm")]
+    pub fn run_with_registers(
+        func: &JitFunction<ProgramFunc>,
+        memory: &mut [u8],
+        registers: &mut [i16; 32],
+    ) {
+        Function::run_with_state(func, memory, registers, &mut 0, &[]);
+    }
+
+    /// Run a compiled program with the given state of the random number
+    /// generator, where `ecall n` calls `host_functions[n]`.
+    #[cfg(feature = "llvm")]
+    pub fn run_with_state(
+        func: &JitFunction<ProgramFunc>,
+        memory: &mut [u8],
+        registers: &mut [i16; 32],
+        rng_state: &mut u32,
+        host_functions: &[Option<HostFunc>],
+    ) {
+        Function::call_compiled(
+            func,
+            memory,
+            registers,
+            rng_state,
+            0,
+            host_functions,
+            &mut Counters::default(),
+        );
+    }
+
+    /// Run a compiled program with the registers, the random number generator
+    /// state and the base address of the processor, where `ecall n` calls
+    /// `host_functions[n]`. Memory protection violations are counted in the
+    /// processor, as are memory accesses if it has `profile_memory` set and
+    /// what's executed if it has `profile_execution` set. Instructions are
+    /// only counted for the functions the execution profile was fitted to.
+    ///
+    /// The machine options and memory protection are those the program was
+    /// compiled with, not those of the processor.
+    #[cfg(feature = "llvm")]
+    pub fn run_with_processor(
+        func: &JitFunction<ProgramFunc>,
+        memory: &mut [u8],
+        processor: &mut Processor,
+        host_functions: &[Option<HostFunc>],
+    ) {
+        let mut rng_state = processor.rng_state();
+        let mut registers = *processor.registers();
+        let mut counters = Counters::default();
+        processor.start_run(memory.len());
+        let base = processor.window_base() as u32;
+        if processor.options().profile_memory {
+            (counters.memory_reads, counters.memory_writes) =
+                processor.memory_profile_mut().buffers();
+        }
+        if processor.options().profile_execution {
+            let buffers = processor.execution_profile_mut().buffers();
+            counters.opcodes = buffers.opcodes;
+            counters.functions = buffers.functions;
+            counters.offsets = buffers.offsets;
+            counters.instructions = buffers.instructions;
+            counters.taken = buffers.taken;
+        }
+        Function::call_compiled(
+            func,
+            memory,
+            &mut registers,
+            &mut rng_state,
+            base,
+            host_functions,
+            &mut counters,
+        );
+        *processor.registers_mut() = registers;
+        processor.set_rng_state(rng_state);
+        *processor.violations_mut() = counters.violations;
+    }
+
+    #[cfg(feature = "llvm")]
+    fn call_compiled(
+        func: &JitFunction<ProgramFunc>,
+        memory: &mut [u8],
+        registers: &mut [i16; 32],
+        rng_state: &mut u32,
+        base: u32,
+        host_functions: &[Option<HostFunc>],
+        counters: &mut Counters,
+    ) {
         unsafe {
-            func.call(memory.as_mut_ptr());
+            func.call(
+                memory.as_mut_ptr(),
+                registers.as_mut_ptr(),
+                rng_state,
+                host_functions.as_ptr(),
+                host_functions.len() as u32,
+                base,
+                counters,
+            );
         }
     }
 
+    // the same function with other instructions, which are used as they are
+    pub(crate) fn with_instructions(&self, instructions: Vec<Instruction>) -> Function {
+        Function {
+            name: self.name.clone(),
+            instructions,
+            repeat: self.repeat,
+        }
+    }
+
+    pub(crate) fn with_repeat(&self, repeat: u8) -> Function {
+        Function {
+            repeat,
+            ..self.clone()
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
     pub fn get_instructions(&self) -> &[Instruction] {
         &self.instructions
     }
@@ -162,7 +287,7 @@ impl Function {
         }
     }
 
-    fn targets(instructions: &[Instruction]) -> FxHashMap<u8, usize> {
+    pub(crate) fn targets(instructions: &[Instruction]) -> FxHashMap<u8, usize> {
         let mut targets = FxHashMap::default();
         for (index, instruction) in instructions.iter().enumerate() {
             if let Instruction::BranchTarget(BranchTarget {
@@ -1,6 +1,7 @@
 use std::ops::Rem;
 
 use crate::function::Function;
+use crate::profile::{ExecutionProfile, MemoryProfile};
 use byteorder::{ByteOrder, LittleEndian};
 use rustc_hash::FxHashMap;
 use strum::EnumCount;
@@ -156,7 +157,45 @@ pub enum CallIdOpcode {
     Call = CALL_OPCODE_START as isize,
 }
 
-// const SWITCH_OPCODE_START: usize = CALL_OPCODE_START + CallIdOpcode::COUNT;
+const ECALL_OPCODE_START: usize = CALL_OPCODE_START + CallIdOpcode::COUNT;
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    Hash,
+    Display,
+    EnumIter,
+    EnumCountMacro,
+    FromPrimitive,
+    ToPrimitive,
+)]
+pub enum EcallOpcode {
+    Ecall = ECALL_OPCODE_START as isize,
+}
+
+const RANDOM_OPCODE_START: usize = ECALL_OPCODE_START + EcallOpcode::COUNT;
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    Hash,
+    Display,
+    EnumIter,
+    EnumCountMacro,
+    FromPrimitive,
+    ToPrimitive,
+)]
+pub enum RandomOpcode {
+    Rand = RANDOM_OPCODE_START as isize,
+}
+
+pub(crate) const OPCODE_COUNT: usize = RANDOM_OPCODE_START + RandomOpcode::COUNT;
+
+// const SWITCH_OPCODE_START: usize = RANDOM_OPCODE_START + RandomOpcode::COUNT;
 // #[derive(
 //     Debug,
 //     PartialEq,
@@ -226,6 +265,18 @@ pub struct CallId {
     pub identifier: u16,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Ecall {
+    pub opcode: EcallOpcode,
+    pub number: u16,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Random {
+    pub opcode: RandomOpcode,
+    pub rd: u8,
+}
+
 // #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 // pub struct Switch {
 //     pub opcode: SwitchOpcode,
@@ -243,27 +294,503 @@ pub enum Instruction {
     Branch(Branch),
     BranchTarget(BranchTarget),
     CallId(CallId),
+    Ecall(Ecall),
+    Random(Random),
     // Switch(Switch),
 }
 
+/// Configuration of the machine, honoured by the interpreter as well as the
+/// code generators.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MachineOptions {
+    /// Make r0 always zero, ignoring writes to it, as in RISC-V.
+    pub zero_register: bool,
+    /// Address memory through a window of this many bytes that starts at the
+    /// base address of the processor, so that address 0 is the base. Accesses
+    /// inside the window wrap around the end of memory, and accesses outside
+    /// of it are ignored like out of bounds accesses otherwise are.
+    pub window_size: Option<u16>,
+    /// Count the loads and stores that memory protection forbids, see
+    /// `Processor::violations`.
+    pub count_violations: bool,
+    /// Record the reads and writes of each address, see
+    /// `Processor::memory_profile`.
+    pub profile_memory: bool,
+    /// Record how often opcodes and instructions are executed and branches
+    /// are taken, see `Processor::execution_profile`.
+    pub profile_execution: bool,
+}
+
+/// What a program may do with a region of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Permissions {
+    pub const READ_WRITE: Permissions = Permissions {
+        read: true,
+        write: true,
+    };
+    pub const READ_ONLY: Permissions = Permissions {
+        read: true,
+        write: false,
+    };
+    pub const NONE: Permissions = Permissions {
+        read: false,
+        write: false,
+    };
+}
+
+/// The memory indexes `start..end` with their permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub permissions: Permissions,
+}
+
+/// Regions of memory that programs may not read or write. Memory outside of
+/// all regions can be read and written, and where regions overlap both their
+/// restrictions apply. Regions are in memory indexes, so they don't move with
+/// the base of a window.
+///
+/// A load or store is forbidden if any byte it touches is, in which case a
+/// load gives 0 and a store is dropped, as happens for out of bounds accesses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryProtection {
+    regions: Vec<Region>,
+}
+
+impl MemoryProtection {
+    pub fn new() -> MemoryProtection {
+        MemoryProtection::default()
+    }
+
+    pub fn add_region(&mut self, start: usize, end: usize, permissions: Permissions) {
+        self.regions.push(Region {
+            start,
+            end,
+            permissions,
+        });
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn can_read(&self, index: usize) -> bool {
+        self.regions
+            .iter()
+            .all(|region| region.permissions.read || !(region.start..region.end).contains(&index))
+    }
+
+    pub fn can_write(&self, index: usize) -> bool {
+        self.regions
+            .iter()
+            .all(|region| region.permissions.write || !(region.start..region.end).contains(&index))
+    }
+
+    // the regions that forbid loads, or stores if write is set
+    #[cfg(any(feature = "llvm", feature = "cranelift"))]
+    pub(crate) fn forbidding(&self, write: bool) -> impl Iterator<Item = &Region> {
+        self.regions.iter().filter(move |region| {
+            if write {
+                !region.permissions.write
+            } else {
+                !region.permissions.read
+            }
+        })
+    }
+}
+
+pub(crate) const RNG_MULTIPLIER: u32 = 1664525;
+pub(crate) const RNG_INCREMENT: u32 = 1013904223;
+
+/// Advance the state of the pseudo-random number generator used by `rand`,
+/// and return the next number. This is a 32 bit linear congruential generator
+/// of which we use the high half; all backends implement exactly this, so
+/// that runs with the same seed are reproducible.
+pub fn next_random(state: &mut u32) -> i16 {
+    *state = state
+        .wrapping_mul(RNG_MULTIPLIER)
+        .wrapping_add(RNG_INCREMENT);
+    (*state >> 16) as i16
+}
+
+/// A position in a function that's being interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub function: u16,
+    pub pc: usize,
+    pub iteration: u8,
+}
+
+/// A snapshot of a `Processor`, which can be stored and restored later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessorState {
+    pub registers: [i16; 32],
+    /// The state of the pseudo-random number generator.
+    pub rng: u32,
+    /// The base address of the window.
+    pub base: u64,
+    /// The frames of a paused run, with the innermost function last. This is
+    /// empty if no run is paused.
+    pub frames: Vec<Frame>,
+}
+
+impl ProcessorState {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        for register in self.registers {
+            output.extend_from_slice(&register.to_le_bytes());
+        }
+        output.extend_from_slice(&self.rng.to_le_bytes());
+        output.extend_from_slice(&self.base.to_le_bytes());
+        output.extend_from_slice(&(self.frames.len() as u16).to_le_bytes());
+        for frame in &self.frames {
+            output.extend_from_slice(&frame.function.to_le_bytes());
+            output.extend_from_slice(&(frame.pc as u32).to_le_bytes());
+            output.push(frame.iteration);
+        }
+        output
+    }
+
+    pub fn deserialize(input: &[u8]) -> Option<ProcessorState> {
+        const FRAME_SIZE: usize = 7;
+        if input.len() < 78 {
+            return None;
+        }
+        let mut registers = [0; 32];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = LittleEndian::read_i16(&input[i * 2..]);
+        }
+        let rng = LittleEndian::read_u32(&input[64..]);
+        let base = LittleEndian::read_u64(&input[68..]);
+        let amount = LittleEndian::read_u16(&input[76..]) as usize;
+        let input = &input[78..];
+        if input.len() != amount * FRAME_SIZE {
+            return None;
+        }
+        let frames = input
+            .chunks(FRAME_SIZE)
+            .map(|chunk| Frame {
+                function: LittleEndian::read_u16(chunk),
+                pc: LittleEndian::read_u32(&chunk[2..]) as usize,
+                iteration: chunk[6],
+            })
+            .collect();
+        Some(ProcessorState {
+            registers,
+            rng,
+            base,
+            frames,
+        })
+    }
+}
+
+/// A function provided by the embedder, which a program invokes with
+/// `ecall`.
+pub trait HostFunction {
+    fn call(&mut self, registers: &mut [i16; 32], memory: &mut [u8]);
+}
+
+impl<F: FnMut(&mut [i16; 32], &mut [u8])> HostFunction for F {
+    fn call(&mut self, registers: &mut [i16; 32], memory: &mut [u8]) {
+        self(registers, memory)
+    }
+}
+
+/// A host function that compiled code calls for `ecall`, which gets the
+/// registers, the memory and the length of the memory.
+pub type HostFunc = unsafe extern "C" fn(*mut i16, *mut u8, u32);
+
+/// The host functions available to `ecall`, by number. Calling a number
+/// without a host function is a no-op.
+#[derive(Default)]
+pub struct HostFunctions {
+    functions: FxHashMap<u16, Box<dyn HostFunction>>,
+}
+
+impl HostFunctions {
+    pub fn new() -> HostFunctions {
+        HostFunctions::default()
+    }
+
+    pub fn register(&mut self, number: u16, function: impl HostFunction + 'static) {
+        self.functions.insert(number, Box::new(function));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    pub fn call(&mut self, number: u16, registers: &mut [i16; 32], memory: &mut [u8]) {
+        if let Some(function) = self.functions.get_mut(&number) {
+            function.call(registers, memory);
+        }
+    }
+}
+
+impl std::fmt::Debug for HostFunctions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut numbers: Vec<_> = self.functions.keys().collect();
+        numbers.sort();
+        f.debug_struct("HostFunctions")
+            .field("numbers", &numbers)
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct Processor {
     registers: [i16; 32],
     pc: usize,
     jumped: bool,
-    call_stack: Vec<usize>,
+    call_stack: Vec<Frame>,
+    options: MachineOptions,
+    host_functions: HostFunctions,
+    rng: u32,
+    base: usize,
+    // the base within the memory of the current run
+    window_base: usize,
+    protection: MemoryProtection,
+    violations: u64,
+    memory_profile: MemoryProfile,
+    execution_profile: ExecutionProfile,
+    // the id of the function being interpreted
+    function: u16,
 }
 
 impl Processor {
     pub fn new() -> Processor {
+        Processor::with_options(MachineOptions::default())
+    }
+
+    pub fn with_options(options: MachineOptions) -> Processor {
         Processor {
             registers: [0; 32],
             pc: 0,
             jumped: false,
             call_stack: Vec::new(),
+            options,
+            host_functions: HostFunctions::new(),
+            rng: 0,
+            base: 0,
+            window_base: 0,
+            protection: MemoryProtection::new(),
+            violations: 0,
+            memory_profile: MemoryProfile::default(),
+            execution_profile: ExecutionProfile::new(),
+            function: 0,
+        }
+    }
+
+    pub fn with_registers(registers: [i16; 32]) -> Processor {
+        Processor {
+            registers,
+            ..Processor::new()
+        }
+    }
+
+    pub fn from_state(state: &ProcessorState) -> Processor {
+        let mut processor = Processor::new();
+        processor.restore(state);
+        processor
+    }
+
+    pub fn state(&self) -> ProcessorState {
+        ProcessorState {
+            registers: self.registers,
+            rng: self.rng,
+            base: self.base as u64,
+            frames: self.call_stack.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: &ProcessorState) {
+        self.registers = state.registers;
+        self.rng = state.rng;
+        self.base = state.base as usize;
+        self.call_stack = state.frames.clone();
+        self.pc = self.call_stack.last().map(|frame| frame.pc).unwrap_or(0);
+        self.jumped = false;
+    }
+
+    pub fn registers(&self) -> &[i16; 32] {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [i16; 32] {
+        &mut self.registers
+    }
+
+    /// The state of the pseudo-random number generator, which is 0 for a new
+    /// processor.
+    pub fn rng_state(&self) -> u32 {
+        self.rng
+    }
+
+    /// Seed the pseudo-random number generator.
+    pub fn set_rng_state(&mut self, state: u32) {
+        self.rng = state;
+    }
+
+    /// The address in memory where the window starts, if the machine has a
+    /// window.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn set_base(&mut self, base: usize) {
+        self.base = base;
+    }
+
+    pub fn protection(&self) -> &MemoryProtection {
+        &self.protection
+    }
+
+    pub fn set_protection(&mut self, protection: MemoryProtection) {
+        self.protection = protection;
+    }
+
+    /// The amount of loads and stores forbidden by memory protection in the
+    /// current or last run. Only counted if the machine has
+    /// `count_violations` set.
+    pub fn violations(&self) -> u64 {
+        self.violations
+    }
+
+    // get ready for a new run over a memory of memory_len bytes, which
+    // discards a paused run
+    pub(crate) fn start_run(&mut self, memory_len: usize) {
+        self.call_stack.clear();
+        self.fit_base(memory_len);
+        self.violations = 0;
+        if self.options.profile_memory {
+            self.memory_profile.grow(memory_len);
+        }
+    }
+
+    // take the base modulo memory_len, so that a base beyond the end of
+    // memory wraps around like the addresses in a window do
+    pub(crate) fn fit_base(&mut self, memory_len: usize) {
+        self.window_base = if memory_len == 0 {
+            0
+        } else {
+            self.base % memory_len
+        };
+    }
+
+    // where the window starts in the memory of the current run
+    #[cfg(any(feature = "llvm", feature = "cranelift"))]
+    pub(crate) fn window_base(&self) -> usize {
+        self.window_base
+    }
+
+    #[cfg(any(feature = "llvm", feature = "cranelift"))]
+    pub(crate) fn violations_mut(&mut self) -> &mut u64 {
+        &mut self.violations
+    }
+
+    /// The reads and writes of each address over all runs so far, if the
+    /// machine has `profile_memory` set.
+    pub fn memory_profile(&self) -> &MemoryProfile {
+        &self.memory_profile
+    }
+
+    /// Take the memory profile, starting a new one.
+    pub fn take_memory_profile(&mut self) -> MemoryProfile {
+        std::mem::take(&mut self.memory_profile)
+    }
+
+    /// What was executed over all runs so far, if the machine has
+    /// `profile_execution` set.
+    pub fn execution_profile(&self) -> &ExecutionProfile {
+        &self.execution_profile
+    }
+
+    pub fn execution_profile_mut(&mut self) -> &mut ExecutionProfile {
+        &mut self.execution_profile
+    }
+
+    /// Take the execution profile, starting a new one.
+    pub fn take_execution_profile(&mut self) -> ExecutionProfile {
+        std::mem::take(&mut self.execution_profile)
+    }
+
+    pub(crate) fn set_function(&mut self, function: u16) {
+        self.function = function;
+    }
+
+    pub(crate) fn profile_instruction(
+        &mut self,
+        function: u16,
+        pc: usize,
+        instruction: &Instruction,
+        taken: bool,
+    ) {
+        if self.options.profile_execution {
+            self.execution_profile
+                .record(function, pc, instruction.into(), taken);
         }
     }
 
+    #[cfg(feature = "llvm")]
+    pub(crate) fn memory_profile_mut(&mut self) -> &mut MemoryProfile {
+        &mut self.memory_profile
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The frames of a paused run, with the innermost function last.
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    pub fn is_paused(&self) -> bool {
+        !self.call_stack.is_empty()
+    }
+
+    pub fn options(&self) -> MachineOptions {
+        self.options
+    }
+
+    pub fn register_host_function(&mut self, number: u16, function: impl HostFunction + 'static) {
+        self.host_functions.register(number, function);
+    }
+
+    pub fn host_functions(&self) -> &HostFunctions {
+        &self.host_functions
+    }
+
+    pub fn host_functions_mut(&mut self) -> &mut HostFunctions {
+        &mut self.host_functions
+    }
+
+    // undo any write to r0 if it's hardwired to zero
+    pub(crate) fn clear_zero_register(&mut self) {
+        if self.options.zero_register {
+            self.registers[0] = 0;
+        }
+    }
+
+    pub(crate) fn call_stack_mut(&mut self) -> &mut Vec<Frame> {
+        &mut self.call_stack
+    }
+
+    pub(crate) fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    // returns whether the last instruction jumped, and resets it
+    pub(crate) fn take_jumped(&mut self) -> bool {
+        std::mem::replace(&mut self.jumped, false)
+    }
+
     pub fn execute(
         &mut self,
         instructions: &[Instruction],
@@ -272,9 +799,13 @@ impl Processor {
         functions: &[Function],
     ) {
         self.pc = 0;
+        self.clear_zero_register();
         while self.pc < instructions.len() {
             let instruction = &instructions[self.pc];
+            let pc = self.pc;
             instruction.execute(self, memory, targets, functions);
+            self.clear_zero_register();
+            self.profile_instruction(self.function, pc, instruction, self.jumped);
             if self.jumped {
                 self.jumped = false;
             } else {
@@ -338,7 +869,9 @@ impl Instruction {
                         processor.registers[rd as usize] = result;
                     }
                     Slli => {
-                        let result = if value < 16 {
+                        // the amount is unsigned, as it is for the compiled
+                        // backends and wasm's i32.shl
+                        let result = if (value as u16) < 16 {
                             processor.registers[rs as usize] << (value as u16)
                         } else {
                             processor.registers[rs as usize]
@@ -451,33 +984,24 @@ impl Instruction {
                 match load.opcode {
                     Lh => {
                         let address = address_h(processor, rs, offset);
-                        let result = if let Some(address) = address {
-                            if address < (memory.len() - 1) {
-                                LittleEndian::read_i16(&memory[address..])
-                            } else {
-                                0
-                            }
-                        } else {
-                            0
-                        };
+                        let result = address
+                            .and_then(|address| load_bytes(processor, memory, address))
+                            .map(i16::from_le_bytes)
+                            .unwrap_or(0);
                         processor.registers[rd as usize] = result;
                     }
                     Lb => {
                         let address = address_b(processor, rs, offset);
-                        let result = if address < memory.len() {
-                            memory[address]
-                        } else {
-                            0
-                        };
+                        let result = load_bytes(processor, memory, address)
+                            .map(|[byte]| byte)
+                            .unwrap_or(0);
                         processor.registers[rd as usize] = result as i8 as i16;
                     }
                     Lbu => {
                         let address = address_b(processor, rs, offset);
-                        let result = if address < memory.len() {
-                            memory[address]
-                        } else {
-                            0
-                        };
+                        let result = load_bytes(processor, memory, address)
+                            .map(|[byte]| byte)
+                            .unwrap_or(0);
                         processor.registers[rd as usize] = result as u16 as i16;
                     }
                 }
@@ -491,19 +1015,14 @@ impl Instruction {
                     Sh => {
                         let address = address_h(processor, rd, offset);
                         if let Some(address) = address {
-                            if address < (memory.len() - 1) {
-                                LittleEndian::write_i16(
-                                    &mut memory[address..],
-                                    processor.registers[rs as usize],
-                                );
-                            }
+                            let value = processor.registers[rs as usize].to_le_bytes();
+                            store_bytes(processor, memory, address, value);
                         }
                     }
                     Sb => {
                         let address = address_b(processor, rd, offset);
-                        if address < memory.len() {
-                            memory[address] = processor.registers[rs as usize] as u8;
-                        }
+                        let value = [processor.registers[rs as usize] as u8];
+                        store_bytes(processor, memory, address, value);
                     }
                 }
             }
@@ -594,10 +1113,32 @@ impl Instruction {
                     Call => {
                         let identifier = call_id.identifier as usize;
                         let function = &functions[identifier];
-                        processor.call_stack.push(processor.pc);
-                        processor.pc = 0;
+                        let pc = processor.pc;
+                        let caller = processor.function;
+                        processor.function = call_id.identifier;
                         function.interpret(memory, processor, functions);
-                        processor.pc = processor.call_stack.pop().unwrap();
+                        processor.function = caller;
+                        processor.pc = pc;
+                    }
+                }
+            }
+            Instruction::Ecall(ecall) => {
+                use EcallOpcode::*;
+                match ecall.opcode {
+                    Ecall => {
+                        processor.host_functions.call(
+                            ecall.number,
+                            &mut processor.registers,
+                            memory,
+                        );
+                    }
+                }
+            }
+            Instruction::Random(random) => {
+                use RandomOpcode::*;
+                match random.opcode {
+                    Rand => {
+                        processor.registers[random.rd as usize] = next_random(&mut processor.rng);
                     }
                 }
             } // Instruction::Switch(switch) => {
@@ -630,6 +1171,8 @@ impl Instruction {
             Branch(branch) => branch.opcode.to_string(),
             BranchTarget(target) => target.opcode.to_string(),
             CallId(call_id) => call_id.opcode.to_string(),
+            Ecall(ecall) => ecall.opcode.to_string(),
+            Random(random) => random.opcode.to_string(),
             // Switch(switch) => switch.opcode.to_string(),
         }
     }
@@ -647,3 +1190,87 @@ fn address_h(processor: &Processor, rs: u8, offset: u16) -> Option<usize> {
         .checked_mul(2)
         .map(|address| address as usize)
 }
+
+// the number of accesses of access_size bytes that fit in size bytes, which
+// compiled code checks the index against. like in address_h, the address of a
+// halfword has to fit in 16 bits
+pub(crate) fn index_bound(size: u32, access_size: u32) -> u32 {
+    (size / access_size).min(0x10000 / access_size)
+}
+
+// the memory indexes of the N bytes accessed at address, or None if the access
+// is out of bounds. in a window the bytes are relative to the base and may wrap
+// around the end of memory
+fn memory_indexes<const N: usize>(
+    processor: &Processor,
+    memory_len: usize,
+    address: usize,
+) -> Option<[usize; N]> {
+    let (base, size) = match processor.options.window_size {
+        Some(window_size) => (processor.window_base, window_size as usize),
+        None => (0, memory_len),
+    };
+    if memory_len == 0 || address + N > size {
+        return None;
+    }
+    Some(std::array::from_fn(|i| (base + address + i) % memory_len))
+}
+
+// the memory indexes of an access that's in bounds and permitted. a forbidden
+// access counts as a violation
+fn permitted_indexes<const N: usize>(
+    processor: &mut Processor,
+    memory_len: usize,
+    address: usize,
+    write: bool,
+) -> Option<[usize; N]> {
+    let indexes = memory_indexes::<N>(processor, memory_len, address)?;
+    let protection = &processor.protection;
+    let permitted = indexes.iter().all(|index| {
+        if write {
+            protection.can_write(*index)
+        } else {
+            protection.can_read(*index)
+        }
+    });
+    if !permitted {
+        if processor.options.count_violations {
+            processor.violations += 1;
+        }
+        return None;
+    }
+    if processor.options.profile_memory {
+        let profile = &mut processor.memory_profile;
+        profile.grow(memory_len);
+        for index in indexes {
+            if write {
+                profile.record_write(index);
+            } else {
+                profile.record_read(index);
+            }
+        }
+    }
+    Some(indexes)
+}
+
+fn load_bytes<const N: usize>(
+    processor: &mut Processor,
+    memory: &[u8],
+    address: usize,
+) -> Option<[u8; N]> {
+    permitted_indexes(processor, memory.len(), address, false)
+        .map(|indexes| indexes.map(|i| memory[i]))
+}
+
+fn store_bytes<const N: usize>(
+    processor: &mut Processor,
+    memory: &mut [u8],
+    address: usize,
+    bytes: [u8; N],
+) {
+    if let Some(indexes) = permitted_indexes::<N>(processor, memory.len(), address, true) {
+        for (index, byte) in indexes.into_iter().zip(bytes) {
+            memory[index] = byte;
+        }
+    }
+}
@@ -3,20 +3,61 @@ extern crate num;
 extern crate num_derive;
 
 mod assembler;
+#[cfg(feature = "llvm")]
 mod cache;
+mod cfg;
+mod cost;
+#[cfg(feature = "cranelift")]
+mod cranelift;
+mod csource;
+mod debugger;
 mod disassembler;
+mod equivalence;
 mod function;
 mod lang;
+#[cfg(feature = "llvm")]
 mod llvm;
+#[cfg(feature = "llvm")]
 mod llvmasm;
+mod minimize;
+mod profile;
 mod program;
 pub mod run;
 mod serializer;
+mod simplify;
+mod symbolic;
+mod wasm;
+mod world;
 
-pub use assembler::{parse, parse_program};
+pub use assembler::{
+    parse, parse_program, parse_program_with_labels, parse_program_with_labels_and_resolver,
+    parse_program_with_resolver, DirectoryResolver, FileResolver,
+};
+#[cfg(feature = "llvm")]
 pub use cache::FunctionValueCache;
-pub use disassembler::disassemble;
+pub use cfg::{BasicBlock, CallEdge, Cfg, Edge, EdgeKind, FunctionGraph};
+pub use cost::{CostAnalysis, CostBounds};
+#[cfg(feature = "cranelift")]
+pub use cranelift::{CraneliftCodeGen, CraneliftFunction};
+pub use csource::compile_c;
+pub use debugger::Debugger;
+pub use disassembler::{
+    disassemble, disassemble_program, disassemble_program_with_options, disassemble_with_options,
+    DisassemblyOptions,
+};
+pub use equivalence::{check_equivalence, Equivalence};
 pub use function::Function;
+pub use lang::{
+    next_random, Frame, HostFunc, HostFunction, HostFunctions, MachineOptions, MemoryProtection,
+    Permissions, Processor, ProcessorState, Region,
+};
+#[cfg(feature = "llvm")]
 pub use llvm::CodeGen;
-pub use program::Program;
+pub use minimize::{minimize_genome, minimize_program};
+pub use profile::{ExecutionProfile, MemoryProfile};
+pub use program::{Program, RunStatus};
 pub use serializer::Serializer;
+pub use simplify::SimplifyReport;
+pub use symbolic::SymbolicExecution;
+pub use wasm::compile_wasm;
+pub use world::{Agent, CompiledRun, World};
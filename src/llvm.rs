@@ -1,8 +1,10 @@
 use crate::cache::FunctionValueCache;
 use crate::function::Function;
 use crate::lang::{
-    Branch, BranchOpcode, BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode, Immediate,
-    ImmediateOpcode, Instruction, Load, LoadOpcode, Register, RegisterOpcode, Store, StoreOpcode,
+    index_bound, Branch, BranchOpcode, BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode,
+    Ecall, EcallOpcode, HostFunc, Immediate, ImmediateOpcode, Instruction, Load, LoadOpcode,
+    MachineOptions, MemoryProtection, Random, RandomOpcode, Register, RegisterOpcode, Store,
+    StoreOpcode, RNG_INCREMENT, RNG_MULTIPLIER,
 };
 use crate::llvmasm::save_asm;
 use crate::program::Program;
@@ -13,22 +15,88 @@ use inkwell::execution_engine::{ExecutionEngine, JitFunction};
 use inkwell::module::{Linkage, Module};
 use inkwell::passes::{PassManager, PassManagerBuilder};
 use inkwell::targets::{Target, TargetMachine, TargetTriple};
-use inkwell::types::FunctionType;
-use inkwell::values::{FunctionValue, IntValue, PointerValue};
+use inkwell::types::{FunctionType, StructType};
+use inkwell::values::{
+    BasicMetadataValueEnum, CallableValue, FunctionValue, IntValue, PointerValue,
+};
 use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
 use rustc_hash::FxHashMap;
 use std::error::Error;
 
-pub type ProgramFunc = unsafe extern "C" fn(*mut u8) -> ();
+/// A compiled program, which gets the memory, the registers, the state of the
+/// random number generator, a table of host functions with its length, the
+/// base address of the window and the counters. `ecall` with a number outside
+/// of the table or without a host function does nothing.
+pub type ProgramFunc = unsafe extern "C" fn(
+    *mut u8,
+    *mut i16,
+    *mut u32,
+    *const Option<HostFunc>,
+    u32,
+    u32,
+    *mut Counters,
+) -> ();
+
+/// What a compiled program counts, if it's compiled with the machine options
+/// for it. Buffers that are null aren't counted in, and others need room for
+/// every byte of memory.
+#[repr(C)]
+pub struct Counters {
+    pub(crate) violations: u64,
+    pub(crate) memory_reads: *mut u64,
+    pub(crate) memory_writes: *mut u64,
+    pub(crate) opcodes: *mut u64,
+    // the id of the running function
+    pub(crate) function: u64,
+    // the counts of the instructions of functions, laid out as in
+    // `ExecutionProfile`
+    pub(crate) functions: u64,
+    pub(crate) offsets: *const u64,
+    pub(crate) instructions: *mut u64,
+    pub(crate) taken: *mut u64,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Counters {
+            violations: 0,
+            memory_reads: std::ptr::null_mut(),
+            memory_writes: std::ptr::null_mut(),
+            opcodes: std::ptr::null_mut(),
+            function: 0,
+            functions: 0,
+            offsets: std::ptr::null(),
+            instructions: std::ptr::null_mut(),
+            taken: std::ptr::null_mut(),
+        }
+    }
+}
+
+// the fields of `Counters`
+const VIOLATIONS: u32 = 0;
+const MEMORY_READS: u32 = 1;
+const MEMORY_WRITES: u32 = 2;
+const OPCODES: u32 = 3;
+const FUNCTION: u32 = 4;
+const FUNCTIONS: u32 = 5;
+const OFFSETS: u32 = 6;
+const INSTRUCTIONS: u32 = 7;
+const TAKEN: u32 = 8;
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     pub module: Module<'ctx>,
     builder: Builder<'ctx>,
     execution_engine: ExecutionEngine<'ctx>,
+    options: MachineOptions,
+    protection: MemoryProtection,
 }
 
-struct Registers<'a>(Vec<PointerValue<'a>>);
+struct Registers<'a> {
+    registers: Vec<PointerValue<'a>>,
+    // writes to a hardwired zero register go here, and are never read
+    discard: Option<PointerValue<'a>>,
+}
 
 impl<'a> Registers<'a> {
     fn new(codegen: &CodeGen<'a>, function: FunctionValue<'a>) -> Self {
@@ -45,11 +113,28 @@ impl<'a> Registers<'a> {
             };
             registers.push(register_ptr);
         }
-        Registers(registers)
+        let discard = if codegen.options.zero_register {
+            Some(
+                codegen
+                    .builder
+                    .build_alloca(codegen.context.i16_type(), "discard"),
+            )
+        } else {
+            None
+        };
+        Registers { registers, discard }
     }
 
     fn get(&self, index: u8) -> PointerValue<'a> {
-        self.0[index as usize]
+        self.registers[index as usize]
+    }
+
+    // the register to write a result to
+    fn get_rd(&self, index: u8) -> PointerValue<'a> {
+        match self.discard {
+            Some(discard) if index == 0 => discard,
+            _ => self.get(index),
+        }
     }
 }
 
@@ -60,6 +145,10 @@ type StoreValue<'ctx> = fn(&Builder<'ctx>, &Context, PointerValue<'ctx>, IntValu
 
 impl<'ctx> CodeGen<'ctx> {
     pub fn new(context: &'ctx Context) -> CodeGen<'ctx> {
+        CodeGen::with_options(context, MachineOptions::default())
+    }
+
+    pub fn with_options(context: &'ctx Context, options: MachineOptions) -> CodeGen<'ctx> {
         let module = context.create_module("program");
 
         let execution_engine = module
@@ -81,50 +170,39 @@ impl<'ctx> CodeGen<'ctx> {
             module,
             builder: context.create_builder(),
             execution_engine,
+            options,
+            protection: MemoryProtection::new(),
         }
     }
 
+    /// Protect memory in the code compiled from now on.
+    pub fn set_protection(&mut self, protection: MemoryProtection) {
+        self.protection = protection;
+    }
+
     pub fn compile_program(
         &self,
         program_id: usize,
         functions: &FxHashMap<u16, FunctionValue>,
     ) -> Option<JitFunction<ProgramFunc>> {
-        let i8_type = self.context.i8_type();
-        let void_type = self.context.void_type();
-        let memory_ptr_type = i8_type.ptr_type(AddressSpace::Generic);
-        let fn_type = void_type.fn_type(&[memory_ptr_type.into()], false);
-
         let function_name = format!("func-{}", program_id);
-        let function = self.module.add_function(&function_name, fn_type, None);
+        let function = self
+            .module
+            .add_function(&function_name, self.get_function_type(), None);
         let basic_block = self.context.append_basic_block(function, "entry");
         self.builder.position_at_end(basic_block);
 
-        let memory_ptr = function.get_nth_param(0).unwrap().into_pointer_value();
-        let registers_ptr = self.builder.build_array_alloca(
-            self.context.i16_type(),
-            self.context.i16_type().const_int(32, false),
-            "registers",
-        );
-        for i in 0..32 {
-            let register_ptr = unsafe {
-                self.builder.build_gep(
-                    registers_ptr,
-                    &[self.context.i16_type().const_int(i, false)],
-                    "register",
-                )
-            };
+        let registers_ptr = function.get_nth_param(1).unwrap().into_pointer_value();
+        if self.options.zero_register {
             self.builder
-                .build_store(register_ptr, self.context.i16_type().const_int(0, false));
+                .build_store(registers_ptr, self.context.i16_type().const_int(0, false));
         }
 
         let inner_function = functions.get(&0).unwrap();
 
         self.builder.position_at_end(basic_block);
-        self.builder.build_call(
-            *inner_function,
-            &[memory_ptr.into(), registers_ptr.into()],
-            "call",
-        );
+        self.builder
+            .build_call(*inner_function, &params(function), "call");
         self.builder.build_return(None);
 
         // let pass_manager_builder = PassManagerBuilder::create();
@@ -155,8 +233,62 @@ impl<'ctx> CodeGen<'ctx> {
         let void_type = self.context.void_type();
         let memory_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
         let registers_ptr_type = self.context.i16_type().ptr_type(AddressSpace::Generic);
+        let rng_ptr_type = self.context.i32_type().ptr_type(AddressSpace::Generic);
+        let host_table_type = self
+            .get_host_function_type()
+            .ptr_type(AddressSpace::Generic)
+            .ptr_type(AddressSpace::Generic);
+        let host_table_len_type = self.context.i32_type();
+        let base_type = self.context.i32_type();
+        let counters_ptr_type = self.get_counters_type().ptr_type(AddressSpace::Generic);
+
+        void_type.fn_type(
+            &[
+                memory_ptr_type.into(),
+                registers_ptr_type.into(),
+                rng_ptr_type.into(),
+                host_table_type.into(),
+                host_table_len_type.into(),
+                base_type.into(),
+                counters_ptr_type.into(),
+            ],
+            false,
+        )
+    }
+
+    fn get_counters_type(&self) -> StructType<'ctx> {
+        let i64_type = self.context.i64_type();
+        let buffer_type = i64_type.ptr_type(AddressSpace::Generic);
+        self.context.struct_type(
+            &[
+                i64_type.into(),
+                buffer_type.into(),
+                buffer_type.into(),
+                buffer_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                buffer_type.into(),
+                buffer_type.into(),
+                buffer_type.into(),
+            ],
+            false,
+        )
+    }
 
-        void_type.fn_type(&[memory_ptr_type.into(), registers_ptr_type.into()], false)
+    fn get_host_function_type(&self) -> FunctionType<'ctx> {
+        let void_type = self.context.void_type();
+        let registers_ptr_type = self.context.i16_type().ptr_type(AddressSpace::Generic);
+        let memory_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let memory_size_type = self.context.i32_type();
+
+        void_type.fn_type(
+            &[
+                registers_ptr_type.into(),
+                memory_ptr_type.into(),
+                memory_size_type.into(),
+            ],
+            false,
+        )
     }
 
     pub fn compile_function(
@@ -164,7 +296,7 @@ impl<'ctx> CodeGen<'ctx> {
         id: usize,
         repeat: u8,
         instructions: &[Instruction],
-        memory_size: u16,
+        memory_size: u32,
         functions: &FxHashMap<u16, FunctionValue>,
     ) -> FunctionValue<'ctx> {
         let function = self.module.add_function(
@@ -176,7 +308,6 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.position_at_end(basic_block);
 
         let memory_ptr = function.get_nth_param(0).unwrap().into_pointer_value();
-        let registers_ptr = function.get_nth_param(1).unwrap().into_pointer_value();
 
         let registers = &Registers::new(self, function);
 
@@ -202,11 +333,12 @@ impl<'ctx> CodeGen<'ctx> {
         let mut next_instr_block = blocks_iter.next().unwrap().1;
         self.builder.build_unconditional_branch(next_instr_block);
 
-        for instruction in instructions {
+        for (pc, instruction) in instructions.iter().enumerate() {
             let instr_block = next_instr_block;
             self.builder.position_at_end(instr_block);
             // there is safe as there's always more more block than instructions
             next_instr_block = blocks_iter.next().unwrap().1;
+            self.profile_instruction(registers, instruction, pc, &targets, function);
             let mut branched = false;
             match instruction {
                 Instruction::Immediate(immediate) => {
@@ -238,6 +370,12 @@ impl<'ctx> CodeGen<'ctx> {
                         Sra => self.compile_sra(registers, register),
                     }
                 }
+                Instruction::Load(load) if self.bytewise(false) => {
+                    self.compile_load_bytes(registers, load, memory_size, function);
+                }
+                Instruction::Store(store) if self.bytewise(true) => {
+                    self.compile_store_bytes(registers, store, memory_size, function);
+                }
                 Instruction::Load(load) => {
                     use LoadOpcode::*;
                     match load.opcode {
@@ -304,9 +442,23 @@ impl<'ctx> CodeGen<'ctx> {
                     use CallIdOpcode::*;
                     match call_id.opcode {
                         Call => {
-                            self.compile_call(call_id, memory_ptr, registers_ptr, functions);
+                            self.compile_call(call_id, function, functions);
                         }
                     }
+                }
+                Instruction::Ecall(ecall) => {
+                    use EcallOpcode::*;
+                    match ecall.opcode {
+                        Ecall => {
+                            self.compile_ecall(ecall, memory_size, function);
+                        }
+                    }
+                }
+                Instruction::Random(random) => {
+                    use RandomOpcode::*;
+                    match random.opcode {
+                        Rand => self.compile_rand(registers, random, function),
+                    }
                 } // Instruction::Switch(switch) => {
                   //     use SwitchOpcode::*;
                   //     match switch.opcode {
@@ -407,7 +559,7 @@ impl<'ctx> CodeGen<'ctx> {
             value,
         );
         self.builder
-            .build_store(registers.get(immediate.rd), result);
+            .build_store(registers.get_rd(immediate.rd), result);
     }
 
     fn compile_immediate_shift(
@@ -439,7 +591,7 @@ impl<'ctx> CodeGen<'ctx> {
             mvalue,
         );
         self.builder
-            .build_store(registers.get(immediate.rd), result);
+            .build_store(registers.get_rd(immediate.rd), result);
     }
 
     fn compile_addi(&self, registers: &Registers<'ctx>, immediate: &Immediate) {
@@ -509,7 +661,8 @@ impl<'ctx> CodeGen<'ctx> {
             rs1_value.into_int_value(),
             rs2_value.into_int_value(),
         );
-        self.builder.build_store(registers.get(register.rd), result);
+        self.builder
+            .build_store(registers.get_rd(register.rd), result);
     }
 
     fn compile_register_shift(
@@ -545,7 +698,8 @@ impl<'ctx> CodeGen<'ctx> {
             rs1_value.into_int_value(),
             mvalue,
         );
-        self.builder.build_store(registers.get(register.rd), result);
+        self.builder
+            .build_store(registers.get_rd(register.rd), result);
     }
 
     fn compile_add(&self, registers: &Registers<'ctx>, register: &Register) {
@@ -606,7 +760,7 @@ impl<'ctx> CodeGen<'ctx> {
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         load: &Load,
-        memory_size: u16,
+        bound: u32,
         function: FunctionValue,
         load_branch: LoadValue<'ctx>,
     ) {
@@ -625,10 +779,14 @@ impl<'ctx> CodeGen<'ctx> {
         let else_block = self.context.append_basic_block(function, "else");
         let end_block = self.context.append_basic_block(function, "end_load");
 
-        let memory_size = self.context.i16_type().const_int(memory_size as u64, false);
+        // the index is unsigned
+        let index = self
+            .builder
+            .build_int_z_extend(index, self.context.i32_type(), "index");
+        let bound = self.context.i32_type().const_int(bound as u64, false);
         let in_bounds =
             self.builder
-                .build_int_compare(IntPredicate::ULT, index, memory_size, "in_bounds");
+                .build_int_compare(IntPredicate::ULT, index, bound, "in_bounds");
         self.builder
             .build_conditional_branch(in_bounds, then_block, else_block);
 
@@ -651,7 +809,7 @@ impl<'ctx> CodeGen<'ctx> {
         phi.add_incoming(&[(&load_value, then_block), (&else_value, else_block)]);
 
         self.builder.build_store(
-            registers.get(load.rd),
+            registers.get_rd(load.rd),
             phi.as_basic_value().into_int_value(),
         );
     }
@@ -661,7 +819,7 @@ impl<'ctx> CodeGen<'ctx> {
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         store: &Store,
-        memory_size: u16,
+        bound: u32,
         function: FunctionValue,
         store_branch: StoreValue<'ctx>,
     ) {
@@ -683,10 +841,14 @@ impl<'ctx> CodeGen<'ctx> {
         let then_block = self.context.append_basic_block(function, "store");
         let end_block = self.context.append_basic_block(function, "end_store");
 
-        let memory_size = self.context.i16_type().const_int(memory_size as u64, false);
+        // the index is unsigned
+        let index = self
+            .builder
+            .build_int_z_extend(index, self.context.i32_type(), "index");
+        let bound = self.context.i32_type().const_int(bound as u64, false);
         let in_bounds =
             self.builder
-                .build_int_compare(IntPredicate::ULT, index, memory_size, "in_bounds");
+                .build_int_compare(IntPredicate::ULT, index, bound, "in_bounds");
         self.builder
             .build_conditional_branch(in_bounds, then_block, end_block);
 
@@ -707,19 +869,414 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.position_at_end(end_block);
     }
 
+    // whether loads, or stores if write is set, are compiled byte by byte. a
+    // halfword in a window may wrap around the end of memory, and memory
+    // protection is checked and profiled for each byte
+    fn bytewise(&self, write: bool) -> bool {
+        self.options.window_size.is_some()
+            || self.options.profile_memory
+            || self.protection.forbidding(write).next().is_some()
+    }
+
+    // the memory indexes of the bytes accessed at rs + offset, and whether the
+    // access is in bounds, which is inside of the window if there is one
+    fn byte_indexes(
+        &self,
+        registers: &Registers<'ctx>,
+        rs: u8,
+        offset: u16,
+        size: u64,
+        memory_size: u32,
+        function: FunctionValue<'ctx>,
+    ) -> (IntValue<'ctx>, Vec<IntValue<'ctx>>) {
+        let i16_type = self.context.i16_type();
+        let i64_type = self.context.i64_type();
+        let window_size = self
+            .options
+            .window_size
+            .map(u32::from)
+            .unwrap_or(memory_size);
+
+        let offset = i16_type.const_int(offset as u64, false);
+        let rs_value = self.builder.build_load(registers.get(rs), "rs_value");
+        let index = self
+            .builder
+            .build_int_add(offset, rs_value.into_int_value(), "index");
+        let index = self.builder.build_int_z_extend(index, i64_type, "index");
+        let in_bounds = if memory_size == 0 {
+            self.context.bool_type().const_zero()
+        } else {
+            let bound = index_bound(window_size, size as u32);
+            self.builder.build_int_compare(
+                IntPredicate::ULT,
+                index,
+                i64_type.const_int(bound as u64, false),
+                "in_bounds",
+            )
+        };
+
+        let base = if self.options.window_size.is_some() {
+            let base = function.get_nth_param(5).unwrap().into_int_value();
+            self.builder.build_int_z_extend(base, i64_type, "base")
+        } else {
+            i64_type.const_zero()
+        };
+        let address = self
+            .builder
+            .build_int_mul(index, i64_type.const_int(size, false), "address");
+        let address = self.builder.build_int_add(base, address, "address");
+        // avoid a division by zero, though nothing is accessed then
+        let memory_size = i64_type.const_int(memory_size.max(1) as u64, false);
+        let indexes = (0..size)
+            .map(|byte| {
+                let address = self.builder.build_int_add(
+                    address,
+                    i64_type.const_int(byte, false),
+                    "byte_address",
+                );
+                self.builder
+                    .build_int_unsigned_rem(address, memory_size, "wrapped")
+            })
+            .collect();
+        (in_bounds, indexes)
+    }
+
+    // whether an access to the bytes at indexes goes ahead, which is when it's
+    // in bounds and memory protection permits it. an access that's in bounds
+    // but forbidden is counted as a violation
+    fn permitted(
+        &self,
+        in_bounds: IntValue<'ctx>,
+        indexes: &[IntValue<'ctx>],
+        write: bool,
+        function: FunctionValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let i64_type = self.context.i64_type();
+        let mut permitted = self.context.bool_type().const_all_ones();
+        for region in self.protection.forbidding(write) {
+            let start = i64_type.const_int(region.start as u64, false);
+            let end = i64_type.const_int(region.end as u64, false);
+            for index in indexes {
+                let before =
+                    self.builder
+                        .build_int_compare(IntPredicate::ULT, *index, start, "before");
+                let after = self
+                    .builder
+                    .build_int_compare(IntPredicate::UGE, *index, end, "after");
+                let outside = self.builder.build_or(before, after, "outside");
+                permitted = self.builder.build_and(permitted, outside, "permitted");
+            }
+        }
+        if self.options.count_violations {
+            let forbidden = self.builder.build_not(permitted, "forbidden");
+            let violation = self.builder.build_and(in_bounds, forbidden, "violation");
+            let violation = self
+                .builder
+                .build_int_z_extend(violation, i64_type, "violation");
+            let violations_ptr = self.counter(VIOLATIONS, function);
+            let violations = self
+                .builder
+                .build_load(violations_ptr, "violations")
+                .into_int_value();
+            let violations = self
+                .builder
+                .build_int_add(violations, violation, "violations");
+            self.builder.build_store(violations_ptr, violations);
+        }
+        self.builder.build_and(in_bounds, permitted, "permitted")
+    }
+
+    // record the accesses to the bytes at indexes in the memory profile, if
+    // there is a buffer for it
+    fn profile_accesses(
+        &self,
+        indexes: &[IntValue<'ctx>],
+        write: bool,
+        function: FunctionValue<'ctx>,
+    ) {
+        if !self.options.profile_memory {
+            return;
+        }
+        let i64_type = self.context.i64_type();
+        let field = if write { MEMORY_WRITES } else { MEMORY_READS };
+        let buffer = self
+            .builder
+            .build_load(self.counter(field, function), "buffer")
+            .into_pointer_value();
+
+        let count_block = self.context.append_basic_block(function, "profile");
+        let end_block = self.context.append_basic_block(function, "end_profile");
+        let is_null = self.builder.build_is_null(buffer, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, end_block, count_block);
+
+        self.builder.position_at_end(count_block);
+        for index in indexes {
+            let count_ptr = unsafe { self.builder.build_gep(buffer, &[*index], "count_ptr") };
+            let count = self.builder.build_load(count_ptr, "count").into_int_value();
+            let count = self
+                .builder
+                .build_int_add(count, i64_type.const_int(1, false), "count");
+            self.builder.build_store(count_ptr, count);
+        }
+        self.builder.build_unconditional_branch(end_block);
+
+        self.builder.position_at_end(end_block);
+    }
+
+    // a pointer to a field of the counters
+    fn counter(&self, field: u32, function: FunctionValue<'ctx>) -> PointerValue<'ctx> {
+        let counters = function.get_nth_param(6).unwrap().into_pointer_value();
+        self.builder
+            .build_struct_gep(counters, field, "counter")
+            .unwrap()
+    }
+
+    fn increment(&self, ptr: PointerValue<'ctx>, amount: IntValue<'ctx>) {
+        let value = self.builder.build_load(ptr, "value").into_int_value();
+        let value = self.builder.build_int_add(value, amount, "incremented");
+        self.builder.build_store(ptr, value);
+    }
+
+    // build what body builds only if cond holds, continuing after it
+    fn build_if(&self, cond: IntValue<'ctx>, function: FunctionValue<'ctx>, body: impl FnOnce()) {
+        let then_block = self.context.append_basic_block(function, "then");
+        let end_block = self.context.append_basic_block(function, "end_if");
+        self.builder
+            .build_conditional_branch(cond, then_block, end_block);
+        self.builder.position_at_end(then_block);
+        body();
+        self.builder.build_unconditional_branch(end_block);
+        self.builder.position_at_end(end_block);
+    }
+
+    // count the execution of the instruction at pc in the execution profile,
+    // and whether it's a branch that's taken
+    fn profile_instruction(
+        &self,
+        registers: &Registers<'ctx>,
+        instruction: &Instruction,
+        pc: usize,
+        targets: &FxHashMap<u8, BasicBlock>,
+        function: FunctionValue<'ctx>,
+    ) {
+        if !self.options.profile_execution {
+            return;
+        }
+        let i64_type = self.context.i64_type();
+        let one = i64_type.const_int(1, false);
+
+        let opcodes = self
+            .builder
+            .build_load(self.counter(OPCODES, function), "opcodes")
+            .into_pointer_value();
+        let has_opcodes = self.builder.build_is_not_null(opcodes, "has_opcodes");
+        self.build_if(has_opcodes, function, || {
+            let opcode = i64_type.const_int(u8::from(instruction) as u64, false);
+            let count_ptr = unsafe { self.builder.build_gep(opcodes, &[opcode], "count_ptr") };
+            self.increment(count_ptr, one);
+        });
+
+        let id = self
+            .builder
+            .build_load(self.counter(FUNCTION, function), "id")
+            .into_int_value();
+        let functions = self
+            .builder
+            .build_load(self.counter(FUNCTIONS, function), "functions")
+            .into_int_value();
+        let known = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, id, functions, "known");
+        self.build_if(known, function, || {
+            let offsets = self
+                .builder
+                .build_load(self.counter(OFFSETS, function), "offsets")
+                .into_pointer_value();
+            let start_ptr = unsafe { self.builder.build_gep(offsets, &[id], "start_ptr") };
+            let start = self.builder.build_load(start_ptr, "start").into_int_value();
+            let next = self.builder.build_int_add(id, one, "next");
+            let end_ptr = unsafe { self.builder.build_gep(offsets, &[next], "end_ptr") };
+            let end = self.builder.build_load(end_ptr, "end").into_int_value();
+            let slot =
+                self.builder
+                    .build_int_add(start, i64_type.const_int(pc as u64, false), "slot");
+            let in_function =
+                self.builder
+                    .build_int_compare(IntPredicate::ULT, slot, end, "in_function");
+            self.build_if(in_function, function, || {
+                let instructions = self
+                    .builder
+                    .build_load(self.counter(INSTRUCTIONS, function), "instructions")
+                    .into_pointer_value();
+                let count_ptr =
+                    unsafe { self.builder.build_gep(instructions, &[slot], "count_ptr") };
+                self.increment(count_ptr, one);
+
+                if let Instruction::Branch(branch) = instruction {
+                    if targets.contains_key(&branch.target) {
+                        let rs1 = self.builder.build_load(registers.get(branch.rs1), "rs1");
+                        let rs2 = self.builder.build_load(registers.get(branch.rs2), "rs2");
+                        let cond = self.builder.build_int_compare(
+                            branch_predicate(branch.opcode),
+                            rs1.into_int_value(),
+                            rs2.into_int_value(),
+                            "cond",
+                        );
+                        let taken = self.builder.build_int_z_extend(cond, i64_type, "taken");
+                        let taken_buffer = self
+                            .builder
+                            .build_load(self.counter(TAKEN, function), "taken_buffer")
+                            .into_pointer_value();
+                        let taken_ptr =
+                            unsafe { self.builder.build_gep(taken_buffer, &[slot], "taken_ptr") };
+                        self.increment(taken_ptr, taken);
+                    }
+                }
+            });
+        });
+    }
+
+    fn byte_address(
+        &self,
+        index: IntValue<'ctx>,
+        function: FunctionValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let ptr = function.get_nth_param(0).unwrap().into_pointer_value();
+        unsafe { self.builder.build_gep(ptr, &[index], "gep index") }
+    }
+
+    fn compile_load_bytes(
+        &self,
+        registers: &Registers<'ctx>,
+        load: &Load,
+        memory_size: u32,
+        function: FunctionValue<'ctx>,
+    ) {
+        use LoadOpcode::*;
+        let i16_type = self.context.i16_type();
+        let size = match load.opcode {
+            Lh => 2,
+            Lb | Lbu => 1,
+        };
+        let (in_bounds, indexes) =
+            self.byte_indexes(registers, load.rs, load.offset, size, memory_size, function);
+        let permitted = self.permitted(in_bounds, &indexes, false, function);
+
+        let then_block = self.context.append_basic_block(function, "load");
+        let else_block = self.context.append_basic_block(function, "else");
+        let end_block = self.context.append_basic_block(function, "end_load");
+        self.builder
+            .build_conditional_branch(permitted, then_block, else_block);
+
+        self.builder.position_at_end(then_block);
+        self.profile_accesses(&indexes, false, function);
+        let bytes: Vec<IntValue> = indexes
+            .iter()
+            .map(|index| {
+                let address = self.byte_address(*index, function);
+                self.builder.build_load(address, "byte").into_int_value()
+            })
+            .collect();
+        let load_value = match load.opcode {
+            Lh => {
+                let low = self.builder.build_int_z_extend(bytes[0], i16_type, "low");
+                let high = self.builder.build_int_z_extend(bytes[1], i16_type, "high");
+                let high = self.builder.build_left_shift(
+                    high,
+                    i16_type.const_int(8, false),
+                    "high_shifted",
+                );
+                self.builder.build_or(low, high, "lh")
+            }
+            Lb => self
+                .builder
+                .build_int_s_extend(bytes[0], i16_type, "extended"),
+            Lbu => self
+                .builder
+                .build_int_z_extend(bytes[0], i16_type, "extended"),
+        };
+        // profiling may have moved us on from the then block
+        let load_block = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(end_block);
+
+        self.builder.position_at_end(else_block);
+        let else_value = i16_type.const_int(0, false);
+        self.builder.build_unconditional_branch(end_block);
+
+        self.builder.position_at_end(end_block);
+        let phi = self.builder.build_phi(i16_type, "load_result");
+        phi.add_incoming(&[(&load_value, load_block), (&else_value, else_block)]);
+        self.builder.build_store(
+            registers.get_rd(load.rd),
+            phi.as_basic_value().into_int_value(),
+        );
+    }
+
+    fn compile_store_bytes(
+        &self,
+        registers: &Registers<'ctx>,
+        store: &Store,
+        memory_size: u32,
+        function: FunctionValue<'ctx>,
+    ) {
+        use StoreOpcode::*;
+        let size = match store.opcode {
+            Sh => 2,
+            Sb => 1,
+        };
+        let (in_bounds, indexes) = self.byte_indexes(
+            registers,
+            store.rd,
+            store.offset,
+            size,
+            memory_size,
+            function,
+        );
+        let permitted = self.permitted(in_bounds, &indexes, true, function);
+
+        let then_block = self.context.append_basic_block(function, "store");
+        let end_block = self.context.append_basic_block(function, "end_store");
+        self.builder
+            .build_conditional_branch(permitted, then_block, end_block);
+
+        self.builder.position_at_end(then_block);
+        self.profile_accesses(&indexes, true, function);
+        let value = self
+            .builder
+            .build_load(registers.get(store.rs), "rs_value")
+            .into_int_value();
+        for (byte, index) in indexes.iter().enumerate() {
+            let shifted = self.builder.build_right_shift(
+                value,
+                self.context.i16_type().const_int(byte as u64 * 8, false),
+                false,
+                "shifted",
+            );
+            let truncated =
+                self.builder
+                    .build_int_truncate(shifted, self.context.i8_type(), "truncated");
+            self.builder
+                .build_store(self.byte_address(*index, function), truncated);
+        }
+        self.builder.build_unconditional_branch(end_block);
+
+        self.builder.position_at_end(end_block);
+    }
+
     fn compile_lb(
         &self,
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         load: &Load,
-        memory_size: u16,
+        memory_size: u32,
         function: FunctionValue,
     ) {
         self.compile_load_in_bounds(
             registers,
             ptr,
             load,
-            memory_size,
+            index_bound(memory_size, 1),
             function,
             |builder, context, address| {
                 let load_value = builder.build_load(address, "lb");
@@ -737,14 +1294,14 @@ impl<'ctx> CodeGen<'ctx> {
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         load: &Load,
-        memory_size: u16,
+        memory_size: u32,
         function: FunctionValue,
     ) {
         self.compile_load_in_bounds(
             registers,
             ptr,
             load,
-            memory_size,
+            index_bound(memory_size, 1),
             function,
             |builder, context, address| {
                 let load_value = builder.build_load(address, "lb");
@@ -762,14 +1319,14 @@ impl<'ctx> CodeGen<'ctx> {
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         store: &Store,
-        memory_size: u16,
+        memory_size: u32,
         function: FunctionValue,
     ) {
         self.compile_store_in_bounds(
             registers,
             ptr,
             store,
-            memory_size,
+            index_bound(memory_size, 1),
             function,
             |builder, context, address, value| {
                 let truncated = builder.build_int_truncate(value, context.i8_type(), "truncated");
@@ -783,7 +1340,7 @@ impl<'ctx> CodeGen<'ctx> {
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         load: &Load,
-        memory_size: u16,
+        memory_size: u32,
         function: FunctionValue,
     ) {
         let i16_type = self.context.i16_type();
@@ -794,7 +1351,7 @@ impl<'ctx> CodeGen<'ctx> {
             registers,
             ptr,
             load,
-            memory_size / 2,
+            index_bound(memory_size, 2),
             function,
             |builder, _i16_type, address| builder.build_load(address, "lh").into_int_value(),
         );
@@ -805,7 +1362,7 @@ impl<'ctx> CodeGen<'ctx> {
         registers: &Registers<'ctx>,
         ptr: PointerValue<'ctx>,
         store: &Store,
-        memory_size: u16,
+        memory_size: u32,
         function: FunctionValue,
     ) {
         let i16_type = self.context.i16_type();
@@ -816,7 +1373,7 @@ impl<'ctx> CodeGen<'ctx> {
             registers,
             i16_ptr,
             store,
-            memory_size / 2,
+            index_bound(memory_size, 2),
             function,
             |builder, _context, address, value| {
                 builder.build_store(address, value);
@@ -914,16 +1471,104 @@ impl<'ctx> CodeGen<'ctx> {
     fn compile_call(
         &self,
         call: &CallId,
-        memory_ptr: PointerValue,
-        registers_ptr: PointerValue,
+        function: FunctionValue<'ctx>,
         functions: &FxHashMap<u16, FunctionValue>,
     ) {
         let identifier = call.identifier;
+        // the callee is profiled as the function with its id, as the same code
+        // may be shared by functions with different ids
+        let caller = self.options.profile_execution.then(|| {
+            let function_ptr = self.counter(FUNCTION, function);
+            let caller = self.builder.build_load(function_ptr, "caller");
+            self.builder.build_store(
+                function_ptr,
+                self.context.i64_type().const_int(identifier as u64, false),
+            );
+            (function_ptr, caller)
+        });
         self.builder.build_call(
             *functions.get(&identifier).unwrap(),
-            &[memory_ptr.into(), registers_ptr.into()],
+            &params(function),
             "call",
         );
+        if let Some((function_ptr, caller)) = caller {
+            self.builder.build_store(function_ptr, caller);
+        }
+    }
+
+    fn compile_ecall(&self, ecall: &Ecall, memory_size: u32, function: FunctionValue) {
+        let memory_ptr = function.get_nth_param(0).unwrap().into_pointer_value();
+        let registers_ptr = function.get_nth_param(1).unwrap().into_pointer_value();
+        let host_table_ptr = function.get_nth_param(3).unwrap().into_pointer_value();
+        let host_table_len = function.get_nth_param(4).unwrap().into_int_value();
+
+        let lookup_block = self.context.append_basic_block(function, "ecall_lookup");
+        let call_block = self.context.append_basic_block(function, "ecall_call");
+        let end_block = self.context.append_basic_block(function, "ecall_end");
+
+        let number = self
+            .context
+            .i32_type()
+            .const_int(ecall.number as u64, false);
+        let in_table =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, number, host_table_len, "in_table");
+        self.builder
+            .build_conditional_branch(in_table, lookup_block, end_block);
+
+        self.builder.position_at_end(lookup_block);
+        let host_function_ptr = unsafe {
+            self.builder
+                .build_gep(host_table_ptr, &[number], "host_function_ptr")
+        };
+        let host_function = self
+            .builder
+            .build_load(host_function_ptr, "host_function")
+            .into_pointer_value();
+        let is_null = self.builder.build_is_null(host_function, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, end_block, call_block);
+
+        self.builder.position_at_end(call_block);
+        let memory_size = self.context.i32_type().const_int(memory_size as u64, false);
+        self.builder.build_call(
+            CallableValue::try_from(host_function).unwrap(),
+            &[registers_ptr.into(), memory_ptr.into(), memory_size.into()],
+            "ecall",
+        );
+        // the host function may have written to a hardwired zero register
+        if self.options.zero_register {
+            self.builder
+                .build_store(registers_ptr, self.context.i16_type().const_int(0, false));
+        }
+        self.builder.build_unconditional_branch(end_block);
+
+        self.builder.position_at_end(end_block);
+    }
+
+    fn compile_rand(&self, registers: &Registers<'ctx>, random: &Random, function: FunctionValue) {
+        let rng_ptr = function.get_nth_param(2).unwrap().into_pointer_value();
+        let i32_type = self.context.i32_type();
+        let state = self.builder.build_load(rng_ptr, "rng").into_int_value();
+        let state = self.builder.build_int_mul(
+            state,
+            i32_type.const_int(RNG_MULTIPLIER as u64, false),
+            "rng_mul",
+        );
+        let state = self.builder.build_int_add(
+            state,
+            i32_type.const_int(RNG_INCREMENT as u64, false),
+            "rng_add",
+        );
+        self.builder.build_store(rng_ptr, state);
+        let high =
+            self.builder
+                .build_right_shift(state, i32_type.const_int(16, false), false, "rng_high");
+        let result = self
+            .builder
+            .build_int_truncate(high, self.context.i16_type(), "rand");
+        self.builder
+            .build_store(registers.get_rd(random.rd), result);
     }
 
     // fn compile_switch(
@@ -981,6 +1626,26 @@ impl<'ctx> CodeGen<'ctx> {
     // }
 }
 
+// pass on all parameters of the function we're in
+fn params<'ctx>(function: FunctionValue<'ctx>) -> Vec<BasicMetadataValueEnum<'ctx>> {
+    function
+        .get_param_iter()
+        .map(|param| param.into())
+        .collect()
+}
+
+fn branch_predicate(opcode: BranchOpcode) -> IntPredicate {
+    use BranchOpcode::*;
+    match opcode {
+        Beq => IntPredicate::EQ,
+        Bne => IntPredicate::NE,
+        Blt => IntPredicate::SLT,
+        Bltu => IntPredicate::ULT,
+        Bge => IntPredicate::SGE,
+        Bgeu => IntPredicate::UGE,
+    }
+}
+
 pub fn main() -> Result<(), Box<dyn Error>> {
     let mut memory = [0u8; 64];
     memory[0] = 11;
@@ -1057,7 +1722,7 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let codegen = CodeGen::new(&context);
     let mut cache = FunctionValueCache::new();
     println!("Compiling program");
-    let func = program.compile(0, &codegen, memory.len() as u16, &mut cache);
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
     codegen.module.verify().unwrap();
 
     save_asm(&codegen.module);
@@ -3,16 +3,126 @@ extern crate num;
 extern crate num_derive;
 
 pub mod assembler;
+#[cfg(feature = "llvm")]
 pub mod cache;
+pub mod cfg;
+pub mod cost;
+#[cfg(feature = "cranelift")]
+pub mod cranelift;
+pub mod csource;
+pub mod debugger;
 pub mod disassembler;
+pub mod equivalence;
 pub mod function;
 pub mod lang;
+#[cfg(feature = "llvm")]
 pub mod llvm;
+#[cfg(feature = "llvm")]
 pub mod llvmasm;
+pub mod minimize;
+pub mod profile;
 pub mod program;
 pub mod run;
 pub mod serializer;
+pub mod simplify;
+pub mod symbolic;
+pub mod wasm;
+pub mod world;
+
+use std::io::{self, BufRead, Write};
+
+const USAGE: &str = "usage: aleven debug <program.ale> [memory size]
+       aleven smt <program.ale> <memory size> [assertion...]";
 
 fn main() {
-    llvm::main();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("debug") => {
+            if let Err(error) = debug(&args[2..]) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        Some("smt") => match smt(&args[2..]) {
+            Ok(output) => print!("{}", output),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        },
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+        None => {
+            #[cfg(feature = "llvm")]
+            llvm::main();
+        }
+    }
+}
+
+// files included by the program at path are relative to its directory, where
+// the program is at its file name
+fn resolver(path: &str) -> (assembler::DirectoryResolver, String) {
+    let path = std::path::Path::new(path);
+    let directory = path.parent().unwrap_or_else(|| std::path::Path::new(""));
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (assembler::DirectoryResolver::new(directory), name)
+}
+
+fn smt(args: &[String]) -> Result<String, String> {
+    let (path, memory_size, assertions) = match args {
+        [path, memory_size, assertions @ ..] => (path, memory_size, assertions),
+        _ => return Err(USAGE.to_string()),
+    };
+    let memory_size = memory_size
+        .parse()
+        .map_err(|_| format!("invalid memory size {}", memory_size))?;
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let (resolver, name) = resolver(path);
+    let program = assembler::parse_program_with_resolver(&source, &name, &resolver)
+        .map_err(|e| format!("{}: {:?}", path, e))?;
+    let assertions: Vec<&str> = assertions.iter().map(String::as_str).collect();
+    Ok(symbolic::SymbolicExecution::new(&program, memory_size).to_smt2(&assertions))
+}
+
+fn debug(args: &[String]) -> Result<(), String> {
+    let (path, memory_size) = match args {
+        [path] => (path, 65536),
+        [path, memory_size] => (
+            path,
+            memory_size
+                .parse()
+                .map_err(|_| format!("invalid memory size {}", memory_size))?,
+        ),
+        _ => return Err(USAGE.to_string()),
+    };
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let (resolver, name) = resolver(path);
+    let (program, labels) =
+        assembler::parse_program_with_labels_and_resolver(&source, &name, &resolver)
+            .map_err(|e| format!("{}: {:?}", path, e))?;
+    let mut debugger = debugger::Debugger::new(program, labels, vec![0; memory_size]);
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(aleven) ");
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        };
+        if matches!(line.trim(), "quit" | "q") {
+            return Ok(());
+        }
+        match debugger.command(&line) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(error) => println!("error: {}", error),
+        }
+    }
 }
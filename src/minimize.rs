@@ -0,0 +1,301 @@
+use crate::function::Function;
+use crate::lang::{
+    Branch, CallId, CallIdOpcode, Ecall, Immediate, Instruction, Load, Random, Register, Store,
+};
+use crate::program::Program;
+
+/// Shrink a genome to a smaller one for which predicate still holds, by
+/// removing bytes and then making the remaining bytes smaller. Returns the
+/// genome as it is if predicate doesn't hold for it.
+pub fn minimize_genome(genome: &[u8], mut predicate: impl FnMut(&[u8]) -> bool) -> Vec<u8> {
+    if !predicate(genome) {
+        return genome.to_vec();
+    }
+    let mut genome = genome.to_vec();
+    loop {
+        let before = genome.clone();
+        genome = ddmin(genome, &mut predicate);
+        for index in 0..genome.len() {
+            for smaller in smaller_numbers(genome[index] as i64) {
+                let mut candidate = genome.clone();
+                candidate[index] = smaller as u8;
+                if predicate(&candidate) {
+                    genome = candidate;
+                    break;
+                }
+            }
+        }
+        if genome == before {
+            return genome;
+        }
+    }
+}
+
+/// Shrink a program to a smaller one for which predicate still holds, by
+/// removing functions and instructions, lowering repeats and making operands
+/// smaller. Returns the program as it is if predicate doesn't hold for it.
+pub fn minimize_program(program: &Program, mut predicate: impl FnMut(&Program) -> bool) -> Program {
+    if !predicate(program) {
+        return program.clone();
+    }
+    let mut functions = program.functions().to_vec();
+    let mut test = |functions: &[Function]| predicate(&Program::from_functions(functions.to_vec()));
+    loop {
+        let before = functions.clone();
+
+        // main stays, as it's where a run starts
+        let mut id = 1;
+        while id < functions.len() {
+            let candidate = without_function(&functions, id);
+            if test(&candidate) {
+                functions = candidate;
+            } else {
+                id += 1;
+            }
+        }
+
+        for id in 0..functions.len() {
+            let instructions = ddmin(
+                functions[id].get_instructions().to_vec(),
+                &mut |instructions| {
+                    let mut candidate = functions.clone();
+                    candidate[id] = with_forward_branches(&functions[id], instructions.to_vec());
+                    test(&candidate)
+                },
+            );
+            functions[id] = with_forward_branches(&functions[id], instructions);
+
+            for repeat in smaller_numbers(functions[id].get_repeat() as i64) {
+                let mut candidate = functions.clone();
+                candidate[id] = functions[id].with_repeat(repeat.max(1) as u8);
+                if candidate[id].get_repeat() < functions[id].get_repeat() && test(&candidate) {
+                    functions = candidate;
+                    break;
+                }
+            }
+
+            for pc in 0..functions[id].get_instructions().len() {
+                for smaller in smaller_instructions(&functions[id].get_instructions()[pc]) {
+                    let mut instructions = functions[id].get_instructions().to_vec();
+                    instructions[pc] = smaller;
+                    let mut candidate = functions.clone();
+                    candidate[id] = with_forward_branches(&functions[id], instructions);
+                    if test(&candidate) {
+                        functions = candidate;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if functions == before {
+            return Program::from_functions(functions);
+        }
+    }
+}
+
+// remove the items that predicate doesn't need, trying to remove ever smaller
+// chunks of them
+fn ddmin<T: Clone>(items: Vec<T>, predicate: &mut impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut items = items;
+    let mut chunks = 2;
+    while !items.is_empty() {
+        let chunk_len = items.len().div_ceil(chunks.min(items.len()));
+        let reduced = (0..items.len()).step_by(chunk_len).find_map(|start| {
+            let mut candidate = items[..start].to_vec();
+            candidate.extend_from_slice(&items[(start + chunk_len).min(items.len())..]);
+            predicate(&candidate).then_some(candidate)
+        });
+        match reduced {
+            Some(candidate) => {
+                items = candidate;
+                chunks = (chunks - 1).max(2);
+            }
+            None if chunk_len == 1 => break,
+            None => chunks *= 2,
+        }
+    }
+    items
+}
+
+// the program without function id, along with the calls of it
+fn without_function(functions: &[Function], id: usize) -> Vec<Function> {
+    functions
+        .iter()
+        .enumerate()
+        .filter(|(other, _)| *other != id)
+        .map(|(_, function)| {
+            let instructions = function
+                .get_instructions()
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::CallId(CallId {
+                        opcode: CallIdOpcode::Call,
+                        identifier,
+                    }) => match (*identifier as usize).cmp(&id) {
+                        std::cmp::Ordering::Less => Some(instruction.clone()),
+                        std::cmp::Ordering::Equal => None,
+                        std::cmp::Ordering::Greater => Some(Instruction::CallId(CallId {
+                            opcode: CallIdOpcode::Call,
+                            identifier: identifier - 1,
+                        })),
+                    },
+                    instruction => Some(instruction.clone()),
+                })
+                .collect();
+            with_forward_branches(function, instructions)
+        })
+        .collect()
+}
+
+// the function with other instructions, leaving out branches that don't jump
+// forward; removing a target can leave a branch pointing backward to an
+// earlier target with the same identifier, which would loop forever
+fn with_forward_branches(function: &Function, instructions: Vec<Instruction>) -> Function {
+    let targets = Function::targets(&instructions);
+    let instructions = instructions
+        .into_iter()
+        .enumerate()
+        .filter(|(index, instruction)| match instruction {
+            Instruction::Branch(branch) => targets
+                .get(&branch.target)
+                .is_some_and(|target| target > index),
+            _ => true,
+        })
+        .map(|(_, instruction)| instruction)
+        .collect();
+    function.with_instructions(instructions)
+}
+
+// values closer to 0 to try instead of value
+fn smaller_numbers(value: i64) -> Vec<i64> {
+    let mut numbers = vec![0, value / 2, value - value.signum()];
+    numbers.retain(|number| number.abs() < value.abs());
+    numbers.dedup();
+    numbers
+}
+
+// the instruction with one of its operands made smaller, in each way that's
+// possible
+fn smaller_instructions(instruction: &Instruction) -> Vec<Instruction> {
+    fn each(value: impl Into<i64>, mut with: impl FnMut(i64) -> Instruction) -> Vec<Instruction> {
+        smaller_numbers(value.into())
+            .into_iter()
+            .map(&mut with)
+            .collect()
+    }
+    match instruction {
+        Instruction::Immediate(immediate) => [
+            each(immediate.value, |value| {
+                Instruction::Immediate(Immediate {
+                    value: value as i16,
+                    ..immediate.clone()
+                })
+            }),
+            each(immediate.rs, |rs| {
+                Instruction::Immediate(Immediate {
+                    rs: rs as u8,
+                    ..immediate.clone()
+                })
+            }),
+            each(immediate.rd, |rd| {
+                Instruction::Immediate(Immediate {
+                    rd: rd as u8,
+                    ..immediate.clone()
+                })
+            }),
+        ]
+        .concat(),
+        Instruction::Register(register) => [
+            each(register.rs1, |rs1| {
+                Instruction::Register(Register {
+                    rs1: rs1 as u8,
+                    ..register.clone()
+                })
+            }),
+            each(register.rs2, |rs2| {
+                Instruction::Register(Register {
+                    rs2: rs2 as u8,
+                    ..register.clone()
+                })
+            }),
+            each(register.rd, |rd| {
+                Instruction::Register(Register {
+                    rd: rd as u8,
+                    ..register.clone()
+                })
+            }),
+        ]
+        .concat(),
+        Instruction::Load(load) => [
+            each(load.offset, |offset| {
+                Instruction::Load(Load {
+                    offset: offset as u16,
+                    ..load.clone()
+                })
+            }),
+            each(load.rs, |rs| {
+                Instruction::Load(Load {
+                    rs: rs as u8,
+                    ..load.clone()
+                })
+            }),
+            each(load.rd, |rd| {
+                Instruction::Load(Load {
+                    rd: rd as u8,
+                    ..load.clone()
+                })
+            }),
+        ]
+        .concat(),
+        Instruction::Store(store) => [
+            each(store.offset, |offset| {
+                Instruction::Store(Store {
+                    offset: offset as u16,
+                    ..store.clone()
+                })
+            }),
+            each(store.rs, |rs| {
+                Instruction::Store(Store {
+                    rs: rs as u8,
+                    ..store.clone()
+                })
+            }),
+            each(store.rd, |rd| {
+                Instruction::Store(Store {
+                    rd: rd as u8,
+                    ..store.clone()
+                })
+            }),
+        ]
+        .concat(),
+        Instruction::Branch(branch) => [
+            each(branch.rs1, |rs1| {
+                Instruction::Branch(Branch {
+                    rs1: rs1 as u8,
+                    ..branch.clone()
+                })
+            }),
+            each(branch.rs2, |rs2| {
+                Instruction::Branch(Branch {
+                    rs2: rs2 as u8,
+                    ..branch.clone()
+                })
+            }),
+        ]
+        .concat(),
+        Instruction::Ecall(ecall) => each(ecall.number, |number| {
+            Instruction::Ecall(Ecall {
+                number: number as u16,
+                ..ecall.clone()
+            })
+        }),
+        Instruction::Random(random) => each(random.rd, |rd| {
+            Instruction::Random(Random {
+                rd: rd as u8,
+                ..random.clone()
+            })
+        }),
+        Instruction::BranchTarget(_) | Instruction::CallId(_) => Vec::new(),
+    }
+}
@@ -0,0 +1,287 @@
+use crate::lang::{
+    BranchOpcode, BranchTargetOpcode, CallIdOpcode, EcallOpcode, ImmediateOpcode, LoadOpcode,
+    RandomOpcode, RegisterOpcode, StoreOpcode, OPCODE_COUNT,
+};
+use crate::program::Program;
+use std::io::{self, Write};
+use strum::IntoEnumIterator;
+
+/// How often each byte of memory was read and written, recorded when the
+/// machine has `profile_memory` set. Only loads and stores that go ahead are
+/// recorded, and a halfword access counts for both of its bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryProfile {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl MemoryProfile {
+    pub fn new(len: usize) -> MemoryProfile {
+        MemoryProfile {
+            reads: vec![0; len],
+            writes: vec![0; len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+
+    pub fn reads(&self) -> &[u64] {
+        &self.reads
+    }
+
+    pub fn writes(&self) -> &[u64] {
+        &self.writes
+    }
+
+    pub fn record_read(&mut self, index: usize) {
+        self.reads[index] += 1;
+    }
+
+    pub fn record_write(&mut self, index: usize) {
+        self.writes[index] += 1;
+    }
+
+    // make room for a memory of len bytes, keeping what's recorded
+    pub(crate) fn grow(&mut self, len: usize) {
+        if len > self.len() {
+            self.reads.resize(len, 0);
+            self.writes.resize(len, 0);
+        }
+    }
+
+    #[cfg(feature = "llvm")]
+    pub(crate) fn buffers(&mut self) -> (*mut u64, *mut u64) {
+        (self.reads.as_mut_ptr(), self.writes.as_mut_ptr())
+    }
+
+    /// Write the profile as CSV, with a line per address.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "address,reads,writes")?;
+        for (address, (reads, writes)) in self.reads.iter().zip(&self.writes).enumerate() {
+            writeln!(writer, "{},{},{}", address, reads, writes)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = Vec::new();
+        self.write_csv(&mut csv).unwrap();
+        String::from_utf8(csv).unwrap()
+    }
+
+    /// Write a heatmap of the reads and writes of each address as a binary
+    /// PGM image, `width` addresses per row. The most accessed address is
+    /// white, and a last row that isn't full is padded with black.
+    pub fn write_pgm(&self, writer: &mut impl Write, width: usize) -> io::Result<()> {
+        let width = width.max(1);
+        let height = self.len().div_ceil(width);
+        let accesses: Vec<u64> = self
+            .reads
+            .iter()
+            .zip(&self.writes)
+            .map(|(reads, writes)| reads + writes)
+            .collect();
+        let max = accesses.iter().copied().max().unwrap_or(0).max(1);
+        write!(writer, "P5\n{} {}\n255\n", width, height)?;
+        let mut pixels: Vec<u8> = accesses
+            .iter()
+            .map(|accesses| (*accesses as u128 * 255 / max as u128) as u8)
+            .collect();
+        pixels.resize(width * height, 0);
+        writer.write_all(&pixels)
+    }
+}
+
+/// How often each opcode and each instruction was executed, and how often
+/// branches were taken, recorded when the machine has `profile_execution` set.
+///
+/// Instructions are identified by the id of their function and their index in
+/// it, and are only counted for the functions the profile was fitted to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionProfile {
+    opcodes: Vec<u64>,
+    // where the counts of each function start in instructions and taken, with
+    // the end of the last function at the end
+    offsets: Vec<u64>,
+    instructions: Vec<u64>,
+    taken: Vec<u64>,
+}
+
+impl Default for ExecutionProfile {
+    fn default() -> Self {
+        ExecutionProfile::new()
+    }
+}
+
+impl ExecutionProfile {
+    pub fn new() -> ExecutionProfile {
+        ExecutionProfile {
+            opcodes: vec![0; OPCODE_COUNT],
+            offsets: vec![0],
+            instructions: Vec::new(),
+            taken: Vec::new(),
+        }
+    }
+
+    /// Make room for the instructions of program, keeping what's recorded.
+    /// Runs of an interpreted program do this by themselves, while compiled
+    /// code only counts instructions the profile has room for.
+    pub fn fit(&mut self, program: &Program) {
+        let lens: Vec<usize> = program
+            .functions()
+            .iter()
+            .map(|function| function.get_instructions().len())
+            .collect();
+        let functions = self.functions().max(lens.len());
+        let fitted = (0..functions).all(|id| lens.get(id).copied().unwrap_or(0) <= self.len(id));
+        if fitted {
+            return;
+        }
+        let mut profile = ExecutionProfile {
+            opcodes: std::mem::take(&mut self.opcodes),
+            ..ExecutionProfile::new()
+        };
+        for id in 0..functions {
+            let len = self.len(id).max(lens.get(id).copied().unwrap_or(0));
+            let start = self.offsets.get(id).copied().unwrap_or(0) as usize;
+            for pc in 0..len {
+                let (instructions, taken) = if pc < self.len(id) {
+                    (self.instructions[start + pc], self.taken[start + pc])
+                } else {
+                    (0, 0)
+                };
+                profile.instructions.push(instructions);
+                profile.taken.push(taken);
+            }
+            profile.offsets.push(profile.instructions.len() as u64);
+        }
+        *self = profile;
+    }
+
+    fn functions(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    // the amount of instructions of function id there's room for
+    fn len(&self, id: usize) -> usize {
+        if id < self.functions() {
+            (self.offsets[id + 1] - self.offsets[id]) as usize
+        } else {
+            0
+        }
+    }
+
+    fn slot(&self, function: u16, pc: usize) -> Option<usize> {
+        let id = function as usize;
+        (pc < self.len(id)).then(|| self.offsets[id] as usize + pc)
+    }
+
+    pub(crate) fn record(&mut self, function: u16, pc: usize, opcode: u8, taken: bool) {
+        self.opcodes[opcode as usize] += 1;
+        if let Some(slot) = self.slot(function, pc) {
+            self.instructions[slot] += 1;
+            if taken {
+                self.taken[slot] += 1;
+            }
+        }
+    }
+
+    /// The amount of times each opcode was executed, indexed by the byte the
+    /// opcode is serialized as.
+    pub fn opcodes(&self) -> &[u64] {
+        &self.opcodes
+    }
+
+    /// The amount of times each opcode was executed, by name.
+    pub fn opcode_counts(&self) -> Vec<(String, u64)> {
+        opcode_names()
+            .into_iter()
+            .zip(self.opcodes.iter().copied())
+            .collect()
+    }
+
+    /// The amount of times the instruction at pc in a function was executed.
+    pub fn count(&self, function: u16, pc: usize) -> u64 {
+        self.slot(function, pc)
+            .map(|slot| self.instructions[slot])
+            .unwrap_or(0)
+    }
+
+    /// The amount of times the branch at pc in a function was taken.
+    pub fn taken(&self, function: u16, pc: usize) -> u64 {
+        self.slot(function, pc)
+            .map(|slot| self.taken[slot])
+            .unwrap_or(0)
+    }
+
+    /// The amount of times the branch at pc in a function wasn't taken.
+    pub fn not_taken(&self, function: u16, pc: usize) -> u64 {
+        self.count(function, pc) - self.taken(function, pc)
+    }
+
+    /// The fraction of executions in which the branch at pc in a function was
+    /// taken, or None if it was never executed.
+    pub fn taken_ratio(&self, function: u16, pc: usize) -> Option<f64> {
+        let count = self.count(function, pc);
+        (count > 0).then(|| self.taken(function, pc) as f64 / count as f64)
+    }
+
+    /// The instructions of program that were never executed, as function id
+    /// and pc.
+    pub fn unexecuted(&self, program: &Program) -> Vec<(u16, usize)> {
+        program
+            .functions()
+            .iter()
+            .enumerate()
+            .flat_map(|(id, function)| {
+                let id = id as u16;
+                (0..function.get_instructions().len())
+                    .filter(move |pc| self.count(id, *pc) == 0)
+                    .map(move |pc| (id, pc))
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "llvm")]
+    pub(crate) fn buffers(&mut self) -> ExecutionBuffers {
+        ExecutionBuffers {
+            opcodes: self.opcodes.as_mut_ptr(),
+            functions: self.functions() as u64,
+            offsets: self.offsets.as_ptr(),
+            instructions: self.instructions.as_mut_ptr(),
+            taken: self.taken.as_mut_ptr(),
+        }
+    }
+}
+
+#[cfg(feature = "llvm")]
+pub(crate) struct ExecutionBuffers {
+    pub(crate) opcodes: *mut u64,
+    pub(crate) functions: u64,
+    pub(crate) offsets: *const u64,
+    pub(crate) instructions: *mut u64,
+    pub(crate) taken: *mut u64,
+}
+
+// the lowercase names of the opcodes, in the order they're serialized in
+fn opcode_names() -> Vec<String> {
+    fn names<T: IntoEnumIterator + ToString>() -> impl Iterator<Item = String> {
+        T::iter().map(|opcode| opcode.to_string().to_lowercase())
+    }
+    names::<ImmediateOpcode>()
+        .chain(names::<RegisterOpcode>())
+        .chain(names::<LoadOpcode>())
+        .chain(names::<StoreOpcode>())
+        .chain(names::<BranchOpcode>())
+        .chain(names::<BranchTargetOpcode>())
+        .chain(names::<CallIdOpcode>())
+        .chain(names::<EcallOpcode>())
+        .chain(names::<RandomOpcode>())
+        .collect()
+}
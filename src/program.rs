@@ -1,11 +1,21 @@
+#[cfg(feature = "llvm")]
 use crate::cache::FunctionValueCache;
+use crate::cost::{CostAnalysis, CostBounds};
 use crate::function::Function;
-use crate::lang::{Instruction, Processor};
-use crate::llvm::CodeGen;
-use crate::llvm::ProgramFunc;
+use crate::lang::{CallId, CallIdOpcode, Frame, Instruction, Processor};
+#[cfg(feature = "llvm")]
+use crate::llvm::{CodeGen, ProgramFunc};
+use crate::simplify::{simplify, SimplifyReport};
+#[cfg(feature = "llvm")]
 use inkwell::execution_engine::JitFunction;
 use rustc_hash::{FxHashMap, FxHashSet};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Finished,
+    Paused,
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Program {
     functions: Vec<Function>,
@@ -40,11 +50,11 @@ impl Program {
     }
 
     fn clean_calls_helper(&mut self, call_id: u16, seen: &FxHashSet<u16>) {
-        let function = &self.functions[call_id as usize];
-        let converted_function = function.cleanup_calls(&self.functions, seen);
-
         let mut seen = seen.clone();
         seen.insert(call_id);
+
+        let function = &self.functions[call_id as usize];
+        let converted_function = function.cleanup_calls(&self.functions, &seen);
         for sub_call_id in converted_function.get_call_id_set() {
             self.clean_calls_helper(sub_call_id, &seen);
         }
@@ -89,22 +99,164 @@ impl Program {
     }
 
     pub fn interpret_with_processor(&self, memory: &mut [u8], processor: &mut Processor) {
+        self.start_run(memory, processor);
         self.call(memory, processor, 0);
     }
 
+    fn start_run(&self, memory: &[u8], processor: &mut Processor) {
+        processor.start_run(memory.len());
+        if processor.options().profile_execution {
+            processor.execution_profile_mut().fit(self);
+        }
+    }
+
+    /// Interpret at most `budget` instructions. If the budget runs out before
+    /// the program finishes the run is paused in the processor, and the next
+    /// call continues it from exactly the same point. Otherwise a new run is
+    /// started. A paused run that doesn't fit this program is discarded.
+    pub fn interpret_with_budget(
+        &self,
+        memory: &mut [u8],
+        processor: &mut Processor,
+        budget: u64,
+    ) -> RunStatus {
+        if processor
+            .call_stack()
+            .iter()
+            .any(|frame| frame.function as usize >= self.functions.len())
+        {
+            processor.call_stack_mut().clear();
+        }
+        if processor.is_paused() {
+            // the base may have changed since the run was paused
+            processor.fit_base(memory.len());
+        } else {
+            self.start_run(memory, processor);
+            processor.call_stack_mut().push(Frame {
+                function: 0,
+                pc: 0,
+                iteration: 0,
+            });
+        }
+        processor.clear_zero_register();
+        let mut targets = vec![None; self.functions.len()];
+        let mut budget = budget;
+        loop {
+            let frame = match processor.call_stack().last() {
+                Some(frame) => *frame,
+                None => return RunStatus::Finished,
+            };
+            let function = &self.functions[frame.function as usize];
+            let instructions = function.get_instructions();
+            processor.set_pc(frame.pc);
+            if frame.pc >= instructions.len() {
+                let call_stack = processor.call_stack_mut();
+                let iteration = frame.iteration + 1;
+                if iteration < function.get_repeat() {
+                    *call_stack.last_mut().unwrap() = Frame {
+                        pc: 0,
+                        iteration,
+                        ..frame
+                    };
+                } else {
+                    call_stack.pop();
+                }
+                continue;
+            }
+            if budget == 0 {
+                return RunStatus::Paused;
+            }
+            budget -= 1;
+            let instruction = &instructions[frame.pc];
+            match instruction {
+                Instruction::CallId(CallId {
+                    opcode: CallIdOpcode::Call,
+                    identifier,
+                }) => {
+                    processor.profile_instruction(frame.function, frame.pc, instruction, false);
+                    let call_stack = processor.call_stack_mut();
+                    call_stack.last_mut().unwrap().pc += 1;
+                    call_stack.push(Frame {
+                        function: *identifier,
+                        pc: 0,
+                        iteration: 0,
+                    });
+                }
+                instruction => {
+                    let function_targets = targets[frame.function as usize]
+                        .get_or_insert_with(|| Function::targets(instructions));
+                    processor.set_function(frame.function);
+                    instruction.execute(processor, memory, function_targets, &self.functions);
+                    processor.clear_zero_register();
+                    let jumped = processor.take_jumped();
+                    processor.profile_instruction(frame.function, frame.pc, instruction, jumped);
+                    let pc = if jumped { processor.pc() } else { frame.pc + 1 };
+                    processor.call_stack_mut().last_mut().unwrap().pc = pc;
+                }
+            }
+        }
+    }
+
+    /// Bounds on the instructions a run executes. A budget of at least
+    /// `cost_bounds().max_bound` lets `interpret_with_budget` finish in one go.
+    pub fn cost_bounds(&self) -> CostBounds {
+        CostAnalysis::new(self).program()
+    }
+
+    /// A program with the same effect on memory without dead register
+    /// writes, no-ops, unused targets, branches to the next instruction and
+    /// functions that aren't called. The registers when the program finishes
+    /// may differ, as may what profiles and violation counts record.
+    pub fn simplify(&self) -> (Program, SimplifyReport) {
+        simplify(self)
+    }
+
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
     pub fn get_function(&self, id: u16) -> &Function {
         &self.functions[id as usize]
     }
 
+    /// The call ids of the functions reachable from the main function, with
+    /// each function after the functions it calls.
+    pub fn reachable_call_ids(&self) -> Vec<u16> {
+        fn visit(program: &Program, call_id: u16, result: &mut Vec<u16>) {
+            if result.contains(&call_id) {
+                return;
+            }
+            for dependency_call_id in program.get_function(call_id).get_call_ids() {
+                visit(program, dependency_call_id, result);
+            }
+            result.push(call_id);
+        }
+        let mut result = Vec::new();
+        visit(self, 0, &mut result);
+        result
+    }
+
+    /// Whether a function reachable from the main function does an `ecall`.
+    pub fn calls_host_functions(&self) -> bool {
+        self.reachable_call_ids().into_iter().any(|call_id| {
+            self.get_function(call_id)
+                .get_instructions()
+                .iter()
+                .any(|instruction| matches!(instruction, Instruction::Ecall(_)))
+        })
+    }
+
     pub fn call(&self, memory: &mut [u8], processor: &mut Processor, id: usize) {
+        processor.set_function(id as u16);
         self.functions[id].interpret(memory, processor, &self.functions);
     }
 
+    #[cfg(feature = "llvm")]
     pub fn compile<'ctx>(
         &'ctx self,
         program_id: usize,
         codegen: &'ctx CodeGen,
-        memory_size: u16,
+        memory_size: u32,
         cache: &mut FunctionValueCache<'ctx>,
     ) -> JitFunction<ProgramFunc> {
         let dependency_map = cache.compile(0, self, codegen, memory_size);
@@ -172,6 +324,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_call_ids_no_recursion_in_called_function() {
+        let program = Program::new(&[
+            (
+                0,
+                &[Instruction::CallId(CallId {
+                    opcode: CallIdOpcode::Call,
+                    identifier: 1,
+                })],
+            ),
+            (
+                0,
+                &[Instruction::CallId(CallId {
+                    opcode: CallIdOpcode::Call,
+                    identifier: 1,
+                })],
+            ),
+        ]);
+
+        assert_eq!(
+            program.functions,
+            vec![
+                Function::new(
+                    "unknown".to_string(),
+                    &[Instruction::CallId(CallId {
+                        opcode: CallIdOpcode::Call,
+                        identifier: 1
+                    }),],
+                    0
+                ),
+                Function::new("unknown".to_string(), &[], 0),
+            ]
+        );
+    }
+
     #[test]
     fn test_call_ids_no_indirect_multiple_calls() {
         let program = Program::new(&[
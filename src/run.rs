@@ -1,43 +1,163 @@
+#[cfg(feature = "llvm")]
 use crate::cache::FunctionValueCache;
+#[cfg(feature = "cranelift")]
+use crate::cranelift::CraneliftCodeGen;
+#[cfg(feature = "llvm")]
 use crate::function::Function;
-use crate::lang::Instruction;
+use crate::lang::{Instruction, MachineOptions, Processor};
+#[cfg(feature = "llvm")]
 use crate::llvm::CodeGen;
 use crate::program::Program;
+#[cfg(feature = "llvm")]
 use inkwell::context::Context;
 
 pub type Run = fn(&Program, &mut [u8]);
+pub type RunWithRegisters = fn(&Program, &mut [u8], &mut [i16; 32]);
+pub type RunWithSeed = fn(&Program, &mut [u8], &mut u32);
+pub type RunWithProcessor = fn(&Program, &mut [u8], &mut Processor);
 pub type Runner = fn(&[(u8, &[Instruction])], &mut [u8]);
 pub type RunnerFunc = fn(&[Instruction], &mut [u8]);
 pub type RunnerProgram = fn(&[&[Instruction]], &mut [u8]);
+pub type RunnerFuncWithOptions = fn(&[Instruction], &mut [u8], MachineOptions);
 
 pub fn interpreted(program: &Program, memory: &mut [u8]) {
     program.interpret(memory);
 }
 
+#[cfg(feature = "llvm")]
 pub fn compiled(program: &Program, memory: &mut [u8]) {
     let context = Context::create();
     let codegen = CodeGen::new(&context);
     let mut cache = FunctionValueCache::new();
-    let func = program.compile(0, &codegen, memory.len() as u16, &mut cache);
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
     codegen.module.verify().unwrap();
     Function::run(&func, memory);
 }
 
+#[cfg(feature = "cranelift")]
+pub fn cranelift_compiled(program: &Program, memory: &mut [u8]) {
+    let mut codegen = CraneliftCodeGen::new();
+    let func = codegen.compile_program(program, memory.len() as u32);
+    func.run(memory);
+}
+
+pub fn interpreted_with_registers(program: &Program, memory: &mut [u8], registers: &mut [i16; 32]) {
+    let mut processor = Processor::with_registers(*registers);
+    program.interpret_with_processor(memory, &mut processor);
+    *registers = *processor.registers();
+}
+
+#[cfg(feature = "llvm")]
+pub fn compiled_with_registers(program: &Program, memory: &mut [u8], registers: &mut [i16; 32]) {
+    let context = Context::create();
+    let codegen = CodeGen::new(&context);
+    let mut cache = FunctionValueCache::new();
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
+    codegen.module.verify().unwrap();
+    Function::run_with_registers(&func, memory, registers);
+}
+
+#[cfg(feature = "cranelift")]
+pub fn cranelift_compiled_with_registers(
+    program: &Program,
+    memory: &mut [u8],
+    registers: &mut [i16; 32],
+) {
+    let mut codegen = CraneliftCodeGen::new();
+    let func = codegen.compile_program(program, memory.len() as u32);
+    func.run_with_registers(memory, registers);
+}
+
+pub fn interpreted_with_seed(program: &Program, memory: &mut [u8], rng_state: &mut u32) {
+    let mut processor = Processor::new();
+    processor.set_rng_state(*rng_state);
+    program.interpret_with_processor(memory, &mut processor);
+    *rng_state = processor.rng_state();
+}
+
+#[cfg(feature = "llvm")]
+pub fn compiled_with_seed(program: &Program, memory: &mut [u8], rng_state: &mut u32) {
+    let context = Context::create();
+    let codegen = CodeGen::new(&context);
+    let mut cache = FunctionValueCache::new();
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
+    codegen.module.verify().unwrap();
+    Function::run_with_state(&func, memory, &mut [0; 32], rng_state, &[]);
+}
+
+#[cfg(feature = "cranelift")]
+pub fn cranelift_compiled_with_seed(program: &Program, memory: &mut [u8], rng_state: &mut u32) {
+    let mut codegen = CraneliftCodeGen::new();
+    let func = codegen.compile_program(program, memory.len() as u32);
+    func.run_with_state(memory, &mut [0; 32], rng_state, &[]);
+}
+
+pub fn interpreted_with_processor(program: &Program, memory: &mut [u8], processor: &mut Processor) {
+    program.interpret_with_processor(memory, processor);
+}
+
+#[cfg(feature = "llvm")]
+pub fn compiled_with_processor(program: &Program, memory: &mut [u8], processor: &mut Processor) {
+    assert_compilable(processor);
+    let context = Context::create();
+    let mut codegen = CodeGen::with_options(&context, processor.options());
+    codegen.set_protection(processor.protection().clone());
+    let mut cache = FunctionValueCache::new();
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
+    codegen.module.verify().unwrap();
+    if processor.options().profile_execution {
+        processor.execution_profile_mut().fit(program);
+    }
+    Function::run_with_processor(&func, memory, processor, &[]);
+}
+
+// the Cranelift backend doesn't profile, so this panics for a processor that
+// does
+#[cfg(feature = "cranelift")]
+pub fn cranelift_compiled_with_processor(
+    program: &Program,
+    memory: &mut [u8],
+    processor: &mut Processor,
+) {
+    assert_compilable(processor);
+    let mut codegen = CraneliftCodeGen::with_options(processor.options()).unwrap();
+    codegen.set_protection(processor.protection().clone());
+    let func = codegen.compile_program(program, memory.len() as u32);
+    func.run_with_processor(memory, processor, &[]);
+}
+
+// compiled code only calls host functions it's given as `HostFunc`, not those
+// of the processor
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+fn assert_compilable(processor: &Processor) {
+    assert!(
+        processor.host_functions().is_empty(),
+        "compiled code can't call the host functions of a processor"
+    );
+}
+
 pub fn run_interpreter(funcs: &[(u8, &[Instruction])], memory: &mut [u8]) {
     let program = Program::new(funcs);
     program.interpret(memory);
 }
 
+#[cfg(feature = "llvm")]
 pub fn run_llvm(funcs: &[(u8, &[Instruction])], memory: &mut [u8]) {
     let program = Program::new(funcs);
     let context = Context::create();
     let codegen = CodeGen::new(&context);
     let mut cache = FunctionValueCache::new();
-    let func = program.compile(0, &codegen, memory.len() as u16, &mut cache);
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
     codegen.module.verify().unwrap();
     Function::run(&func, memory);
 }
 
+#[cfg(feature = "cranelift")]
+pub fn run_cranelift(funcs: &[(u8, &[Instruction])], memory: &mut [u8]) {
+    let program = Program::new(funcs);
+    cranelift_compiled(&program, memory);
+}
+
 fn repeat_0<'a>(funcs: &'a [&'a [Instruction]]) -> Vec<(u8, &'a [Instruction])> {
     funcs.iter().map(|f| (0, *f)).collect()
 }
@@ -46,10 +166,17 @@ pub fn run_interpreter_program(funcs: &[&[Instruction]], memory: &mut [u8]) {
     run_interpreter(&repeat_0(funcs), memory);
 }
 
+#[cfg(feature = "llvm")]
 pub fn run_llvm_program(funcs: &[&[Instruction]], memory: &mut [u8]) {
     run_llvm(&repeat_0(funcs), memory);
 }
 
+#[cfg(feature = "cranelift")]
+pub fn run_cranelift_program(funcs: &[&[Instruction]], memory: &mut [u8]) {
+    run_cranelift(&repeat_0(funcs), memory);
+}
+
+#[cfg(feature = "llvm")]
 pub fn run_llvm_func(instructions: &[Instruction], memory: &mut [u8]) {
     run_llvm_program(&[instructions], memory);
 }
@@ -57,3 +184,45 @@ pub fn run_llvm_func(instructions: &[Instruction], memory: &mut [u8]) {
 pub fn run_interpreter_func(instructions: &[Instruction], memory: &mut [u8]) {
     run_interpreter_program(&[instructions], memory);
 }
+
+#[cfg(feature = "cranelift")]
+pub fn run_cranelift_func(instructions: &[Instruction], memory: &mut [u8]) {
+    run_cranelift_program(&[instructions], memory);
+}
+
+pub fn run_interpreter_func_with_options(
+    instructions: &[Instruction],
+    memory: &mut [u8],
+    options: MachineOptions,
+) {
+    let program = Program::new(&[(0, instructions)]);
+    let mut processor = Processor::with_options(options);
+    program.interpret_with_processor(memory, &mut processor);
+}
+
+#[cfg(feature = "llvm")]
+pub fn run_llvm_func_with_options(
+    instructions: &[Instruction],
+    memory: &mut [u8],
+    options: MachineOptions,
+) {
+    let program = Program::new(&[(0, instructions)]);
+    let context = Context::create();
+    let codegen = CodeGen::with_options(&context, options);
+    let mut cache = FunctionValueCache::new();
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
+    codegen.module.verify().unwrap();
+    Function::run(&func, memory);
+}
+
+#[cfg(feature = "cranelift")]
+pub fn run_cranelift_func_with_options(
+    instructions: &[Instruction],
+    memory: &mut [u8],
+    options: MachineOptions,
+) {
+    let program = Program::new(&[(0, instructions)]);
+    let mut codegen = CraneliftCodeGen::with_options(options).unwrap();
+    let func = codegen.compile_program(&program, memory.len() as u32);
+    func.run(memory);
+}
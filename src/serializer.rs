@@ -1,6 +1,7 @@
 use crate::lang::{
-    Branch, BranchOpcode, BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode, Immediate,
-    ImmediateOpcode, Instruction, Load, LoadOpcode, Register, RegisterOpcode, Store, StoreOpcode,
+    Branch, BranchOpcode, BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode, Ecall,
+    EcallOpcode, Immediate, ImmediateOpcode, Instruction, Load, LoadOpcode, Random, RandomOpcode,
+    Register, RegisterOpcode, Store, StoreOpcode,
 };
 use byteorder::{ByteOrder, LittleEndian};
 use num::{FromPrimitive, ToPrimitive};
@@ -13,6 +14,8 @@ enum OpcodeWithType {
     Branch(BranchOpcode),
     BranchTarget(BranchTargetOpcode),
     CallId(CallIdOpcode),
+    Ecall(EcallOpcode),
+    Random(RandomOpcode),
     // Switch(SwitchOpcode),
 }
 
@@ -26,6 +29,8 @@ impl OpcodeWithType {
             OpcodeWithType::Branch(_opcode) => Branch::size(),
             OpcodeWithType::BranchTarget(_opcode) => BranchTarget::size(),
             OpcodeWithType::CallId(_opcode) => CallId::size(),
+            OpcodeWithType::Ecall(_opcode) => Ecall::size(),
+            OpcodeWithType::Random(_opcode) => Random::size(),
             // OpcodeWithType::Switch(_opcode) => Switch::size(),
         }
     }
@@ -50,6 +55,12 @@ impl OpcodeWithType {
             }
             OpcodeWithType::CallId(opcode) => {
                 Instruction::CallId(CallId::deserialize(*opcode, values))
+            }
+            OpcodeWithType::Ecall(opcode) => {
+                Instruction::Ecall(Ecall::deserialize(*opcode, values))
+            }
+            OpcodeWithType::Random(opcode) => {
+                Instruction::Random(Random::deserialize(*opcode, values))
             } // OpcodeWithType::Switch(opcode) => {
               //     Instruction::Switch(Switch::deserialize(*opcode, values))
               // }
@@ -67,6 +78,8 @@ impl From<&Instruction> for u8 {
             Instruction::Branch(Branch { opcode, .. }) => opcode.to_u8().unwrap(),
             Instruction::BranchTarget(BranchTarget { opcode, .. }) => opcode.to_u8().unwrap(),
             Instruction::CallId(CallId { opcode, .. }) => opcode.to_u8().unwrap(),
+            Instruction::Ecall(Ecall { opcode, .. }) => opcode.to_u8().unwrap(),
+            Instruction::Random(Random { opcode, .. }) => opcode.to_u8().unwrap(),
             // Instruction::Switch(Switch { opcode, .. }) => opcode.to_u8().unwrap(),
         }
     }
@@ -81,6 +94,8 @@ fn decode_opcode(value: u8) -> Option<OpcodeWithType> {
         .or_else(|| BranchOpcode::from_u8(value).map(OpcodeWithType::Branch))
         .or_else(|| BranchTargetOpcode::from_u8(value).map(OpcodeWithType::BranchTarget))
         .or_else(|| CallIdOpcode::from_u8(value).map(OpcodeWithType::CallId))
+        .or_else(|| EcallOpcode::from_u8(value).map(OpcodeWithType::Ecall))
+        .or_else(|| RandomOpcode::from_u8(value).map(OpcodeWithType::Random))
     // .or_else(|| SwitchOpcode::from_u8(value).map(OpcodeWithType::Switch))
 }
 
@@ -107,6 +122,8 @@ impl ValueSerializer for Instruction {
             Branch(branch) => branch.serialize(output),
             BranchTarget(branch_target) => branch_target.serialize(output),
             CallId(call_id) => call_id.serialize(output),
+            Ecall(ecall) => ecall.serialize(output),
+            Random(random) => random.serialize(output),
             // Switch(switch) => switch.serialize(output),
         }
     }
@@ -324,6 +341,42 @@ impl ValueSerializer for CallId {
     }
 }
 
+impl ValueDeserializer<EcallOpcode> for Ecall {
+    fn size() -> usize {
+        2
+    }
+    fn deserialize(opcode: EcallOpcode, input: &[u8]) -> Self {
+        Ecall {
+            opcode,
+            number: bytes_to_u16(&input[0..2]),
+        }
+    }
+}
+
+impl ValueSerializer for Ecall {
+    fn serialize(&self, output: &mut Vec<u8>) {
+        output.extend(u16_to_bytes(self.number));
+    }
+}
+
+impl ValueDeserializer<RandomOpcode> for Random {
+    fn size() -> usize {
+        1
+    }
+    fn deserialize(opcode: RandomOpcode, input: &[u8]) -> Self {
+        Random {
+            opcode,
+            rd: clampreg(input[0]),
+        }
+    }
+}
+
+impl ValueSerializer for Random {
+    fn serialize(&self, output: &mut Vec<u8>) {
+        output.push(self.rd);
+    }
+}
+
 fn i16_to_bytes(value: i16) -> [u8; 2] {
     let mut buffer = [0u8; 2];
     LittleEndian::write_i16(&mut buffer, value);
@@ -424,6 +477,14 @@ mod tests {
                 opcode: CallIdOpcode::Call,
                 identifier: 0,
             }),
+            Instruction::Ecall(Ecall {
+                opcode: EcallOpcode::Ecall,
+                number: 300,
+            }),
+            Instruction::Random(Random {
+                opcode: RandomOpcode::Rand,
+                rd: 2,
+            }),
         ];
 
         let bytes = serializer.serialize(&instructions);
@@ -0,0 +1,264 @@
+use crate::function::Function;
+use crate::lang::{
+    Branch, BranchTarget, CallId, CallIdOpcode, Immediate, ImmediateOpcode, Instruction, Load,
+    Random, Register, RegisterOpcode,
+};
+use crate::program::Program;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+// a set of registers, with a bit for each
+type Registers = u32;
+
+const ALL_REGISTERS: Registers = u32::MAX;
+
+/// How much `Program::simplify` shrunk a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimplifyReport {
+    pub functions_before: usize,
+    pub functions_after: usize,
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+}
+
+impl SimplifyReport {
+    pub fn removed_instructions(&self) -> usize {
+        self.instructions_before - self.instructions_after
+    }
+
+    /// The size of the simplified program relative to the original, in
+    /// instructions. A program without instructions has a ratio of 1.
+    pub fn ratio(&self) -> f64 {
+        if self.instructions_before == 0 {
+            1.0
+        } else {
+            self.instructions_after as f64 / self.instructions_before as f64
+        }
+    }
+}
+
+pub(crate) fn simplify(program: &Program) -> (Program, SimplifyReport) {
+    let mut functions: Vec<Function> = program.functions().to_vec();
+    loop {
+        let simplified: Vec<Function> = remove_unreachable(functions.clone())
+            .iter()
+            .enumerate()
+            .map(|(id, function)| simplify_function(function, id == 0))
+            .collect();
+        let simplified = remove_empty_calls(simplified);
+        if simplified == functions {
+            break;
+        }
+        functions = simplified;
+    }
+    let report = SimplifyReport {
+        functions_before: program.functions().len(),
+        functions_after: functions.len(),
+        instructions_before: instruction_count(program.functions()),
+        instructions_after: instruction_count(&functions),
+    };
+    (Program::from_functions(functions), report)
+}
+
+fn instruction_count(functions: &[Function]) -> usize {
+    functions
+        .iter()
+        .map(|function| function.get_instructions().len())
+        .sum()
+}
+
+// simplify a function on its own until there's nothing left to remove
+fn simplify_function(function: &Function, is_main: bool) -> Function {
+    // the registers of main are gone once the program finishes, while those
+    // of other functions may be read by the caller
+    let exit_live = if is_main { 0 } else { ALL_REGISTERS };
+    let mut instructions = function.get_instructions().to_vec();
+    loop {
+        let live = live_out(&instructions, function.get_repeat(), exit_live);
+        let targets = Function::targets(&instructions);
+        let used_targets: FxHashSet<u8> = instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Branch(Branch { target, .. }) => Some(*target),
+                _ => None,
+            })
+            .collect();
+        let simplified: Vec<Instruction> = instructions
+            .iter()
+            .enumerate()
+            .filter(|(pc, instruction)| match instruction {
+                Instruction::BranchTarget(BranchTarget { identifier, .. }) => {
+                    used_targets.contains(identifier) && targets.get(identifier) == Some(pc)
+                }
+                Instruction::Branch(Branch { target, .. }) => {
+                    targets.get(target) != Some(&(pc + 1))
+                }
+                instruction => {
+                    !is_no_op(instruction)
+                        && match removable_definition(instruction) {
+                            Some(rd) => live[*pc] & bit(rd) != 0,
+                            None => true,
+                        }
+                }
+            })
+            .map(|(_, instruction)| instruction.clone())
+            .collect();
+        if simplified.len() == instructions.len() {
+            return function.with_instructions(instructions);
+        }
+        instructions = simplified;
+    }
+}
+
+fn bit(register: u8) -> Registers {
+    1 << register
+}
+
+// the register an instruction writes, if it has no effect besides that
+fn removable_definition(instruction: &Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::Immediate(Immediate { rd, .. })
+        | Instruction::Register(Register { rd, .. })
+        | Instruction::Load(Load { rd, .. }) => Some(*rd),
+        _ => None,
+    }
+}
+
+// instructions that leave their register as it is
+fn is_no_op(instruction: &Instruction) -> bool {
+    use ImmediateOpcode::*;
+    use RegisterOpcode::*;
+    match instruction {
+        Instruction::Immediate(Immediate {
+            opcode,
+            value,
+            rs,
+            rd,
+        }) if rs == rd => match opcode {
+            Addi | Ori | Xori | Slli | Srli | Srai => *value == 0,
+            Andi => *value == -1,
+            _ => false,
+        },
+        Instruction::Register(Register {
+            opcode: And | Or,
+            rs1,
+            rs2,
+            rd,
+        }) => rs1 == rd && rs2 == rd,
+        _ => false,
+    }
+}
+
+// the registers an instruction reads, and those it certainly writes
+fn uses_and_definitions(instruction: &Instruction) -> (Registers, Registers) {
+    match instruction {
+        Instruction::Immediate(Immediate { rs, rd, .. }) => (bit(*rs), bit(*rd)),
+        Instruction::Register(Register { rs1, rs2, rd, .. }) => (bit(*rs1) | bit(*rs2), bit(*rd)),
+        Instruction::Load(Load { rs, rd, .. }) => (bit(*rs), bit(*rd)),
+        Instruction::Store(store) => (bit(store.rs) | bit(store.rd), 0),
+        Instruction::Branch(Branch { rs1, rs2, .. }) => (bit(*rs1) | bit(*rs2), 0),
+        Instruction::BranchTarget(_) => (0, 0),
+        Instruction::Random(Random { rd, .. }) => (0, bit(*rd)),
+        // called functions and host functions may read and write any register
+        Instruction::CallId(_) | Instruction::Ecall(_) => (ALL_REGISTERS, 0),
+    }
+}
+
+// the registers that may be read after each instruction. branches only go
+// forward, so a pass from the end is enough, except that a repeating function
+// continues at its start
+fn live_out(instructions: &[Instruction], repeat: u8, exit_live: Registers) -> Vec<Registers> {
+    let targets = Function::targets(instructions);
+    let mut end_live = exit_live;
+    loop {
+        let mut live_in = vec![0; instructions.len() + 1];
+        let mut live_out = vec![0; instructions.len()];
+        live_in[instructions.len()] = end_live;
+        for (pc, instruction) in instructions.iter().enumerate().rev() {
+            let mut out = live_in[pc + 1];
+            if let Instruction::Branch(Branch { target, .. }) = instruction {
+                if let Some(index) = targets.get(target) {
+                    out |= live_in[*index];
+                }
+            }
+            let (mut uses, definitions) = uses_and_definitions(instruction);
+            // what a dead write reads isn't needed either, even when the
+            // write feeds itself in the next iteration
+            if let Some(rd) = removable_definition(instruction) {
+                if out & bit(rd) == 0 {
+                    uses = 0;
+                }
+            }
+            live_out[pc] = out;
+            live_in[pc] = uses | (out & !definitions);
+        }
+        let next_end_live = if repeat > 1 {
+            exit_live | live_in[0]
+        } else {
+            exit_live
+        };
+        if next_end_live == end_live {
+            return live_out;
+        }
+        end_live = next_end_live;
+    }
+}
+
+// calls of functions without instructions do nothing
+fn remove_empty_calls(functions: Vec<Function>) -> Vec<Function> {
+    let empty: Vec<bool> = functions
+        .iter()
+        .map(|function| function.get_instructions().is_empty())
+        .collect();
+    functions
+        .iter()
+        .map(|function| {
+            let instructions: Vec<Instruction> = function
+                .get_instructions()
+                .iter()
+                .filter(|instruction| match instruction {
+                    Instruction::CallId(CallId {
+                        opcode: CallIdOpcode::Call,
+                        identifier,
+                    }) => !empty.get(*identifier as usize).copied().unwrap_or(false),
+                    _ => true,
+                })
+                .cloned()
+                .collect();
+            function.with_instructions(instructions)
+        })
+        .collect()
+}
+
+// keep only the functions main reaches, in the same order, and renumber the
+// calls to match
+fn remove_unreachable(functions: Vec<Function>) -> Vec<Function> {
+    let program = Program::from_functions(functions);
+    let reachable: FxHashSet<u16> = program.reachable_call_ids().into_iter().collect();
+    let ids: FxHashMap<u16, u16> = (0..program.functions().len() as u16)
+        .filter(|id| reachable.contains(id))
+        .enumerate()
+        .map(|(new, old)| (old, new as u16))
+        .collect();
+    program
+        .functions()
+        .iter()
+        .enumerate()
+        .filter(|(id, _)| reachable.contains(&(*id as u16)))
+        .map(|(_, function)| {
+            let instructions: Vec<Instruction> = function
+                .get_instructions()
+                .iter()
+                .map(|instruction| match instruction {
+                    Instruction::CallId(CallId { opcode, identifier }) => {
+                        Instruction::CallId(CallId {
+                            opcode: *opcode,
+                            identifier: ids[identifier],
+                        })
+                    }
+                    instruction => instruction.clone(),
+                })
+                .collect();
+            function.with_instructions(instructions)
+        })
+        .collect()
+}
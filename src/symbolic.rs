@@ -0,0 +1,661 @@
+use crate::function::Function;
+use crate::lang::{
+    Branch, BranchOpcode, Immediate, ImmediateOpcode, Instruction, Load, LoadOpcode, Random,
+    Register, RegisterOpcode, Store, StoreOpcode, RNG_INCREMENT, RNG_MULTIPLIER,
+};
+use crate::program::Program;
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+type TermId = usize;
+
+// the width of a term in bits, where a width of 0 is a boolean
+type Width = u8;
+
+const BOOL: Width = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+    Not,
+    Shl,
+    Lshr,
+    Ashr,
+    Eq,
+    Slt,
+    Ult,
+    Ite,
+    Concat,
+    Extract(Width, Width),
+    SignExtend(Width),
+    ZeroExtend(Width),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Term {
+    Constant(u32, Width),
+    Input(usize),
+    Apply(Op, Vec<TermId>),
+}
+
+// terms are shared, so that a term that's used in many places is only
+// written out once
+#[derive(Debug, Clone, Default)]
+struct Terms {
+    terms: Vec<Term>,
+    widths: Vec<Width>,
+    interned: FxHashMap<Term, TermId>,
+}
+
+/// A program run on symbolic memory. Since branches only go forward and
+/// there's no recursion, a program can be unrolled completely, which turns
+/// each byte of memory after the run into a bit-vector expression over the
+/// bytes before it. The run is that of `Program::interpret` with the default
+/// machine options, except that an ecall does nothing.
+///
+/// A load or store at an address that isn't known in advance takes a term for
+/// each byte of memory, so memory should be kept small.
+#[derive(Debug, Clone)]
+pub struct SymbolicExecution {
+    terms: Terms,
+    outputs: Vec<TermId>,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    registers: Vec<TermId>,
+    memory: Vec<TermId>,
+    rng: TermId,
+}
+
+// a state along with the condition under which it's reached
+type Path = (TermId, State);
+
+struct Executor<'a> {
+    terms: &'a mut Terms,
+    functions: &'a [Function],
+}
+
+impl SymbolicExecution {
+    pub fn new(program: &Program, memory_len: usize) -> SymbolicExecution {
+        let mut terms = Terms::default();
+        let zero = terms.constant(0, 16);
+        let state = State {
+            registers: vec![zero; 32],
+            memory: (0..memory_len).map(|index| terms.input(index)).collect(),
+            rng: terms.constant(0, 32),
+        };
+        let mut executor = Executor {
+            terms: &mut terms,
+            functions: program.functions(),
+        };
+        let state = executor.call(0, state);
+        SymbolicExecution {
+            terms,
+            outputs: state.memory,
+        }
+    }
+
+    pub fn memory_len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Work out the memory after a run from the memory before it, as the
+    /// expressions say. This is the memory `Program::interpret` leaves.
+    pub fn evaluate(&self, memory: &[u8]) -> Vec<u8> {
+        assert_eq!(memory.len(), self.memory_len(), "memory has the wrong size");
+        let mut values: Vec<u32> = Vec::with_capacity(self.terms.terms.len());
+        for term in &self.terms.terms {
+            let value = match term {
+                Term::Constant(value, _) => *value,
+                Term::Input(index) => memory[*index] as u32,
+                Term::Apply(op, args) => {
+                    let arg_values: Vec<u32> = args.iter().map(|arg| values[*arg]).collect();
+                    let arg_widths: Vec<Width> =
+                        args.iter().map(|arg| self.terms.widths[*arg]).collect();
+                    evaluate(*op, &arg_values, &arg_widths)
+                }
+            };
+            values.push(value);
+        }
+        self.outputs
+            .iter()
+            .map(|output| values[*output] as u8)
+            .collect()
+    }
+
+    /// Write the run out as SMT-LIB2, for a solver such as z3. The memory
+    /// before the run is declared as the bytes `in_0`, `in_1` and so on, and
+    /// the memory after it is defined as `out_0`, `out_1` and so on. The
+    /// assertions are added as they are, for instance `(= out_10 #x2a)`, and
+    /// the solver is then asked for memory that satisfies them.
+    pub fn to_smt2(&self, assertions: &[&str]) -> String {
+        let terms = &self.terms;
+        let mut needed = vec![false; terms.terms.len()];
+        for output in &self.outputs {
+            needed[*output] = true;
+        }
+        for id in (0..terms.terms.len()).rev() {
+            if let (true, Term::Apply(_, args)) = (needed[id], &terms.terms[id]) {
+                for arg in args {
+                    needed[*arg] = true;
+                }
+            }
+        }
+
+        let mut output = String::new();
+        writeln!(
+            output,
+            "; {} bytes of memory, in_N before the run and out_N after it",
+            self.memory_len()
+        )
+        .unwrap();
+        writeln!(output, "(set-logic QF_BV)").unwrap();
+        for index in 0..self.memory_len() {
+            writeln!(output, "(declare-const in_{} (_ BitVec 8))", index).unwrap();
+        }
+        for (id, term) in terms.terms.iter().enumerate() {
+            if let (true, Term::Apply(op, args)) = (needed[id], term) {
+                let names: Vec<String> = args.iter().map(|arg| terms.name(*arg)).collect();
+                writeln!(
+                    output,
+                    "(define-fun t{} () {} ({} {}))",
+                    id,
+                    sort(terms.widths[id]),
+                    op_name(*op, terms.widths[args[0]]),
+                    names.join(" ")
+                )
+                .unwrap();
+            }
+        }
+        for (index, id) in self.outputs.iter().enumerate() {
+            writeln!(
+                output,
+                "(define-fun out_{} () (_ BitVec 8) {})",
+                index,
+                terms.name(*id)
+            )
+            .unwrap();
+        }
+        for assertion in assertions {
+            writeln!(output, "(assert {})", assertion).unwrap();
+        }
+        writeln!(output, "(check-sat)").unwrap();
+        if self.memory_len() > 0 {
+            let inputs: Vec<String> = (0..self.memory_len())
+                .map(|index| format!("in_{}", index))
+                .collect();
+            writeln!(output, "(get-value ({}))", inputs.join(" ")).unwrap();
+        }
+        output
+    }
+}
+
+fn sort(width: Width) -> String {
+    if width == BOOL {
+        "Bool".to_string()
+    } else {
+        format!("(_ BitVec {})", width)
+    }
+}
+
+fn op_name(op: Op, width: Width) -> String {
+    let name = match op {
+        Op::Add => "bvadd",
+        Op::Sub => "bvsub",
+        Op::Mul => "bvmul",
+        Op::And if width == BOOL => "and",
+        Op::And => "bvand",
+        Op::Or if width == BOOL => "or",
+        Op::Or => "bvor",
+        Op::Xor => "bvxor",
+        Op::Not => "not",
+        Op::Shl => "bvshl",
+        Op::Lshr => "bvlshr",
+        Op::Ashr => "bvashr",
+        Op::Eq => "=",
+        Op::Slt => "bvslt",
+        Op::Ult => "bvult",
+        Op::Ite => "ite",
+        Op::Concat => "concat",
+        Op::Extract(high, low) => return format!("(_ extract {} {})", high, low),
+        Op::SignExtend(bits) => return format!("(_ sign_extend {})", bits),
+        Op::ZeroExtend(bits) => return format!("(_ zero_extend {})", bits),
+    };
+    name.to_string()
+}
+
+fn mask(width: Width) -> u32 {
+    match width {
+        BOOL => 1,
+        32.. => u32::MAX,
+        _ => (1 << width) - 1,
+    }
+}
+
+fn signed(value: u32, width: Width) -> i64 {
+    let shift = 64 - width as u32;
+    ((value as i64) << shift) >> shift
+}
+
+// the value of an operation on values, as SMT-LIB defines it
+fn evaluate(op: Op, args: &[u32], widths: &[Width]) -> u32 {
+    let width = widths[0];
+    let a = args[0];
+    let b = args.get(1).copied().unwrap_or(0);
+    match op {
+        Op::Add => a.wrapping_add(b) & mask(width),
+        Op::Sub => a.wrapping_sub(b) & mask(width),
+        Op::Mul => a.wrapping_mul(b) & mask(width),
+        Op::And => a & b,
+        Op::Or => a | b,
+        Op::Xor => a ^ b,
+        Op::Not => a ^ 1,
+        Op::Shl if b >= width as u32 => 0,
+        Op::Shl => (a << b) & mask(width),
+        Op::Lshr if b >= width as u32 => 0,
+        Op::Lshr => a >> b,
+        Op::Ashr => (signed(a, width) >> b.min(63)) as u32 & mask(width),
+        Op::Eq => (a == b) as u32,
+        Op::Slt => (signed(a, width) < signed(b, width)) as u32,
+        Op::Ult => (a < b) as u32,
+        Op::Ite => {
+            if a != 0 {
+                b
+            } else {
+                args[2]
+            }
+        }
+        Op::Concat => (a << widths[1]) | b,
+        Op::Extract(high, low) => (a >> low) & mask(high - low + 1),
+        Op::SignExtend(bits) => signed(a, width) as u32 & mask(width + bits),
+        Op::ZeroExtend(_) => a,
+    }
+}
+
+impl Terms {
+    fn intern(&mut self, term: Term, width: Width) -> TermId {
+        if let Some(id) = self.interned.get(&term) {
+            return *id;
+        }
+        let id = self.terms.len();
+        self.terms.push(term.clone());
+        self.widths.push(width);
+        self.interned.insert(term, id);
+        id
+    }
+
+    fn constant(&mut self, value: u32, width: Width) -> TermId {
+        self.intern(Term::Constant(value & mask(width), width), width)
+    }
+
+    fn boolean(&mut self, value: bool) -> TermId {
+        self.constant(value as u32, BOOL)
+    }
+
+    fn input(&mut self, index: usize) -> TermId {
+        self.intern(Term::Input(index), 8)
+    }
+
+    fn value(&self, id: TermId) -> Option<u32> {
+        match self.terms[id] {
+            Term::Constant(value, _) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn name(&self, id: TermId) -> String {
+        match self.terms[id] {
+            Term::Constant(value, BOOL) => (value != 0).to_string(),
+            Term::Constant(value, width) if width % 4 == 0 => {
+                format!("#x{:0digits$x}", value, digits = width as usize / 4)
+            }
+            Term::Constant(value, width) => {
+                format!("#b{:0digits$b}", value, digits = width as usize)
+            }
+            Term::Input(index) => format!("in_{}", index),
+            Term::Apply(..) => format!("t{}", id),
+        }
+    }
+
+    // the operation applied to the arguments, worked out right away where the
+    // result is already known
+    fn apply(&mut self, op: Op, args: &[TermId]) -> TermId {
+        let widths: Vec<Width> = args.iter().map(|arg| self.widths[*arg]).collect();
+        let width = match op {
+            Op::Eq | Op::Slt | Op::Ult | Op::Not => BOOL,
+            Op::Ite => widths[1],
+            Op::Concat => widths[0] + widths[1],
+            Op::Extract(high, low) => high - low + 1,
+            Op::SignExtend(bits) | Op::ZeroExtend(bits) => widths[0] + bits,
+            _ => widths[0],
+        };
+        let values: Option<Vec<u32>> = args.iter().map(|arg| self.value(*arg)).collect();
+        if let Some(values) = values {
+            return self.constant(evaluate(op, &values, &widths), width);
+        }
+        match (
+            op,
+            self.value(args[0]),
+            args.get(1).and_then(|arg| self.value(*arg)),
+        ) {
+            (Op::Ite, Some(condition), _) => return if condition != 0 { args[1] } else { args[2] },
+            (Op::Ite, _, _) if args[1] == args[2] => return args[1],
+            (Op::Eq, _, _) if args[0] == args[1] => return self.boolean(true),
+            (Op::And, Some(0), _)
+            | (Op::And, _, Some(1))
+            | (Op::Or, Some(1), _)
+            | (Op::Or, _, Some(0))
+                if width == BOOL =>
+            {
+                return args[0]
+            }
+            (Op::And, _, Some(0))
+            | (Op::And, Some(1), _)
+            | (Op::Or, _, Some(1))
+            | (Op::Or, Some(0), _)
+                if width == BOOL =>
+            {
+                return args[1]
+            }
+            _ => {}
+        }
+        self.intern(Term::Apply(op, args.to_vec()), width)
+    }
+}
+
+impl<'a> Executor<'a> {
+    fn call(&mut self, id: usize, mut state: State) -> State {
+        let functions = self.functions;
+        let function = &functions[id];
+        let instructions = function.get_instructions();
+        let targets = Function::targets(instructions);
+        for _ in 0..function.get_repeat() {
+            state = self.iteration(instructions, &targets, state);
+        }
+        state
+    }
+
+    // a single iteration of a function. all paths through it start from the
+    // same state, so they only need to be told apart within the iteration.
+    // where paths come together their states are merged, which keeps the
+    // expressions from growing with the number of paths
+    fn iteration(
+        &mut self,
+        instructions: &[Instruction],
+        targets: &FxHashMap<u8, usize>,
+        state: State,
+    ) -> State {
+        let mut current = Some((self.terms.boolean(true), state));
+        let mut pending: Vec<Option<Path>> = vec![None; instructions.len()];
+        for (pc, instruction) in instructions.iter().enumerate() {
+            current = self.merge(current, pending[pc].take());
+            let Some((guard, mut state)) = current.take() else {
+                continue;
+            };
+            let target = match instruction {
+                Instruction::Branch(branch) => {
+                    targets.get(&branch.target).map(|index| (branch, *index))
+                }
+                _ => None,
+            };
+            match target {
+                // branches only go forward, and a branch without its target
+                // does nothing
+                Some((branch, index)) if index > pc => {
+                    let condition = self.condition(branch, &state);
+                    let not_condition = self.terms.apply(Op::Not, &[condition]);
+                    let taken = self.terms.apply(Op::And, &[guard, condition]);
+                    let not_taken = self.terms.apply(Op::And, &[guard, not_condition]);
+                    if self.terms.value(taken) != Some(0) {
+                        let path = Some((taken, state.clone()));
+                        pending[index] = self.merge(pending[index].take(), path);
+                    }
+                    if self.terms.value(not_taken) != Some(0) {
+                        current = Some((not_taken, state));
+                    }
+                }
+                _ => {
+                    self.execute(instruction, &mut state);
+                    current = Some((guard, state));
+                }
+            }
+        }
+        current.expect("every path reaches the end").1
+    }
+
+    fn merge(&mut self, a: Option<Path>, b: Option<Path>) -> Option<Path> {
+        let ((guard_a, a), (guard_b, b)) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (a, None) => return a,
+            (None, b) => return b,
+        };
+        let mut merge = |a: &[TermId], b: &[TermId]| -> Vec<TermId> {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| self.terms.apply(Op::Ite, &[guard_a, *a, *b]))
+                .collect()
+        };
+        let registers = merge(&a.registers, &b.registers);
+        let memory = merge(&a.memory, &b.memory);
+        let rng = merge(&[a.rng], &[b.rng])[0];
+        let guard = self.terms.apply(Op::Or, &[guard_a, guard_b]);
+        Some((
+            guard,
+            State {
+                registers,
+                memory,
+                rng,
+            },
+        ))
+    }
+
+    fn constant(&mut self, value: i16) -> TermId {
+        self.terms.constant(value as u16 as u32, 16)
+    }
+
+    // 1 if the condition holds and 0 otherwise
+    fn flag(&mut self, condition: TermId) -> TermId {
+        let one = self.constant(1);
+        let zero = self.constant(0);
+        self.terms.apply(Op::Ite, &[condition, one, zero])
+    }
+
+    // shifting by 16 or more leaves the value as it is
+    fn shift(&mut self, op: Op, value: TermId, amount: TermId) -> TermId {
+        let sixteen = self.constant(16);
+        let in_range = self.terms.apply(Op::Ult, &[amount, sixteen]);
+        let shifted = self.terms.apply(op, &[value, amount]);
+        self.terms.apply(Op::Ite, &[in_range, shifted, value])
+    }
+
+    fn condition(&mut self, branch: &Branch, state: &State) -> TermId {
+        use BranchOpcode::*;
+        let a = state.registers[branch.rs1 as usize];
+        let b = state.registers[branch.rs2 as usize];
+        let (op, negate) = match branch.opcode {
+            Beq => (Op::Eq, false),
+            Bne => (Op::Eq, true),
+            Blt => (Op::Slt, false),
+            Bltu => (Op::Ult, false),
+            Bge => (Op::Slt, true),
+            Bgeu => (Op::Ult, true),
+        };
+        let condition = self.terms.apply(op, &[a, b]);
+        if negate {
+            self.terms.apply(Op::Not, &[condition])
+        } else {
+            condition
+        }
+    }
+
+    fn execute(&mut self, instruction: &Instruction, state: &mut State) {
+        match instruction {
+            Instruction::Immediate(immediate) => self.immediate(immediate, state),
+            Instruction::Register(register) => self.register(register, state),
+            Instruction::Load(load) => self.load(load, state),
+            Instruction::Store(store) => self.store(store, state),
+            Instruction::CallId(call_id) => {
+                *state = self.call(call_id.identifier as usize, state.clone());
+            }
+            Instruction::Random(random) => self.random(random, state),
+            // there are no host functions in a symbolic run
+            Instruction::Ecall(_) => {}
+            Instruction::Branch(_) | Instruction::BranchTarget(_) => {}
+        }
+    }
+
+    fn immediate(&mut self, immediate: &Immediate, state: &mut State) {
+        use ImmediateOpcode::*;
+        let rs = state.registers[immediate.rs as usize];
+        let value = self.constant(immediate.value);
+        let result = match immediate.opcode {
+            Addi => self.terms.apply(Op::Add, &[rs, value]),
+            Slti => {
+                let condition = self.terms.apply(Op::Slt, &[rs, value]);
+                self.flag(condition)
+            }
+            Sltiu => {
+                let condition = self.terms.apply(Op::Ult, &[rs, value]);
+                self.flag(condition)
+            }
+            Andi => self.terms.apply(Op::And, &[rs, value]),
+            Ori => self.terms.apply(Op::Or, &[rs, value]),
+            Xori => self.terms.apply(Op::Xor, &[rs, value]),
+            Slli => self.shift(Op::Shl, rs, value),
+            Srli => self.shift(Op::Lshr, rs, value),
+            Srai => self.shift(Op::Ashr, rs, value),
+        };
+        state.registers[immediate.rd as usize] = result;
+    }
+
+    fn register(&mut self, register: &Register, state: &mut State) {
+        use RegisterOpcode::*;
+        let rs1 = state.registers[register.rs1 as usize];
+        let rs2 = state.registers[register.rs2 as usize];
+        let result = match register.opcode {
+            Add => self.terms.apply(Op::Add, &[rs1, rs2]),
+            Sub => self.terms.apply(Op::Sub, &[rs1, rs2]),
+            Slt => {
+                let condition = self.terms.apply(Op::Slt, &[rs1, rs2]);
+                self.flag(condition)
+            }
+            Sltu => {
+                let condition = self.terms.apply(Op::Ult, &[rs1, rs2]);
+                self.flag(condition)
+            }
+            And => self.terms.apply(Op::And, &[rs1, rs2]),
+            Or => self.terms.apply(Op::Or, &[rs1, rs2]),
+            Xor => self.terms.apply(Op::Xor, &[rs1, rs2]),
+            Sll => self.shift(Op::Shl, rs1, rs2),
+            Srl => self.shift(Op::Lshr, rs1, rs2),
+            Sra => self.shift(Op::Ashr, rs1, rs2),
+        };
+        state.registers[register.rd as usize] = result;
+    }
+
+    fn address(&mut self, register: TermId, offset: u16) -> TermId {
+        let offset = self.constant(offset as i16);
+        self.terms.apply(Op::Add, &[register, offset])
+    }
+
+    // the bytes of memory that can be accessed in units of size bytes, as the
+    // index of each unit and the index of its first byte. an address is an
+    // index in units, which can't go beyond the 16 bit address space
+    fn units(memory_len: usize, size: usize) -> impl Iterator<Item = (usize, usize)> {
+        (0..(memory_len / size).min(65536 / size)).map(move |unit| (unit, unit * size))
+    }
+
+    // the value at an address that's known or, where it isn't, a choice
+    // between the values at all addresses. out of bounds this is 0
+    fn read(
+        &mut self,
+        address: TermId,
+        size: usize,
+        memory_len: usize,
+        value_at: impl Fn(&mut Terms, usize) -> TermId,
+    ) -> TermId {
+        let zero = self.terms.constant(0, 8 * size as Width);
+        let units: Vec<(usize, usize)> = Executor::units(memory_len, size).collect();
+        match self.terms.value(address) {
+            Some(address) => match units.get(address as usize) {
+                Some((_, start)) => value_at(self.terms, *start),
+                None => zero,
+            },
+            None => units.into_iter().fold(zero, |other, (unit, start)| {
+                let unit = self.terms.constant(unit as u32, 16);
+                let here = self.terms.apply(Op::Eq, &[address, unit]);
+                let value = value_at(self.terms, start);
+                self.terms.apply(Op::Ite, &[here, value, other])
+            }),
+        }
+    }
+
+    // write bytes at an address, or at no address if it's out of bounds
+    fn write(&mut self, memory: &mut [TermId], address: TermId, bytes: &[TermId]) {
+        let units: Vec<(usize, usize)> = Executor::units(memory.len(), bytes.len()).collect();
+        let address_value = self.terms.value(address);
+        for (unit, start) in units {
+            let here = match address_value {
+                Some(address) if address as usize == unit => self.terms.boolean(true),
+                Some(_) => continue,
+                None => {
+                    let unit = self.terms.constant(unit as u32, 16);
+                    self.terms.apply(Op::Eq, &[address, unit])
+                }
+            };
+            for (offset, byte) in bytes.iter().enumerate() {
+                memory[start + offset] = self
+                    .terms
+                    .apply(Op::Ite, &[here, *byte, memory[start + offset]]);
+            }
+        }
+    }
+
+    fn load(&mut self, load: &Load, state: &mut State) {
+        use LoadOpcode::*;
+        let address = self.address(state.registers[load.rs as usize], load.offset);
+        let memory = &state.memory;
+        let result = match load.opcode {
+            Lh => self.read(address, 2, memory.len(), |terms, start| {
+                terms.apply(Op::Concat, &[memory[start + 1], memory[start]])
+            }),
+            Lb => {
+                let byte = self.read(address, 1, memory.len(), |_, start| memory[start]);
+                self.terms.apply(Op::SignExtend(8), &[byte])
+            }
+            Lbu => {
+                let byte = self.read(address, 1, memory.len(), |_, start| memory[start]);
+                self.terms.apply(Op::ZeroExtend(8), &[byte])
+            }
+        };
+        state.registers[load.rd as usize] = result;
+    }
+
+    fn store(&mut self, store: &Store, state: &mut State) {
+        use StoreOpcode::*;
+        let address = self.address(state.registers[store.rd as usize], store.offset);
+        let value = state.registers[store.rs as usize];
+        let low = self.terms.apply(Op::Extract(7, 0), &[value]);
+        match store.opcode {
+            Sh => {
+                let high = self.terms.apply(Op::Extract(15, 8), &[value]);
+                self.write(&mut state.memory, address, &[low, high]);
+            }
+            Sb => self.write(&mut state.memory, address, &[low]),
+        }
+    }
+
+    fn random(&mut self, random: &Random, state: &mut State) {
+        let multiplier = self.terms.constant(RNG_MULTIPLIER, 32);
+        let increment = self.terms.constant(RNG_INCREMENT, 32);
+        let multiplied = self.terms.apply(Op::Mul, &[state.rng, multiplier]);
+        state.rng = self.terms.apply(Op::Add, &[multiplied, increment]);
+        state.registers[random.rd as usize] = self.terms.apply(Op::Extract(31, 16), &[state.rng]);
+    }
+}
@@ -0,0 +1,485 @@
+use crate::lang::{
+    index_bound, Branch, BranchOpcode, BranchTarget, BranchTargetOpcode, CallId, CallIdOpcode,
+    Immediate, ImmediateOpcode, Instruction, Load, LoadOpcode, MachineOptions, Random,
+    RandomOpcode, Register, RegisterOpcode, Store, StoreOpcode, RNG_INCREMENT, RNG_MULTIPLIER,
+};
+use crate::program::Program;
+use rustc_hash::FxHashMap;
+use wasm_encoder::{
+    BlockType, CodeSection, ConstExpr, ExportKind, ExportSection, Function as WasmFunction,
+    FunctionSection, GlobalSection, GlobalType, Instruction as WasmInstruction, MemArg,
+    MemorySection, MemoryType, Module, TypeSection, ValType,
+};
+
+// two pages, so that the 64k a program can address can start anywhere in the
+// first page
+pub const WASM_MEMORY_PAGES: u64 = 2;
+
+// function locals: the memory pointer parameter, followed by these
+const MEMORY_PTR_LOCAL: u32 = 0;
+const LOOP_COUNTER_LOCAL: u32 = 1;
+const SCRATCH_LOCAL: u32 = 2;
+
+// the global after the registers
+const RNG_GLOBAL: u32 = 32;
+
+/// Compile a program into a self-contained WebAssembly module.
+///
+/// The module exports its linear memory as `memory`, and a function
+/// `run(memory_ptr)` that runs the program against the `memory_size` bytes
+/// that start at `memory_ptr`. Registers are held in module globals and are
+/// zeroed at the start of each run. The state of the random number generator
+/// is exported as the mutable global `rng`; it starts at 0 and is kept between
+/// runs, so it can be seeded by setting it before a run. There are no host
+/// functions in the module, so a program that does an `ecall` is rejected.
+///
+/// Of the `options`, only `zero_register` is supported; windows, violation
+/// counting and profiling are rejected.
+pub fn compile_wasm(
+    program: &Program,
+    memory_size: u32,
+    options: MachineOptions,
+) -> Result<Vec<u8>, String> {
+    if program.calls_host_functions() {
+        return Err("host functions aren't supported by the WebAssembly backend".to_string());
+    }
+    if options.window_size.is_some()
+        || options.count_violations
+        || options.profile_memory
+        || options.profile_execution
+    {
+        return Err(
+            "only the zero register option is supported by the WebAssembly backend".to_string(),
+        );
+    }
+    let call_ids = program.reachable_call_ids();
+    let function_indexes: FxHashMap<u16, u32> = call_ids
+        .iter()
+        .enumerate()
+        .map(|(index, call_id)| (*call_id, index as u32))
+        .collect();
+
+    let mut types = TypeSection::new();
+    types.function([ValType::I32], []);
+
+    let mut functions = FunctionSection::new();
+    let mut codes = CodeSection::new();
+    for call_id in &call_ids {
+        functions.function(0);
+        codes.function(&compile_function(
+            program,
+            *call_id,
+            memory_size,
+            options.zero_register,
+            &function_indexes,
+        ));
+    }
+    let run_index = call_ids.len() as u32;
+    functions.function(0);
+    codes.function(&compile_run(function_indexes[&0]));
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: WASM_MEMORY_PAGES,
+        maximum: None,
+        memory64: false,
+        shared: false,
+    });
+
+    let mut globals = GlobalSection::new();
+    for _ in 0..32 {
+        globals.global(
+            GlobalType {
+                val_type: ValType::I32,
+                mutable: true,
+            },
+            &ConstExpr::i32_const(0),
+        );
+    }
+    globals.global(
+        GlobalType {
+            val_type: ValType::I32,
+            mutable: true,
+        },
+        &ConstExpr::i32_const(0),
+    );
+
+    let mut exports = ExportSection::new();
+    exports.export("run", ExportKind::Func, run_index);
+    exports.export("memory", ExportKind::Memory, 0);
+    exports.export("rng", ExportKind::Global, RNG_GLOBAL);
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&functions)
+        .section(&memories)
+        .section(&globals)
+        .section(&exports)
+        .section(&codes);
+    Ok(module.finish())
+}
+
+fn compile_run(main_index: u32) -> WasmFunction {
+    let mut f = WasmFunction::new([]);
+    for register in 0..32 {
+        f.instruction(&WasmInstruction::I32Const(0));
+        f.instruction(&WasmInstruction::GlobalSet(register));
+    }
+    f.instruction(&WasmInstruction::LocalGet(MEMORY_PTR_LOCAL));
+    f.instruction(&WasmInstruction::Call(main_index));
+    f.instruction(&WasmInstruction::End);
+    f
+}
+
+fn compile_function(
+    program: &Program,
+    call_id: u16,
+    memory_size: u32,
+    zero_register: bool,
+    function_indexes: &FxHashMap<u16, u32>,
+) -> WasmFunction {
+    let function = program.get_function(call_id);
+    let instructions = function.get_instructions();
+    let repeat = function.get_repeat();
+
+    let mut f = WasmFunction::new([(2, ValType::I32)]);
+
+    if repeat > 1 {
+        f.instruction(&WasmInstruction::Loop(BlockType::Empty));
+    }
+
+    // wasm only has structured control flow. since branches only go forward,
+    // we open a block for each target up front, with the earliest target as
+    // the innermost block, and close each block at its target
+    let mut targets = FxHashMap::default();
+    for (index, instruction) in instructions.iter().enumerate() {
+        if let Instruction::BranchTarget(BranchTarget {
+            opcode: _,
+            identifier,
+        }) = instruction
+        {
+            targets.insert(*identifier, index);
+        }
+    }
+    let mut target_indexes: Vec<usize> = targets.values().copied().collect();
+    target_indexes.sort_unstable();
+    for _ in &target_indexes {
+        f.instruction(&WasmInstruction::Block(BlockType::Empty));
+    }
+
+    let mut compiler = FunctionCompiler {
+        f: &mut f,
+        memory_size,
+        zero_register,
+    };
+    for (index, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            Instruction::Immediate(immediate) => compiler.compile_immediate(immediate),
+            Instruction::Register(register) => compiler.compile_register(register),
+            Instruction::Load(load) => compiler.compile_load(load),
+            Instruction::Store(store) => compiler.compile_store(store),
+            Instruction::Branch(branch) => {
+                if let Some(target_index) = targets.get(&branch.target) {
+                    // the depth is the amount of blocks still open that end
+                    // before the target
+                    let depth = target_indexes
+                        .iter()
+                        .filter(|i| **i > index && **i < *target_index)
+                        .count();
+                    compiler.compile_branch(branch, depth as u32);
+                }
+            }
+            Instruction::BranchTarget(branch_target) => {
+                use BranchTargetOpcode::*;
+                match branch_target.opcode {
+                    Target => {
+                        if targets.get(&branch_target.identifier) == Some(&index) {
+                            compiler.f.instruction(&WasmInstruction::End);
+                        }
+                    }
+                }
+            }
+            Instruction::CallId(call_id) => compiler.compile_call(call_id, function_indexes),
+            Instruction::Ecall(_) => unreachable!("ecall is rejected before compilation"),
+            Instruction::Random(random) => compiler.compile_random(random),
+        }
+    }
+
+    if repeat > 1 {
+        f.instruction(&WasmInstruction::LocalGet(LOOP_COUNTER_LOCAL));
+        f.instruction(&WasmInstruction::I32Const(1));
+        f.instruction(&WasmInstruction::I32Add);
+        f.instruction(&WasmInstruction::LocalTee(LOOP_COUNTER_LOCAL));
+        f.instruction(&WasmInstruction::I32Const(repeat as i32));
+        f.instruction(&WasmInstruction::I32LtU);
+        f.instruction(&WasmInstruction::BrIf(0));
+        f.instruction(&WasmInstruction::End);
+    }
+    f.instruction(&WasmInstruction::End);
+    f
+}
+
+// registers are i32 globals that always hold a sign extended 16 bit value
+struct FunctionCompiler<'a> {
+    f: &'a mut WasmFunction,
+    memory_size: u32,
+    zero_register: bool,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn emit(&mut self, instruction: WasmInstruction) {
+        self.f.instruction(&instruction);
+    }
+
+    fn get(&mut self, register: u8) {
+        self.emit(WasmInstruction::GlobalGet(register as u32));
+    }
+
+    fn get_unsigned(&mut self, register: u8) {
+        self.get(register);
+        self.emit(WasmInstruction::I32Const(0xffff));
+        self.emit(WasmInstruction::I32And);
+    }
+
+    // with a zero register, writes to r0 are discarded
+    fn set(&mut self, register: u8) {
+        if self.zero_register && register == 0 {
+            self.emit(WasmInstruction::Drop);
+            return;
+        }
+        self.emit(WasmInstruction::GlobalSet(register as u32));
+    }
+
+    fn set_wrapped(&mut self, register: u8) {
+        self.emit(WasmInstruction::I32Extend16S);
+        self.set(register);
+    }
+
+    fn compile_immediate(&mut self, immediate: &Immediate) {
+        use ImmediateOpcode::*;
+        let value = immediate.value as i32;
+        // shifting by 16 or more is a no-op
+        let shift = if (immediate.value as u16) < 16 {
+            value
+        } else {
+            0
+        };
+        match immediate.opcode {
+            Addi => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(value));
+                self.emit(WasmInstruction::I32Add);
+            }
+            Slti => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(value));
+                self.emit(WasmInstruction::I32LtS);
+            }
+            Sltiu => {
+                self.get_unsigned(immediate.rs);
+                self.emit(WasmInstruction::I32Const(immediate.value as u16 as i32));
+                self.emit(WasmInstruction::I32LtU);
+            }
+            Andi => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(value));
+                self.emit(WasmInstruction::I32And);
+            }
+            Ori => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(value));
+                self.emit(WasmInstruction::I32Or);
+            }
+            Xori => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(value));
+                self.emit(WasmInstruction::I32Xor);
+            }
+            Slli => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(shift));
+                self.emit(WasmInstruction::I32Shl);
+            }
+            Srli => {
+                self.get_unsigned(immediate.rs);
+                self.emit(WasmInstruction::I32Const(shift));
+                self.emit(WasmInstruction::I32ShrU);
+            }
+            Srai => {
+                self.get(immediate.rs);
+                self.emit(WasmInstruction::I32Const(shift));
+                self.emit(WasmInstruction::I32ShrS);
+            }
+        }
+        self.set_wrapped(immediate.rd);
+    }
+
+    fn compile_register(&mut self, register: &Register) {
+        use RegisterOpcode::*;
+        match register.opcode {
+            Add => self.compile_binary(register, WasmInstruction::I32Add),
+            Sub => self.compile_binary(register, WasmInstruction::I32Sub),
+            Slt => self.compile_binary(register, WasmInstruction::I32LtS),
+            Sltu => {
+                self.get_unsigned(register.rs1);
+                self.get_unsigned(register.rs2);
+                self.emit(WasmInstruction::I32LtU);
+            }
+            And => self.compile_binary(register, WasmInstruction::I32And),
+            Or => self.compile_binary(register, WasmInstruction::I32Or),
+            Xor => self.compile_binary(register, WasmInstruction::I32Xor),
+            Sll => {
+                self.get(register.rs1);
+                self.compile_register_shift(register.rs2);
+                self.emit(WasmInstruction::I32Shl);
+            }
+            Srl => {
+                self.get_unsigned(register.rs1);
+                self.compile_register_shift(register.rs2);
+                self.emit(WasmInstruction::I32ShrU);
+            }
+            Sra => {
+                self.get(register.rs1);
+                self.compile_register_shift(register.rs2);
+                self.emit(WasmInstruction::I32ShrS);
+            }
+        }
+        self.set_wrapped(register.rd);
+    }
+
+    fn compile_binary(&mut self, register: &Register, instruction: WasmInstruction) {
+        self.get(register.rs1);
+        self.get(register.rs2);
+        self.emit(instruction);
+    }
+
+    // leaves the shift amount on the stack, or 0 if it's 16 or more
+    fn compile_register_shift(&mut self, rs: u8) {
+        self.get_unsigned(rs);
+        self.emit(WasmInstruction::LocalTee(SCRATCH_LOCAL));
+        self.emit(WasmInstruction::I32Const(0));
+        self.emit(WasmInstruction::LocalGet(SCRATCH_LOCAL));
+        self.emit(WasmInstruction::I32Const(16));
+        self.emit(WasmInstruction::I32LtU);
+        self.emit(WasmInstruction::Select);
+    }
+
+    // leaves whether the index is in bounds on the stack, and stores the
+    // index in the scratch local
+    fn compile_in_bounds(&mut self, rs: u8, offset: u16, size: u32) {
+        self.get(rs);
+        self.emit(WasmInstruction::I32Const(offset as i32));
+        self.emit(WasmInstruction::I32Add);
+        self.emit(WasmInstruction::I32Const(0xffff));
+        self.emit(WasmInstruction::I32And);
+        self.emit(WasmInstruction::LocalTee(SCRATCH_LOCAL));
+        self.emit(WasmInstruction::I32Const(size as i32));
+        self.emit(WasmInstruction::I32LtU);
+    }
+
+    fn compile_address(&mut self, scale: i32) {
+        self.emit(WasmInstruction::LocalGet(MEMORY_PTR_LOCAL));
+        self.emit(WasmInstruction::LocalGet(SCRATCH_LOCAL));
+        if scale > 1 {
+            self.emit(WasmInstruction::I32Const(scale));
+            self.emit(WasmInstruction::I32Mul);
+        }
+        self.emit(WasmInstruction::I32Add);
+    }
+
+    fn compile_load(&mut self, load: &Load) {
+        use LoadOpcode::*;
+        let memarg = MemArg {
+            offset: 0,
+            align: 0,
+            memory_index: 0,
+        };
+        let (scale, instruction) = match load.opcode {
+            Lh => (2, WasmInstruction::I32Load16S(memarg)),
+            Lb => (1, WasmInstruction::I32Load8S(memarg)),
+            Lbu => (1, WasmInstruction::I32Load8U(memarg)),
+        };
+        let size = index_bound(self.memory_size, scale as u32);
+        self.compile_in_bounds(load.rs, load.offset, size);
+        self.emit(WasmInstruction::If(BlockType::Result(ValType::I32)));
+        self.compile_address(scale);
+        self.emit(instruction);
+        self.emit(WasmInstruction::Else);
+        self.emit(WasmInstruction::I32Const(0));
+        self.emit(WasmInstruction::End);
+        self.set(load.rd);
+    }
+
+    fn compile_store(&mut self, store: &Store) {
+        use StoreOpcode::*;
+        let memarg = MemArg {
+            offset: 0,
+            align: 0,
+            memory_index: 0,
+        };
+        let (scale, instruction) = match store.opcode {
+            Sh => (2, WasmInstruction::I32Store16(memarg)),
+            Sb => (1, WasmInstruction::I32Store8(memarg)),
+        };
+        let size = index_bound(self.memory_size, scale as u32);
+        self.compile_in_bounds(store.rd, store.offset, size);
+        self.emit(WasmInstruction::If(BlockType::Empty));
+        self.compile_address(scale);
+        self.get(store.rs);
+        self.emit(instruction);
+        self.emit(WasmInstruction::End);
+    }
+
+    fn compile_branch(&mut self, branch: &Branch, depth: u32) {
+        use BranchOpcode::*;
+        match branch.opcode {
+            Beq => self.compile_compare(branch, false, WasmInstruction::I32Eq),
+            Bne => self.compile_compare(branch, false, WasmInstruction::I32Ne),
+            Blt => self.compile_compare(branch, false, WasmInstruction::I32LtS),
+            Bltu => self.compile_compare(branch, true, WasmInstruction::I32LtU),
+            Bge => self.compile_compare(branch, false, WasmInstruction::I32GeS),
+            Bgeu => self.compile_compare(branch, true, WasmInstruction::I32GeU),
+        }
+        self.emit(WasmInstruction::BrIf(depth));
+    }
+
+    fn compile_compare(&mut self, branch: &Branch, unsigned: bool, instruction: WasmInstruction) {
+        if unsigned {
+            self.get_unsigned(branch.rs1);
+            self.get_unsigned(branch.rs2);
+        } else {
+            self.get(branch.rs1);
+            self.get(branch.rs2);
+        }
+        self.emit(instruction);
+    }
+
+    fn compile_random(&mut self, random: &Random) {
+        use RandomOpcode::*;
+        match random.opcode {
+            Rand => {
+                self.emit(WasmInstruction::GlobalGet(RNG_GLOBAL));
+                self.emit(WasmInstruction::I32Const(RNG_MULTIPLIER as i32));
+                self.emit(WasmInstruction::I32Mul);
+                self.emit(WasmInstruction::I32Const(RNG_INCREMENT as i32));
+                self.emit(WasmInstruction::I32Add);
+                self.emit(WasmInstruction::GlobalSet(RNG_GLOBAL));
+                self.emit(WasmInstruction::GlobalGet(RNG_GLOBAL));
+                self.emit(WasmInstruction::I32Const(16));
+                self.emit(WasmInstruction::I32ShrU);
+                self.set_wrapped(random.rd);
+            }
+        }
+    }
+
+    fn compile_call(&mut self, call: &CallId, function_indexes: &FxHashMap<u16, u32>) {
+        use CallIdOpcode::*;
+        match call.opcode {
+            Call => {
+                self.emit(WasmInstruction::LocalGet(MEMORY_PTR_LOCAL));
+                self.emit(WasmInstruction::Call(function_indexes[&call.identifier]));
+            }
+        }
+    }
+}
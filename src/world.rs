@@ -0,0 +1,169 @@
+use crate::lang::Processor;
+use crate::program::{Program, RunStatus};
+
+/// Runs a whole compiled program, given the memory and the processor of the
+/// agent, as with `Function::run_with_processor`.
+pub type CompiledRun<'a> = Box<dyn FnMut(&mut [u8], &mut Processor) + 'a>;
+
+enum Executor<'a> {
+    Interpreted {
+        program: Program,
+        quantum: u64,
+    },
+    Compiled {
+        run: CompiledRun<'a>,
+        memory_size: usize,
+    },
+}
+
+/// A program running in a `World`.
+pub struct Agent<'a> {
+    executor: Executor<'a>,
+    processor: Processor,
+    base: usize,
+    runs: u64,
+}
+
+impl<'a> Agent<'a> {
+    pub fn processor(&self) -> &Processor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut Processor {
+        &mut self.processor
+    }
+
+    /// The address in world memory where the memory of the agent starts.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn set_base(&mut self, base: usize) {
+        self.base = base;
+    }
+
+    /// The amount of runs of its program the agent has finished.
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    fn step(&mut self, memory: &mut [u8]) {
+        let memory = if self.processor.options().window_size.is_some() {
+            self.processor.set_base(self.base);
+            memory
+        } else {
+            memory.get_mut(self.base..).unwrap_or(&mut [])
+        };
+        match &mut self.executor {
+            Executor::Interpreted { program, quantum } => {
+                let status = program.interpret_with_budget(memory, &mut self.processor, *quantum);
+                if status == RunStatus::Finished {
+                    self.runs += 1;
+                }
+            }
+            Executor::Compiled { run, memory_size } => {
+                // compiled code doesn't check that the memory is as large as
+                // it was compiled for, so without that much it can't run
+                if let Some(memory) = memory.get_mut(..*memory_size) {
+                    run(memory, &mut self.processor);
+                    self.runs += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Many agents that run over one shared memory.
+///
+/// The world runs in rounds, in which each agent gets a turn in the order in
+/// which the agents were added, so runs are reproducible. An agent sees the
+/// world memory starting at its base address, or if its machine has a window,
+/// the window at its base address that wraps around the end of the world. An
+/// interpreted agent executes up to its quantum of instructions in a turn,
+/// pausing in the middle of a run if needed, and starts a new run in its next
+/// turn once a run is finished. Compiled code cannot be paused, so a compiled
+/// agent does a whole run each turn. Registers and the random number generator
+/// state of each agent are kept between runs.
+pub struct World<'a> {
+    memory: Vec<u8>,
+    agents: Vec<Agent<'a>>,
+}
+
+impl<'a> World<'a> {
+    pub fn new(memory: Vec<u8>) -> World<'a> {
+        World {
+            memory,
+            agents: Vec::new(),
+        }
+    }
+
+    /// Add an interpreted agent, which executes up to `quantum` instructions
+    /// per turn. Returns the index of the agent.
+    pub fn add_interpreted(
+        &mut self,
+        program: Program,
+        processor: Processor,
+        base: usize,
+        quantum: u64,
+    ) -> usize {
+        self.add(Executor::Interpreted { program, quantum }, processor, base)
+    }
+
+    /// Add a compiled agent, which does a whole run of `run` per turn, where
+    /// `run` was compiled for `memory_size` bytes of memory. The agent sees
+    /// only that much of the memory it would otherwise see, and when there is
+    /// less than that, for instance once its base has moved too close to the
+    /// end of the world, it skips its turns. Returns the index of the agent.
+    pub fn add_compiled(
+        &mut self,
+        run: impl FnMut(&mut [u8], &mut Processor) + 'a,
+        memory_size: u32,
+        processor: Processor,
+        base: usize,
+    ) -> usize {
+        let executor = Executor::Compiled {
+            run: Box::new(run),
+            memory_size: memory_size as usize,
+        };
+        self.add(executor, processor, base)
+    }
+
+    fn add(&mut self, executor: Executor<'a>, processor: Processor, base: usize) -> usize {
+        self.agents.push(Agent {
+            executor,
+            processor,
+            base,
+            runs: 0,
+        });
+        self.agents.len() - 1
+    }
+
+    /// Give every agent one turn.
+    pub fn step(&mut self) {
+        for agent in &mut self.agents {
+            agent.step(&mut self.memory);
+        }
+    }
+
+    pub fn run(&mut self, rounds: usize) {
+        for _ in 0..rounds {
+            self.step();
+        }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    pub fn agents(&self) -> &[Agent<'a>] {
+        &self.agents
+    }
+
+    pub fn agents_mut(&mut self) -> &mut [Agent<'a>] {
+        &mut self.agents
+    }
+}
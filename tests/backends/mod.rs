@@ -0,0 +1,57 @@
+// a module of tests for each backend, where each test calls the function of
+// the same name with the runner of that backend. the backends behind features
+// are only tested when their feature is on, while the interpreter always is.
+// tests of what the Cranelift backend doesn't support give only two runners
+macro_rules! backend_tests {
+    ($(($interpreter:ident, $llvm:ident) => $($test:ident),+ $(,)?;)+) => {
+        mod interpreter {
+            $($(
+                #[test]
+                fn $test() {
+                    super::$test(aleven::run::$interpreter)
+                }
+            )+)+
+        }
+
+        #[cfg(feature = "llvm")]
+        mod llvm {
+            $($(
+                #[test]
+                fn $test() {
+                    super::$test(aleven::run::$llvm)
+                }
+            )+)+
+        }
+    };
+
+    ($(($interpreter:ident, $llvm:ident, $cranelift:ident) => $($test:ident),+ $(,)?;)+) => {
+        mod interpreter {
+            $($(
+                #[test]
+                fn $test() {
+                    super::$test(aleven::run::$interpreter)
+                }
+            )+)+
+        }
+
+        #[cfg(feature = "llvm")]
+        mod llvm {
+            $($(
+                #[test]
+                fn $test() {
+                    super::$test(aleven::run::$llvm)
+                }
+            )+)+
+        }
+
+        #[cfg(feature = "cranelift")]
+        mod cranelift {
+            $($(
+                #[test]
+                fn $test() {
+                    super::$test(aleven::run::$cranelift)
+                }
+            )+)+
+        }
+    };
+}
@@ -1,8 +1,26 @@
+#[macro_use]
+mod backends;
+
 use aleven::parse_program;
-use aleven::run::{compiled, interpreted, Run};
-use parameterized::parameterized;
+use aleven::run::Run;
+
+backend_tests! {
+    (interpreted, compiled, cranelift_compiled) =>
+        test_beq_simple,
+        test_beq_earlier_target_means_nop,
+        test_addi_after_beq,
+        test_bne_simple,
+        test_blt_simple,
+        test_blt_negative,
+        test_bltu_simple,
+        test_bge_simple,
+        test_bge_equal,
+        test_bge_negative,
+        test_bgeu_simple,
+        test_bgeu_equal,
+        test_bgeu_negative;
+}
 
-#[parameterized(run={compiled, interpreted})]
 fn test_beq_simple(run: Run) {
     let program = parse_program(
         "
@@ -37,7 +55,6 @@ fn test_beq_simple(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_beq_earlier_target_means_nop(run: Run) {
     let program = parse_program(
         "
@@ -71,7 +88,6 @@ fn test_beq_earlier_target_means_nop(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_addi_after_beq(run: Run) {
     let program = parse_program(
         "
@@ -90,7 +106,6 @@ fn test_addi_after_beq(run: Run) {
     run(&program, &mut memory);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bne_simple(run: Run) {
     let program = parse_program(
         "
@@ -125,7 +140,6 @@ fn test_bne_simple(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_blt_simple(run: Run) {
     let program = parse_program(
         "
@@ -160,7 +174,6 @@ fn test_blt_simple(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_blt_negative(run: Run) {
     let program = parse_program(
         "
@@ -195,7 +208,6 @@ fn test_blt_negative(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bltu_simple(run: Run) {
     let program = parse_program(
         "
@@ -231,7 +243,6 @@ fn test_bltu_simple(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bge_simple(run: Run) {
     let program = parse_program(
         "
@@ -266,7 +277,6 @@ fn test_bge_simple(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bge_equal(run: Run) {
     let program = parse_program(
         "
@@ -301,7 +311,6 @@ fn test_bge_equal(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bge_negative(run: Run) {
     let program = parse_program(
         "
@@ -336,7 +345,6 @@ fn test_bge_negative(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bgeu_simple(run: Run) {
     let program = parse_program(
         "
@@ -371,7 +379,6 @@ fn test_bgeu_simple(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bgeu_equal(run: Run) {
     let program = parse_program(
         "
@@ -406,7 +413,6 @@ fn test_bgeu_equal(run: Run) {
     assert_eq!(memory[10], 30);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_bgeu_negative(run: Run) {
     let program = parse_program(
         "
@@ -1,8 +1,16 @@
+#[macro_use]
+mod backends;
+
 use aleven::parse_program;
-use aleven::run::{compiled, interpreted, Run};
-use parameterized::parameterized;
+use aleven::run::Run;
+
+backend_tests! {
+    (interpreted, compiled, cranelift_compiled) =>
+        test_call,
+        test_nested_call,
+        test_no_recursion_basic;
+}
 
-#[parameterized(run={compiled, interpreted})]
 fn test_call(run: Run) {
     let program = parse_program(
         "
@@ -26,7 +34,6 @@ fn test_call(run: Run) {
     assert_eq!(memory[10], 11);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_nested_call(run: Run) {
     let program = parse_program(
         "
@@ -66,7 +73,6 @@ fn test_nested_call(run: Run) {
     assert_eq!(memory[13], 14);
 }
 
-#[parameterized(run={compiled, interpreted})]
 fn test_no_recursion_basic(run: Run) {
     let program = parse_program(
         "
@@ -0,0 +1,208 @@
+mod common;
+
+use aleven::{parse_program, BasicBlock, CallEdge, Cfg, Edge, EdgeKind, Program};
+use common::random_programs;
+
+fn program() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = addi r0 3
+        call inner
+        beq r1 r0 end
+        sb r0 0 = r1
+        target end
+        call twice
+    }
+
+    repeat inner 2 {
+        r1 = addi r1 1
+        sb r0 1 = r1
+    }
+
+    repeat twice 3 {
+        call inner
+        call inner
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn block(start: usize, end: usize) -> BasicBlock {
+    BasicBlock { start, end }
+}
+
+fn edge(from: usize, to: usize, kind: EdgeKind) -> Edge {
+    Edge { from, to, kind }
+}
+
+#[test]
+fn test_blocks() {
+    let cfg = Cfg::new(&program());
+    let main = &cfg.functions[0];
+    // functions end with a synthetic target
+    assert_eq!(
+        main.blocks,
+        vec![block(0, 3), block(3, 4), block(4, 6), block(6, 7)]
+    );
+    assert_eq!(
+        main.edges,
+        vec![
+            edge(0, 2, EdgeKind::Branch),
+            edge(0, 1, EdgeKind::FallThrough),
+            edge(1, 2, EdgeKind::FallThrough),
+            edge(2, 3, EdgeKind::FallThrough),
+        ]
+    );
+    assert_eq!(main.block_of(1), Some(0));
+    assert_eq!(main.block_of(5), Some(2));
+    assert_eq!(main.block_of(7), None);
+}
+
+#[test]
+fn test_repeat_edge() {
+    let cfg = Cfg::new(&program());
+    let inner = &cfg.functions[1];
+    assert_eq!(inner.blocks, vec![block(0, 2), block(2, 3)]);
+    assert_eq!(
+        inner.edges,
+        vec![
+            edge(0, 1, EdgeKind::FallThrough),
+            edge(1, 0, EdgeKind::Repeat)
+        ]
+    );
+}
+
+#[test]
+fn test_calls() {
+    let cfg = Cfg::new(&program());
+    assert_eq!(
+        cfg.calls,
+        vec![
+            CallEdge {
+                caller: 0,
+                callee: 1,
+                calls: 1,
+                multiplicity: 2
+            },
+            CallEdge {
+                caller: 0,
+                callee: 2,
+                calls: 1,
+                multiplicity: 3
+            },
+            CallEdge {
+                caller: 2,
+                callee: 1,
+                calls: 6,
+                multiplicity: 12
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_dot() {
+    let program = program();
+    let dot = Cfg::new(&program).to_dot(&program);
+    assert!(dot.starts_with("digraph program {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("label=\"inner (repeat 2)\";"));
+    assert!(dot.contains("f0_b0 [label=\"0: r1 = addi r0 3\\l1: call f1\\l2: beq r1 r0 t0\\l\"];"));
+    assert!(dot.contains("f0_b0 -> f0_b2 [label=\"beq\"];"));
+    assert!(dot.contains("f0_b0 -> f0_b1;"));
+    assert!(dot.contains("f1_b1 -> f1_b0 [style=dotted, label=\"repeat\"];"));
+    assert!(dot.contains("f0_b0 -> f1_b0 [style=dashed];"));
+    assert!(dot.contains("f0_b2 -> f2_b0 [style=dashed];"));
+}
+
+#[test]
+fn test_call_graph_dot() {
+    let program = program();
+    let dot = Cfg::new(&program).call_graph_to_dot(&program);
+    assert_eq!(
+        dot,
+        "digraph calls {
+    node [shape=box];
+    f0 [label=\"main\"];
+    f1 [label=\"inner (repeat 2)\"];
+    f2 [label=\"twice (repeat 3)\"];
+    f0 -> f1 [label=\"1 x 2\"];
+    f0 -> f2 [label=\"1 x 3\"];
+    f2 -> f1 [label=\"6 x 2\"];
+}
+"
+    );
+}
+
+#[test]
+fn test_random_programs() {
+    for (i, (program, _)) in random_programs(0xa0761d6478bd642f, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let cfg = Cfg::new(&program);
+        for (function, graph) in program.functions().iter().zip(&cfg.functions) {
+            // the blocks cover the instructions in order
+            let mut end = 0;
+            for block in graph.blocks.iter() {
+                assert_eq!(block.start, end, "program {}", i);
+                assert!(block.end > block.start, "program {}", i);
+                end = block.end;
+            }
+            assert_eq!(end, function.get_instructions().len(), "program {}", i);
+            // only forward branches remain
+            for edge in graph.edges.iter() {
+                assert_eq!(
+                    edge.kind == EdgeKind::Repeat,
+                    edge.to <= edge.from,
+                    "program {}",
+                    i
+                );
+            }
+        }
+        // one node per block and one edge per edge, besides the dashed edges
+        // of calls
+        let dot = cfg.to_dot(&program);
+        let lines: Vec<&str> = dot.lines().collect();
+        let blocks: usize = cfg.functions.iter().map(|graph| graph.blocks.len()).sum();
+        let edges: usize = cfg.functions.iter().map(|graph| graph.edges.len()).sum();
+        let nodes = lines
+            .iter()
+            .filter(|line| line.contains(" [label=") && !line.contains(" -> "));
+        assert_eq!(nodes.count(), blocks, "program {}", i);
+        let branches = lines
+            .iter()
+            .filter(|line| line.contains(" -> ") && !line.contains("dashed"));
+        assert_eq!(branches.count(), edges, "program {}", i);
+        for (id, graph) in cfg.functions.iter().enumerate() {
+            for edge in graph.edges.iter() {
+                let prefix = format!("        f{}_b{} -> f{}_b{}", id, edge.from, id, edge.to);
+                assert!(
+                    lines.iter().any(|line| line.starts_with(&prefix)),
+                    "program {}",
+                    i
+                );
+            }
+        }
+
+        // one node per function and one edge per call edge
+        let dot = cfg.call_graph_to_dot(&program);
+        let lines: Vec<&str> = dot.lines().collect();
+        let nodes = lines
+            .iter()
+            .filter(|line| line.contains(" [label=") && !line.contains(" -> "));
+        assert_eq!(nodes.count(), program.functions().len(), "program {}", i);
+        let calls = lines.iter().filter(|line| line.contains(" -> "));
+        assert_eq!(calls.count(), cfg.calls.len(), "program {}", i);
+        for call in cfg.calls.iter() {
+            let prefix = format!("    f{} -> f{} ", call.caller, call.callee);
+            assert!(
+                lines.iter().any(|line| line.starts_with(&prefix)),
+                "program {}",
+                i
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use aleven::{disassemble, parse, Program, Serializer};
+
+// a small xorshift generator, so that the random programs are reproducible
+pub fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+fn random_bytes(state: &mut u64, len: usize) -> Vec<u8> {
+    (0..len).map(|_| next_random(state) as u8).collect()
+}
+
+// genomes biased towards small numbers, so that opcodes are likely to be
+// valid and offsets are likely to be in bounds
+fn random_genome(state: &mut u64, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|_| {
+            let value = next_random(state);
+            match value % 4 {
+                0 => 0,
+                1 => (value % 32) as u8,
+                _ => (value >> 8) as u8,
+            }
+        })
+        .collect()
+}
+
+// random programs, each with random memory to run them against
+pub fn random_programs(seed: u64, amount: usize) -> Vec<(Program, Vec<u8>)> {
+    let serializer = Serializer::new();
+    // main calls the random code and then stores all registers, so that
+    // differences in register values show up in memory
+    let call = (0..=255u8)
+        .map(|opcode| serializer.deserialize(&[opcode, 1, 0]))
+        .find(|instructions| disassemble(instructions) == "call f1")
+        .unwrap();
+    // r0 is an ordinary register, so clear it before using it as the base
+    let stores = parse(
+        &std::iter::once("r0 = xor r0 r0".to_string())
+            .chain((1..32).map(|r| format!("sh r0 {} = r{}", r, r)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+    .unwrap();
+    let main: Vec<_> = call.into_iter().chain(stores).collect();
+    let mut state = seed;
+    (0..amount)
+        .map(|_| {
+            let genomes: Vec<_> = (0..3)
+                .map(|_| {
+                    let len = next_random(&mut state) as usize % 200;
+                    let bytes = random_genome(&mut state, len);
+                    let repeat = next_random(&mut state) as u8 % 4;
+                    (repeat, serializer.deserialize(&bytes))
+                })
+                .collect();
+            let functions: Vec<_> = std::iter::once((0, &main[..]))
+                .chain(
+                    genomes
+                        .iter()
+                        .map(|(repeat, instructions)| (*repeat, &instructions[..])),
+                )
+                .collect();
+            let program = Program::new(&functions);
+
+            let memory_len = 64 + next_random(&mut state) as usize % 192;
+            (program, random_bytes(&mut state, memory_len))
+        })
+        .collect()
+}
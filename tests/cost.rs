@@ -0,0 +1,201 @@
+mod common;
+
+use aleven::{parse_program, CostAnalysis, CostBounds, MachineOptions, Processor, RunStatus};
+use common::random_programs;
+
+#[test]
+fn test_straight_line() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 1
+        r2 = addi r0 2
+    }
+    ",
+    )
+    .unwrap();
+    // and the synthetic target at the end
+    assert_eq!(
+        program.cost_bounds(),
+        CostBounds {
+            min_bound: 3,
+            max_bound: 3
+        }
+    );
+}
+
+#[test]
+fn test_branches() {
+    let program = parse_program(
+        "
+    func main {
+        beq r1 r0 skip
+        r1 = addi r0 1
+        r2 = addi r0 2
+        target skip
+        bne r1 r0 end
+        r3 = addi r0 3
+        target end
+    }
+    ",
+    )
+    .unwrap();
+    assert_eq!(
+        program.cost_bounds(),
+        CostBounds {
+            min_bound: 5,
+            max_bound: 8
+        }
+    );
+}
+
+#[test]
+fn test_calls_and_repeat() {
+    let program = parse_program(
+        "
+    func main {
+        call inner
+        beq r1 r0 end
+        call outer
+        target end
+    }
+
+    repeat outer 3 {
+        call inner
+        call inner
+    }
+
+    repeat inner 4 {
+        r1 = addi r1 1
+    }
+    ",
+    )
+    .unwrap();
+    let analysis = CostAnalysis::new(&program);
+    assert_eq!(
+        analysis.function(2),
+        Some(CostBounds {
+            min_bound: 8,
+            max_bound: 8
+        })
+    );
+    assert_eq!(
+        analysis.function(1),
+        Some(CostBounds {
+            min_bound: 57,
+            max_bound: 57
+        })
+    );
+    assert_eq!(
+        analysis.program(),
+        CostBounds {
+            min_bound: 12,
+            max_bound: 70
+        }
+    );
+    assert_eq!(analysis.function(3), None);
+}
+
+#[test]
+fn test_unreachable_recursion() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 1
+    }
+
+    func a {
+        call b
+    }
+
+    func b {
+        call a
+    }
+
+    func c {
+        r1 = addi r0 1
+    }
+    ",
+    )
+    .unwrap();
+    let analysis = CostAnalysis::new(&program);
+    assert_eq!(
+        analysis.functions(),
+        &[
+            Some(CostBounds {
+                min_bound: 2,
+                max_bound: 2
+            }),
+            None,
+            None,
+            Some(CostBounds {
+                min_bound: 2,
+                max_bound: 2
+            })
+        ]
+    );
+}
+
+#[test]
+fn test_bounds_not_exact() {
+    // the branch is always taken, but the bounds include the path without it
+    let program = parse_program(
+        "
+    func main {
+        beq r0 r0 end
+        r1 = addi r0 1
+        target end
+    }
+    ",
+    )
+    .unwrap();
+    let bounds = program.cost_bounds();
+    assert_eq!(
+        bounds,
+        CostBounds {
+            min_bound: 3,
+            max_bound: 4
+        }
+    );
+
+    let mut processor = Processor::with_options(MachineOptions {
+        profile_execution: true,
+        ..Default::default()
+    });
+    program.interpret_with_processor(&mut [], &mut processor);
+    let executed: u64 = processor.execution_profile().opcodes().iter().sum();
+    assert_eq!(executed, 3);
+}
+
+#[test]
+fn test_saturates() {
+    let mut source = String::from("func main {\n call f0\n}\n");
+    for i in 0..10 {
+        source.push_str(&format!("repeat f{} 255 {{\n call f{}\n}}\n", i, i + 1));
+    }
+    source.push_str("func f10 {\n r1 = addi r1 1\n}\n");
+    let program = parse_program(&source).unwrap();
+    assert_eq!(program.cost_bounds().max_bound, u64::MAX);
+}
+
+#[test]
+fn test_random_programs() {
+    for (i, (program, memory)) in random_programs(0xe7037ed1a0b428db, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let cost = program.cost_bounds();
+        assert!(cost.min_bound <= cost.max_bound, "program {}", i);
+
+        let mut processor = Processor::with_options(MachineOptions {
+            profile_execution: true,
+            ..Default::default()
+        });
+        let status =
+            program.interpret_with_budget(&mut memory.clone(), &mut processor, cost.max_bound);
+        assert_eq!(status, RunStatus::Finished, "program {}", i);
+        let executed: u64 = processor.execution_profile().opcodes().iter().sum();
+        assert!(cost.min_bound <= executed, "program {}", i);
+        assert!(executed <= cost.max_bound, "program {}", i);
+    }
+}
@@ -0,0 +1,269 @@
+mod common;
+
+use aleven::run::{interpreted, Run};
+use aleven::{compile_c, parse_program, MachineOptions, Program};
+use common::random_programs;
+use parameterized::parameterized;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static BUILD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// compile the programs into an executable that runs the program with the
+// index given as its argument, with memory read from stdin and written to
+// stdout
+fn build_executable(programs: &[(&Program, usize)], options: MachineOptions) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "aleven-csource-{}-{}",
+        std::process::id(),
+        BUILD_COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&directory).unwrap();
+
+    let mut source = String::new();
+    for (index, (program, memory_len)) in programs.iter().enumerate() {
+        source.push_str(
+            &compile_c(
+                program,
+                &format!("program{}", index),
+                *memory_len as u32,
+                options,
+            )
+            .unwrap(),
+        );
+    }
+    source.push_str("#include <stdio.h>\n#include <stdlib.h>\n");
+    source.push_str("static void (*programs[])(uint8_t *) = {");
+    for index in 0..programs.len() {
+        source.push_str(&format!("program{}, ", index));
+    }
+    source.push_str("};\n");
+    source.push_str(
+        "int main(int argc, char **argv) {
+    static uint8_t memory[65536];
+    size_t len = fread(memory, 1, sizeof(memory), stdin);
+    (void)argc;
+    programs[atoi(argv[1])](memory);
+    fwrite(memory, 1, len, stdout);
+    return 0;
+}
+",
+    );
+    let source_path = directory.join("programs.c");
+    fs::write(&source_path, source).unwrap();
+
+    let executable_path = directory.join("programs");
+    let status = Command::new("cc")
+        .arg("-std=c99")
+        .arg("-pedantic")
+        .arg("-O1")
+        .arg("-o")
+        .arg(&executable_path)
+        .arg(&source_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    executable_path
+}
+
+fn run_executable(executable_path: &PathBuf, index: usize, memory: &mut [u8]) {
+    let mut child = Command::new(executable_path)
+        .arg(index.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(memory).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    memory.copy_from_slice(&output.stdout);
+}
+
+fn run_c(program: &Program, memory: &mut [u8]) {
+    run_c_with_options(program, memory, MachineOptions::default());
+}
+
+fn run_c_with_options(program: &Program, memory: &mut [u8], options: MachineOptions) {
+    let executable_path = build_executable(&[(program, memory.len())], options);
+    run_executable(&executable_path, 0, memory);
+    fs::remove_dir_all(executable_path.parent().unwrap()).unwrap();
+}
+
+#[parameterized(run={run_c, interpreted})]
+fn test_stackmachine(run: Run) {
+    let program = parse_program(&fs::read_to_string("stackmachine.ale").unwrap()).unwrap();
+
+    let mut memory = [0u8; 1024];
+    memory[0] = 4;
+    memory[2] = 200;
+    memory[4] = 1;
+    memory[5] = 1;
+    memory[6] = 3; // add
+    memory[7] = 1;
+    memory[8] = 3;
+
+    run(&program, &mut memory);
+
+    assert_eq!(memory[400], 3);
+}
+
+#[parameterized(run={run_c, interpreted})]
+fn test_branches_and_repeat(run: Run) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lb r0 0
+        r2 = addi r0 3
+        blt r1 r2 small
+        call big
+        beq r0 r0 end
+        target small
+        call small
+        target end
+    }
+
+    repeat big 4 {
+        sb r3 10 = r1
+        r3 = addi r3 1
+    }
+
+    func small {
+        r4 = addi r0 -1
+        sh r0 10 = r4
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    memory[0] = 7;
+    run(&program, &mut memory);
+    assert_eq!(&memory[10..15], &[7, 7, 7, 7, 0]);
+
+    let mut memory = [0u8; 64];
+    memory[0] = 2;
+    run(&program, &mut memory);
+    assert_eq!(&memory[20..22], &[255, 255]);
+}
+
+#[parameterized(run={run_c, interpreted})]
+fn test_wrapping_and_shifts(run: Run) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 32767
+        r1 = addi r1 1
+        sh r0 0 = r1
+        r2 = addi r0 -2
+        r3 = srai r2 1
+        sh r0 1 = r3
+        r4 = srli r2 1
+        sh r0 2 = r4
+        r5 = addi r0 16
+        r6 = sll r2 r5
+        sh r0 3 = r6
+        r7 = lb r0 8
+        sh r0 4 = r7
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    memory[8] = 200;
+    run(&program, &mut memory);
+    assert_eq!(
+        &memory[0..10],
+        &[0, 128, 255, 255, 255, 127, 254, 255, 200, 255]
+    );
+}
+
+#[test]
+fn test_random_programs_same_as_interpreter() {
+    let programs: Vec<_> = random_programs(0x2545f4914f6cdd1d, 500)
+        .into_iter()
+        .filter(|(program, _)| !program.calls_host_functions())
+        .collect();
+    let executable_path = build_executable(
+        &programs
+            .iter()
+            .map(|(program, memory)| (program, memory.len()))
+            .collect::<Vec<_>>(),
+        MachineOptions::default(),
+    );
+    for (index, (program, memory)) in programs.into_iter().enumerate() {
+        let mut memory_c = memory.clone();
+        let mut memory_interpreter = memory;
+
+        run_executable(&executable_path, index, &mut memory_c);
+        program.interpret(&mut memory_interpreter);
+
+        assert_eq!(memory_c, memory_interpreter, "{:?}", program);
+    }
+    fs::remove_dir_all(executable_path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_ecall_rejected() {
+    let program = parse_program("func main {\n    ecall 0\n}").unwrap();
+    assert!(compile_c(&program, "program", 64, MachineOptions::default()).is_err());
+}
+
+#[test]
+fn test_zero_register() {
+    let program = parse_program(
+        "
+    func main {
+        r0 = addi r0 5
+        r1 = addi r0 3
+        r0 = lbu r1 0
+        r0 = add r1 r1
+        r0 = srai r1 1
+        sb r0 10 = r1
+        sh r0 6 = r0
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    memory[3] = 100;
+    run_c_with_options(
+        &program,
+        &mut memory,
+        MachineOptions {
+            zero_register: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(memory[10], 3);
+    assert_eq!(&memory[12..14], &[0, 0]);
+}
+
+#[test]
+fn test_unsupported_options_rejected() {
+    let program = parse_program("func main {\n    r1 = addi r0 1\n}").unwrap();
+    for options in [
+        MachineOptions {
+            window_size: Some(16),
+            ..Default::default()
+        },
+        MachineOptions {
+            count_violations: true,
+            ..Default::default()
+        },
+        MachineOptions {
+            profile_memory: true,
+            ..Default::default()
+        },
+        MachineOptions {
+            profile_execution: true,
+            ..Default::default()
+        },
+    ] {
+        assert!(compile_c(&program, "program", 64, options).is_err());
+    }
+}
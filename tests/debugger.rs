@@ -0,0 +1,176 @@
+use aleven::{Debugger, RunStatus};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+const SOURCE: &str = "
+    func main {
+        r1 = addi r0 3
+        call inner
+        beq r1 r0 end
+        sb r0 0 = r1
+        target end
+    }
+
+    repeat inner 2 {
+        r1 = addi r1 1
+        sb r0 1 = r1
+    }
+    ";
+
+fn debugger() -> Debugger {
+    Debugger::from_source(SOURCE, vec![0; 8]).unwrap()
+}
+
+#[test]
+fn test_resolve() {
+    let debugger = debugger();
+    assert_eq!(debugger.resolve("main"), Ok((0, 0)));
+    assert_eq!(debugger.resolve("main:3"), Ok((0, 3)));
+    assert_eq!(debugger.resolve("main:end"), Ok((0, 4)));
+    assert_eq!(debugger.resolve("inner:1"), Ok((1, 1)));
+    assert_eq!(debugger.resolve("main:5"), Ok((0, 5)));
+    assert!(debugger.resolve("main:6").is_err());
+    assert!(debugger.resolve("main:start").is_err());
+    assert!(debugger.resolve("outer").is_err());
+}
+
+#[test]
+fn test_step() {
+    let mut debugger = debugger();
+    assert_eq!(debugger.location(), Some((0, 0)));
+    assert_eq!(debugger.step(), RunStatus::Paused);
+    assert_eq!(debugger.processor().registers()[1], 3);
+    assert_eq!(debugger.step(), RunStatus::Paused);
+    assert_eq!(debugger.location(), Some((1, 0)));
+    // every function ends with a synthetic target, which is a step too
+    for _ in 0..6 {
+        debugger.step();
+    }
+    assert_eq!(debugger.location(), Some((0, 2)));
+    assert_eq!(debugger.memory()[1], 5);
+    for _ in 0..4 {
+        debugger.step();
+    }
+    assert!(debugger.is_finished());
+    assert_eq!(debugger.location(), None);
+    assert_eq!(debugger.step(), RunStatus::Finished);
+    assert_eq!(debugger.memory()[0], 5);
+}
+
+#[test]
+fn test_continue_to_breakpoints() {
+    let mut debugger = debugger();
+    debugger.command("break inner:1").unwrap();
+    debugger.command("break main:end").unwrap();
+    assert_eq!(debugger.cont(), RunStatus::Paused);
+    assert_eq!(debugger.location(), Some((1, 1)));
+    assert_eq!(debugger.processor().registers()[1], 4);
+    // the breakpoint is hit again in the next iteration
+    assert_eq!(debugger.cont(), RunStatus::Paused);
+    assert_eq!(debugger.location(), Some((1, 1)));
+    assert_eq!(debugger.cont(), RunStatus::Paused);
+    assert_eq!(debugger.location(), Some((0, 4)));
+    assert_eq!(debugger.cont(), RunStatus::Finished);
+}
+
+#[test]
+fn test_delete_breakpoint() {
+    let mut debugger = debugger();
+    debugger.command("break inner:1").unwrap();
+    assert_eq!(debugger.breakpoints(), &[(1, 1)]);
+    assert!(debugger.command("delete inner:1").is_ok());
+    assert!(debugger.command("delete inner:1").is_err());
+    assert_eq!(debugger.cont(), RunStatus::Finished);
+}
+
+#[test]
+fn test_call_stack() {
+    let mut debugger = debugger();
+    debugger.command("break inner:1").unwrap();
+    debugger.command("continue").unwrap();
+    debugger.command("continue").unwrap();
+    assert_eq!(
+        debugger.command("stack"),
+        Ok("#0 inner:1 iteration 2/2\n#1 main:1".to_string())
+    );
+}
+
+#[test]
+fn test_registers() {
+    let mut debugger = debugger();
+    debugger.command("set r2 -7").unwrap();
+    let registers = debugger.command("regs").unwrap();
+    assert_eq!(registers.lines().count(), 4);
+    assert!(registers.contains("r2      -7"));
+    assert!(debugger.command("set r32 1").is_err());
+}
+
+#[test]
+fn test_memory() {
+    let mut debugger = debugger();
+    debugger.command("write 2 1 255").unwrap();
+    assert_eq!(
+        debugger.command("mem 1 3"),
+        Ok("0001: 00 01 ff".to_string())
+    );
+    assert!(debugger.command("mem 4 5").is_err());
+    assert!(debugger.command("write 7 1 2").is_err());
+    assert!(debugger.command("write 0 256").is_err());
+}
+
+#[test]
+fn test_restart() {
+    let mut debugger = debugger();
+    debugger.command("write 2 9").unwrap();
+    debugger.command("break main:3").unwrap();
+    debugger.command("continue").unwrap();
+    assert_eq!(
+        debugger.command("restart"),
+        Ok("main:0  r1 = addi r0 3".to_string())
+    );
+    assert_eq!(debugger.memory(), &[0; 8]);
+    assert_eq!(debugger.processor().registers()[1], 0);
+    assert_eq!(debugger.breakpoints(), &[(0, 3)]);
+}
+
+#[test]
+fn test_list() {
+    let mut debugger = debugger();
+    debugger.command("break inner:1").unwrap();
+    assert_eq!(
+        debugger.command("list inner"),
+        Ok("     0  r1 = addi r1 1\n *   1  sb r0 1 = r1\n     2  target t0".to_string())
+    );
+    assert!(debugger.command("list").unwrap().starts_with(">    0"));
+}
+
+#[test]
+fn test_invalid_commands() {
+    let mut debugger = debugger();
+    assert_eq!(debugger.command(""), Ok(String::new()));
+    assert!(debugger.command("jump").is_err());
+    assert!(debugger.command("break").is_err());
+    assert!(debugger.command("step x").is_err());
+}
+
+#[test]
+fn test_stackmachine() {
+    let f = File::open("stackmachine.ale").unwrap();
+    let mut reader = BufReader::new(f);
+    let mut buffer = String::new();
+    reader.read_to_string(&mut buffer).unwrap();
+
+    let mut debugger = Debugger::from_source(&buffer, vec![0; 1024]).unwrap();
+    debugger.command("write 0 4 0 200 0 1 1 3 1 3").unwrap();
+    debugger.command("break add").unwrap();
+    assert_eq!(debugger.cont(), RunStatus::Paused);
+    assert_eq!(debugger.processor().call_stack().len(), 3);
+    // r2 is the opcode the loop dispatched on
+    assert_eq!(debugger.processor().registers()[2], 3);
+    // the program adds twice
+    assert_eq!(debugger.cont(), RunStatus::Paused);
+    debugger.command("delete add").unwrap();
+    assert_eq!(debugger.cont(), RunStatus::Finished);
+    assert_eq!(debugger.memory()[400], 3);
+}
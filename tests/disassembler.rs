@@ -0,0 +1,23 @@
+mod common;
+
+use aleven::{disassemble_program_with_options, parse_program, DisassemblyOptions};
+use common::random_programs;
+
+#[test]
+fn test_disassemble_program_round_trip() {
+    for hex in [false, true] {
+        let options = DisassemblyOptions { hex };
+        for (i, (program, memory)) in random_programs(0x85ebca77c2b2ae63, 100)
+            .into_iter()
+            .enumerate()
+        {
+            let source = disassemble_program_with_options(&program, &options);
+            let parsed = parse_program(&source).unwrap();
+            let mut memory_parsed = memory.clone();
+            parsed.interpret(&mut memory_parsed);
+            let mut memory_original = memory.clone();
+            program.interpret(&mut memory_original);
+            assert_eq!(memory_parsed, memory_original, "program {} hex {}", i, hex);
+        }
+    }
+}
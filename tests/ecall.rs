@@ -0,0 +1,228 @@
+#[cfg(feature = "cranelift")]
+use aleven::CraneliftCodeGen;
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+use aleven::HostFunc;
+use aleven::{parse_program, MachineOptions, Processor, Program, RunStatus};
+#[cfg(feature = "llvm")]
+use aleven::{CodeGen, Function, FunctionValueCache};
+#[cfg(feature = "llvm")]
+use inkwell::context::Context;
+use std::cell::Cell;
+use std::rc::Rc;
+
+// runs a compiled program with the registers and host functions given
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+type RunCompiled = fn(&Program, MachineOptions, &mut [u8], &mut [i16; 32], &[Option<HostFunc>]);
+
+fn program() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = addi r0 5
+        ecall 1
+        ecall 0
+        ecall 7
+        sb r0 1 = r1
+        call inner
+    }
+
+    repeat inner 3 {
+        ecall 1
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn zero_register_program() -> Program {
+    parse_program(
+        "
+    func main {
+        ecall 0
+        sh r0 0 = r0
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn zero_register() -> MachineOptions {
+    MachineOptions {
+        zero_register: true,
+        ..Default::default()
+    }
+}
+
+fn double_r1(registers: &mut [i16; 32], memory: &mut [u8]) {
+    registers[1] *= 2;
+    memory[0] = memory[0].wrapping_add(1);
+}
+
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+unsafe extern "C" fn double_r1_compiled(registers: *mut i16, memory: *mut u8, memory_size: u32) {
+    let registers = &mut *(registers as *mut [i16; 32]);
+    let memory = std::slice::from_raw_parts_mut(memory, memory_size as usize);
+    double_r1(registers, memory);
+}
+
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+unsafe extern "C" fn write_r0_compiled(registers: *mut i16, _memory: *mut u8, _memory_size: u32) {
+    *registers = 42;
+}
+
+#[cfg(feature = "llvm")]
+fn run_llvm(
+    program: &Program,
+    options: MachineOptions,
+    memory: &mut [u8],
+    registers: &mut [i16; 32],
+    host_functions: &[Option<HostFunc>],
+) {
+    let context = Context::create();
+    let codegen = CodeGen::with_options(&context, options);
+    let mut cache = FunctionValueCache::new();
+    let func = program.compile(0, &codegen, memory.len() as u32, &mut cache);
+    codegen.module.verify().unwrap();
+    Function::run_with_state(&func, memory, registers, &mut 0, host_functions);
+}
+
+#[cfg(feature = "cranelift")]
+fn run_cranelift(
+    program: &Program,
+    options: MachineOptions,
+    memory: &mut [u8],
+    registers: &mut [i16; 32],
+    host_functions: &[Option<HostFunc>],
+) {
+    let mut codegen = CraneliftCodeGen::with_options(options).unwrap();
+    let func = codegen.compile_program(program, memory.len() as u32);
+    func.run_with_state(memory, registers, &mut 0, host_functions);
+}
+
+#[test]
+fn test_ecall_interpreted() {
+    let mut memory = [0u8; 4];
+    let mut processor = Processor::new();
+    processor.register_host_function(1, double_r1);
+    program().interpret_with_processor(&mut memory, &mut processor);
+    assert_eq!(memory[0], 4);
+    assert_eq!(memory[1], 10);
+    assert_eq!(processor.registers()[1], 80);
+}
+
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+fn ecall_compiled(run: RunCompiled) {
+    let mut memory = [0u8; 4];
+    let mut registers = [0i16; 32];
+    run(
+        &program(),
+        MachineOptions::default(),
+        &mut memory,
+        &mut registers,
+        &[None, Some(double_r1_compiled)],
+    );
+    assert_eq!(memory[0], 4);
+    assert_eq!(memory[1], 10);
+    assert_eq!(registers[1], 80);
+}
+
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+fn ecall_compiled_without_host_functions(run: RunCompiled) {
+    let mut memory = [0u8; 4];
+    let mut registers = [0i16; 32];
+    run(
+        &program(),
+        MachineOptions::default(),
+        &mut memory,
+        &mut registers,
+        &[],
+    );
+    assert_eq!(memory, [0, 5, 0, 0]);
+}
+
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+fn ecall_compiled_cannot_write_zero_register(run: RunCompiled) {
+    let mut memory = [0u8; 4];
+    let mut registers = [0i16; 32];
+    run(
+        &zero_register_program(),
+        zero_register(),
+        &mut memory,
+        &mut registers,
+        &[Some(write_r0_compiled)],
+    );
+    assert_eq!(memory, [0; 4]);
+    assert_eq!(registers[0], 0);
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn test_ecall_compiled() {
+    ecall_compiled(run_llvm);
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn test_ecall_compiled_without_host_functions() {
+    ecall_compiled_without_host_functions(run_llvm);
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn test_ecall_compiled_cannot_write_zero_register() {
+    ecall_compiled_cannot_write_zero_register(run_llvm);
+}
+
+#[cfg(feature = "cranelift")]
+#[test]
+fn test_ecall_cranelift() {
+    ecall_compiled(run_cranelift);
+}
+
+#[cfg(feature = "cranelift")]
+#[test]
+fn test_ecall_cranelift_without_host_functions() {
+    ecall_compiled_without_host_functions(run_cranelift);
+}
+
+#[cfg(feature = "cranelift")]
+#[test]
+fn test_ecall_cranelift_cannot_write_zero_register() {
+    ecall_compiled_cannot_write_zero_register(run_cranelift);
+}
+
+#[cfg(feature = "cranelift")]
+#[test]
+#[should_panic(expected = "host functions of a processor")]
+fn test_ecall_cranelift_with_processor_host_functions() {
+    let mut processor = Processor::new();
+    processor.register_host_function(1, double_r1);
+    aleven::run::cranelift_compiled_with_processor(&program(), &mut [0u8; 4], &mut processor);
+}
+
+#[test]
+fn test_ecall_with_budget() {
+    let calls = Rc::new(Cell::new(0));
+    let mut processor = Processor::new();
+    let counter = calls.clone();
+    processor.register_host_function(1, move |_: &mut [i16; 32], _: &mut [u8]| {
+        counter.set(counter.get() + 1)
+    });
+
+    let mut memory = [0u8; 4];
+    let program = program();
+    while program.interpret_with_budget(&mut memory, &mut processor, 1) == RunStatus::Paused {}
+    assert_eq!(calls.get(), 4);
+}
+
+#[test]
+fn test_ecall_cannot_write_zero_register() {
+    let mut memory = [0u8; 4];
+    let mut processor = Processor::with_options(zero_register());
+    processor.register_host_function(0, |registers: &mut [i16; 32], _: &mut [u8]| {
+        registers[0] = 42
+    });
+    zero_register_program().interpret_with_processor(&mut memory, &mut processor);
+    assert_eq!(memory, [0; 4]);
+    assert_eq!(processor.registers()[0], 0);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use aleven::{check_equivalence, parse_program, Equivalence, Program};
+use common::random_programs;
+
+fn program(source: &str) -> Program {
+    parse_program(source).unwrap()
+}
+
+fn run(program: &Program, memory: &[u8]) -> Vec<u8> {
+    let mut memory = memory.to_vec();
+    program.interpret(&mut memory);
+    memory
+}
+
+#[test]
+fn test_structural() {
+    let a = program(
+        "
+    func main {
+        r1 = lb r0 0
+        r2 = addi r0 5
+        beq r1 r0 first
+        sb r0 1 = r1
+        target first
+    }
+    ",
+    );
+    let b = program(
+        "
+    func main {
+        r1 = lb r0 0
+        r3 = addi r1 1
+        beq r1 r0 second
+        sb r0 1 = r1
+        target second
+        call unused
+    }
+
+    func unused {
+    }
+    ",
+    );
+    assert_eq!(
+        check_equivalence(&a, &b, 16, 100, 0),
+        Equivalence::Structural
+    );
+}
+
+#[test]
+fn test_simplified_is_structural() {
+    for (program, memory) in random_programs(0x165667b19e3779f9, 100) {
+        let (simplified, _) = program.simplify();
+        assert_eq!(
+            check_equivalence(&program, &simplified, memory.len(), 10, 0),
+            Equivalence::Structural
+        );
+    }
+}
+
+fn add_two() -> Program {
+    program(
+        "
+    func main {
+        r1 = lbu r0 0
+        r1 = addi r1 2
+        sb r0 0 = r1
+    }
+    ",
+    )
+}
+
+fn add_one_twice() -> Program {
+    program(
+        "
+    repeat main 2 {
+        r1 = lbu r0 0
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+}
+
+#[test]
+fn test_exhaustive() {
+    assert_eq!(
+        check_equivalence(&add_two(), &add_one_twice(), 1, 256, 0),
+        Equivalence::Exhaustive
+    );
+}
+
+#[test]
+fn test_tested() {
+    assert_eq!(
+        check_equivalence(&add_two(), &add_one_twice(), 8, 100, 0),
+        Equivalence::Tested(100)
+    );
+}
+
+#[test]
+fn test_counterexample() {
+    let a = add_two();
+    // the same as adding two, except when the byte is 7
+    let b = program(
+        "
+    func main {
+        r1 = lbu r0 0
+        r2 = addi r0 7
+        beq r1 r2 skip
+        r1 = addi r1 2
+        sb r0 0 = r1
+        target skip
+    }
+    ",
+    );
+    assert_eq!(
+        check_equivalence(&a, &b, 1, 256, 0),
+        Equivalence::Different(vec![7])
+    );
+    match check_equivalence(&a, &b, 1, 10, 0) {
+        Equivalence::Tested(10) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+#[test]
+fn test_random_programs() {
+    let programs = random_programs(0x165667b19e3779f9, 100);
+    for (i, pair) in programs.windows(2).enumerate() {
+        let (a, memory) = &pair[0];
+        let (b, _) = &pair[1];
+        let result = check_equivalence(a, b, memory.len(), 20, i as u32);
+        match result {
+            Equivalence::Different(memory) => {
+                assert_ne!(run(a, &memory), run(b, &memory), "programs {}", i)
+            }
+            result => assert_eq!(result, Equivalence::Tested(20), "programs {}", i),
+        }
+    }
+}
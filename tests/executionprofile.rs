@@ -0,0 +1,248 @@
+#[macro_use]
+mod backends;
+mod common;
+
+#[cfg(feature = "llvm")]
+use aleven::run::compiled_with_processor;
+use aleven::run::{interpreted_with_processor, RunWithProcessor};
+use aleven::{parse_program, ExecutionProfile, MachineOptions, Processor, Program, RunStatus};
+use common::random_programs;
+
+backend_tests! {
+    (interpreted_with_processor, compiled_with_processor) =>
+        test_opcode_counts,
+        test_instruction_counts,
+        test_branches,
+        test_unexecuted,
+        test_profile_over_runs,
+        test_no_profile;
+}
+
+// the same tests, interpreted in small steps
+mod budget {
+    use super::interpreted_with_budget;
+
+    #[test]
+    fn test_opcode_counts() {
+        super::test_opcode_counts(interpreted_with_budget)
+    }
+
+    #[test]
+    fn test_instruction_counts() {
+        super::test_instruction_counts(interpreted_with_budget)
+    }
+
+    #[test]
+    fn test_branches() {
+        super::test_branches(interpreted_with_budget)
+    }
+
+    #[test]
+    fn test_unexecuted() {
+        super::test_unexecuted(interpreted_with_budget)
+    }
+
+    #[test]
+    fn test_profile_over_runs() {
+        super::test_profile_over_runs(interpreted_with_budget)
+    }
+
+    #[test]
+    fn test_no_profile() {
+        super::test_no_profile(interpreted_with_budget)
+    }
+}
+
+fn profiling() -> Processor {
+    Processor::with_options(MachineOptions {
+        profile_execution: true,
+        ..Default::default()
+    })
+}
+
+fn interpreted_with_budget(program: &Program, memory: &mut [u8], processor: &mut Processor) {
+    while program.interpret_with_budget(memory, processor, 3) == RunStatus::Paused {}
+}
+
+// functions a and b are the same, so compiled code may share them
+fn program() -> Program {
+    parse_program(
+        "
+    func main {
+        call inner
+        call a
+        call b
+        call b
+        beq r0 r0 end
+        r6 = addi r6 1
+        target end
+        sb r0 0 = r1
+    }
+
+    repeat inner 4 {
+        r1 = addi r1 1
+        r3 = andi r1 1
+        beq r3 r0 skip
+        r4 = addi r4 1
+        target skip
+    }
+
+    func a {
+        r5 = addi r5 1
+    }
+
+    func b {
+        r5 = addi r5 1
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn opcode_count(profile: &ExecutionProfile, name: &str) -> u64 {
+    profile
+        .opcode_counts()
+        .into_iter()
+        .find(|(opcode, _)| opcode == name)
+        .unwrap()
+        .1
+}
+
+fn test_opcode_counts(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    run(&program(), &mut memory, &mut processor);
+    let profile = processor.execution_profile();
+    assert_eq!(opcode_count(profile, "addi"), 9);
+    assert_eq!(opcode_count(profile, "andi"), 4);
+    assert_eq!(opcode_count(profile, "beq"), 5);
+    assert_eq!(opcode_count(profile, "call"), 4);
+    assert_eq!(opcode_count(profile, "sb"), 1);
+    assert_eq!(opcode_count(profile, "lb"), 0);
+}
+
+fn test_instruction_counts(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    run(&program(), &mut memory, &mut processor);
+    let profile = processor.execution_profile();
+    assert_eq!(profile.count(0, 0), 1);
+    assert_eq!(profile.count(0, 5), 0);
+    assert_eq!(profile.count(1, 0), 4);
+    assert_eq!(profile.count(1, 3), 2);
+    assert_eq!(profile.count(2, 0), 1);
+    assert_eq!(profile.count(3, 0), 2);
+    assert_eq!(profile.count(4, 0), 0);
+}
+
+fn test_branches(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    run(&program(), &mut memory, &mut processor);
+    let profile = processor.execution_profile();
+    assert_eq!(profile.taken(1, 2), 2);
+    assert_eq!(profile.not_taken(1, 2), 2);
+    assert_eq!(profile.taken_ratio(1, 2), Some(0.5));
+    assert_eq!(profile.taken_ratio(0, 4), Some(1.0));
+    assert_eq!(profile.taken_ratio(0, 5), None);
+}
+
+fn test_unexecuted(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    let program = program();
+    run(&program, &mut memory, &mut processor);
+    assert_eq!(
+        processor.execution_profile().unexecuted(&program),
+        vec![(0, 5)]
+    );
+}
+
+fn test_profile_over_runs(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    run(&program(), &mut memory, &mut processor);
+    run(&program(), &mut memory, &mut processor);
+    assert_eq!(processor.execution_profile().count(1, 0), 8);
+
+    let profile = processor.take_execution_profile();
+    assert_eq!(opcode_count(&profile, "call"), 8);
+    assert_eq!(processor.execution_profile(), &ExecutionProfile::new());
+}
+
+fn test_no_profile(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = Processor::new();
+    run(&program(), &mut memory, &mut processor);
+    assert_eq!(processor.execution_profile(), &ExecutionProfile::new());
+}
+
+#[cfg(feature = "cranelift")]
+#[test]
+fn test_profile_rejected_by_cranelift() {
+    assert!(aleven::CraneliftCodeGen::with_options(profiling().options()).is_err());
+}
+
+#[test]
+fn test_fit_keeps_counts() {
+    let small = parse_program(
+        "
+    func main {
+        r1 = addi r1 1
+    }
+    ",
+    )
+    .unwrap();
+    let mut processor = profiling();
+    small.interpret_with_processor(&mut [], &mut processor);
+
+    let mut profile = processor.take_execution_profile();
+    profile.fit(&program());
+    assert_eq!(profile.count(0, 0), 1);
+    assert_eq!(profile.count(3, 0), 0);
+    assert_eq!(opcode_count(&profile, "addi"), 1);
+}
+
+#[test]
+fn test_profile_random_programs() {
+    for (i, (program, memory)) in random_programs(0x94d049bb133111eb, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let mut memory_interpreted = memory.clone();
+        let mut processor_interpreted = profiling();
+        interpreted_with_processor(
+            &program,
+            &mut memory_interpreted,
+            &mut processor_interpreted,
+        );
+
+        let mut memory_budget = memory.clone();
+        let mut processor_budget = profiling();
+        interpreted_with_budget(&program, &mut memory_budget, &mut processor_budget);
+
+        assert_eq!(memory_budget, memory_interpreted, "program {}", i);
+
+        assert_eq!(
+            processor_budget.execution_profile(),
+            processor_interpreted.execution_profile(),
+            "program {}",
+            i
+        );
+
+        #[cfg(feature = "llvm")]
+        {
+            let mut memory_compiled = memory.clone();
+            let mut processor_compiled = profiling();
+            compiled_with_processor(&program, &mut memory_compiled, &mut processor_compiled);
+
+            assert_eq!(memory_compiled, memory_interpreted, "program {}", i);
+            assert_eq!(
+                processor_compiled.execution_profile(),
+                processor_interpreted.execution_profile(),
+                "program {}",
+                i
+            );
+        }
+    }
+}
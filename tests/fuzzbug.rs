@@ -1,9 +1,30 @@
-use aleven::parse;
-use aleven::run::{run_interpreter_func, run_llvm_func, RunnerFunc};
+#[macro_use]
+mod backends;
+
+use aleven::run::RunnerFunc;
 use aleven::Serializer;
-use parameterized::parameterized;
+#[cfg(feature = "llvm")]
+use aleven::{
+    parse,
+    run::{run_interpreter_func, run_llvm_func},
+};
+
+backend_tests! {
+    (run_interpreter_func, run_llvm_func, run_cranelift_func) =>
+        test_bug1,
+        test_bug2,
+        test_bug3,
+        test_bug4,
+        test_bug5,
+        test_bug6,
+        test_bug7,
+        test_bug8,
+        test_bug9,
+        test_bug10,
+        test_bug11,
+        test_bug12;
+}
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug1(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[10, 0, 43, 45]);
@@ -11,7 +32,6 @@ fn test_bug1(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug2(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[11, 42, 222, 10]);
@@ -19,7 +39,6 @@ fn test_bug2(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug3(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[]);
@@ -27,7 +46,6 @@ fn test_bug3(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug4(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[7, 92, 209, 218, 176]);
@@ -35,7 +53,6 @@ fn test_bug4(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug5(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[254, 22, 68, 156, 25, 49]);
@@ -43,7 +60,6 @@ fn test_bug5(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug6(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions =
@@ -52,7 +68,6 @@ fn test_bug6(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug7(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[
@@ -63,7 +78,6 @@ fn test_bug7(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug8(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let instructions = assembler.deserialize(&[
@@ -74,7 +88,6 @@ fn test_bug8(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug9(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let data = [
@@ -87,7 +100,6 @@ fn test_bug9(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug10(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let data = [25, 24, 24, 24, 24, 24];
@@ -96,7 +108,6 @@ fn test_bug10(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug11(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let data = [
@@ -108,7 +119,6 @@ fn test_bug11(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_bug12(runner: RunnerFunc) {
     let assembler = Serializer::new();
     let data = [
@@ -123,6 +133,7 @@ fn test_bug12(runner: RunnerFunc) {
     runner(&instructions, &mut memory);
 }
 
+#[cfg(feature = "llvm")]
 #[test]
 fn test_bug13() {
     let data = [
@@ -154,6 +165,7 @@ fn test_bug13() {
     assert_eq!(memory0, memory1);
 }
 
+#[cfg(feature = "llvm")]
 #[test]
 fn test_bug14() {
     let data = [
@@ -188,6 +200,7 @@ fn test_bug14() {
     assert_eq!(memory0, memory1);
 }
 
+#[cfg(feature = "llvm")]
 #[test]
 fn test_bug15() {
     let data = [
@@ -1,9 +1,30 @@
+#[macro_use]
+mod backends;
+
 use aleven::parse;
-use aleven::run::{run_interpreter_func, run_llvm_func, RunnerFunc};
+use aleven::run::RunnerFunc;
 use byteorder::{ByteOrder, LittleEndian};
-use parameterized::parameterized;
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
+backend_tests! {
+    (run_interpreter_func, run_llvm_func, run_cranelift_func) =>
+        test_addi_basic,
+        test_addi_register_has_value,
+        test_addi_register_rs_is_rd,
+        test_addi_register_dec,
+        test_slti_less,
+        test_slti_less_negative,
+        test_sltiu_less,
+        test_slti_equal,
+        test_slti_greater,
+        test_andi,
+        test_ori,
+        test_xori,
+        test_slli,
+        test_slli_negative,
+        test_srai,
+        test_srli_zero_extends;
+}
+
 fn test_addi_basic(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -17,7 +38,6 @@ fn test_addi_basic(runner: RunnerFunc) {
     assert_eq!(memory[10], 33);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_addi_register_has_value(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -32,7 +52,6 @@ fn test_addi_register_has_value(runner: RunnerFunc) {
     assert_eq!(memory[10], 43);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_addi_register_rs_is_rd(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -47,7 +66,6 @@ fn test_addi_register_rs_is_rd(runner: RunnerFunc) {
     assert_eq!(memory[10], 43);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_addi_register_dec(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -62,7 +80,6 @@ fn test_addi_register_dec(runner: RunnerFunc) {
     assert_eq!(memory[10], 9);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slti_less(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -76,7 +93,6 @@ fn test_slti_less(runner: RunnerFunc) {
     assert_eq!(memory[10], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slti_less_negative(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -90,7 +106,6 @@ fn test_slti_less_negative(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sltiu_less(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -105,7 +120,6 @@ fn test_sltiu_less(runner: RunnerFunc) {
     assert_eq!(memory[10], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slti_equal(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -120,7 +134,6 @@ fn test_slti_equal(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slti_greater(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -135,7 +148,6 @@ fn test_slti_greater(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_andi(runner: RunnerFunc) {
     let b1 = 0b1010101.to_string();
     let b2 = 0b1111110.to_string();
@@ -154,7 +166,6 @@ fn test_andi(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b1010100);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_ori(runner: RunnerFunc) {
     let b1 = 0b1010100.to_string();
     let b2 = 0b1111110.to_string();
@@ -173,7 +184,6 @@ fn test_ori(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b1111110);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_xori(runner: RunnerFunc) {
     let b1 = 0b1010100.to_string();
     let b2 = 0b1111010.to_string();
@@ -192,7 +202,6 @@ fn test_xori(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b0101110);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slli(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -207,7 +216,21 @@ fn test_slli(runner: RunnerFunc) {
     assert_eq!(memory[10], 20);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
+fn test_slli_negative(runner: RunnerFunc) {
+    let instructions = parse(
+        "
+    r1 = addi r1 5
+    r2 = slli r1 -2
+    sb r3 10 = r2",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    runner(&instructions, &mut memory);
+    // a negative shift is a large unsigned shift, so it does nothing
+    assert_eq!(memory[10], 5);
+}
+
 fn test_srai(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -222,7 +245,6 @@ fn test_srai(runner: RunnerFunc) {
     assert_eq!(memory[10], 5);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_srli_zero_extends(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -1,9 +1,24 @@
+#[macro_use]
+mod backends;
+
 use aleven::parse;
-use aleven::run::{run_interpreter_func, run_llvm_func, RunnerFunc};
+use aleven::run::RunnerFunc;
 use byteorder::{ByteOrder, LittleEndian};
-use parameterized::parameterized;
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
+backend_tests! {
+    (run_interpreter_func, run_llvm_func, run_cranelift_func) =>
+        test_lb_in_bounds,
+        test_lb_out_of_bounds_means_zero,
+        test_lbu_out_of_bounds_means_nop,
+        test_lh_sh,
+        test_lh_aligns,
+        test_lh_out_of_bounds,
+        test_lb_sign_extends,
+        test_lbu_zero_extends,
+        test_lb_sign_extends_with_sra,
+        test_lbu_zero_extends_sra;
+}
+
 fn test_lb_in_bounds(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -18,7 +33,6 @@ fn test_lb_in_bounds(runner: RunnerFunc) {
     assert_eq!(memory[10], 11);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lb_out_of_bounds_means_zero(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -33,7 +47,6 @@ fn test_lb_out_of_bounds_means_zero(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lbu_out_of_bounds_means_nop(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -47,7 +60,6 @@ fn test_lbu_out_of_bounds_means_nop(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lh_sh(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -65,7 +77,6 @@ fn test_lh_sh(runner: RunnerFunc) {
     assert_eq!(memory[21], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lh_aligns(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -82,7 +93,6 @@ fn test_lh_aligns(runner: RunnerFunc) {
     assert_eq!(memory[21], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lh_out_of_bounds(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -96,7 +106,6 @@ fn test_lh_out_of_bounds(runner: RunnerFunc) {
     assert_eq!(memory, [0u8; 64]);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lb_sign_extends(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -112,7 +121,6 @@ fn test_lb_sign_extends(runner: RunnerFunc) {
     assert_eq!(value, -4);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lbu_zero_extends(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -128,7 +136,6 @@ fn test_lbu_zero_extends(runner: RunnerFunc) {
     assert_eq!(value, 252);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lb_sign_extends_with_sra(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -146,7 +153,6 @@ fn test_lb_sign_extends_with_sra(runner: RunnerFunc) {
     assert_eq!(value, 0xFFFFu16 as i16);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_lbu_zero_extends_sra(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -0,0 +1,153 @@
+#[macro_use]
+mod backends;
+#[cfg(feature = "llvm")]
+mod common;
+
+use aleven::run::RunWithProcessor;
+#[cfg(feature = "llvm")]
+use aleven::run::{compiled_with_processor, interpreted_with_processor};
+use aleven::{parse_program, MachineOptions, MemoryProfile, Processor, Program};
+#[cfg(feature = "llvm")]
+use common::random_programs;
+
+backend_tests! {
+    (interpreted_with_processor, compiled_with_processor) =>
+        test_profile,
+        test_profile_over_runs,
+        test_no_profile,
+        test_profile_in_window;
+}
+
+fn profiling() -> Processor {
+    Processor::with_options(MachineOptions {
+        profile_memory: true,
+        ..Default::default()
+    })
+}
+
+fn program() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = lbu r0 1
+        r2 = lh r0 1
+        sb r0 5 = r1
+        sh r0 3 = r2
+        r3 = lb r0 100
+        sb r0 100 = r3
+        call inner
+    }
+
+    repeat inner 2 {
+        r4 = lb r0 1
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn test_profile(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    run(&program(), &mut memory, &mut processor);
+    let profile = processor.memory_profile();
+    assert_eq!(profile.reads(), &[0, 3, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(profile.writes(), &[0, 0, 0, 0, 0, 1, 1, 1]);
+}
+
+fn test_profile_over_runs(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = profiling();
+    run(&program(), &mut memory, &mut processor);
+    run(&program(), &mut memory, &mut processor);
+    assert_eq!(processor.memory_profile().reads()[1], 6);
+
+    let profile = processor.take_memory_profile();
+    assert_eq!(profile.writes()[5], 2);
+    assert!(processor.memory_profile().is_empty());
+}
+
+fn test_no_profile(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = Processor::new();
+    run(&program(), &mut memory, &mut processor);
+    assert!(processor.memory_profile().is_empty());
+}
+
+#[cfg(feature = "cranelift")]
+#[test]
+fn test_profile_rejected_by_cranelift() {
+    assert!(aleven::CraneliftCodeGen::with_options(profiling().options()).is_err());
+}
+
+fn test_profile_in_window(run: RunWithProcessor) {
+    let mut memory = [0u8; 8];
+    let mut processor = Processor::with_options(MachineOptions {
+        profile_memory: true,
+        window_size: Some(8),
+        ..Default::default()
+    });
+    processor.set_base(4);
+    run(&program(), &mut memory, &mut processor);
+    let profile = processor.memory_profile();
+    assert_eq!(profile.reads(), &[0, 0, 0, 0, 0, 3, 1, 1]);
+    assert_eq!(profile.writes(), &[0, 1, 1, 1, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_csv() {
+    let mut profile = MemoryProfile::new(3);
+    profile.record_read(0);
+    profile.record_read(0);
+    profile.record_write(2);
+    assert_eq!(
+        profile.to_csv(),
+        "address,reads,writes\n0,2,0\n1,0,0\n2,0,1\n"
+    );
+}
+
+#[test]
+fn test_pgm() {
+    let mut profile = MemoryProfile::new(5);
+    for _ in 0..4 {
+        profile.record_read(1);
+    }
+    profile.record_write(2);
+    profile.record_read(4);
+    profile.record_write(4);
+
+    let mut pgm = Vec::new();
+    profile.write_pgm(&mut pgm, 2).unwrap();
+    let header = b"P5\n2 3\n255\n";
+    assert_eq!(&pgm[..header.len()], header);
+    assert_eq!(&pgm[header.len()..], &[0, 255, 63, 0, 127, 0]);
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn test_profile_random_programs() {
+    for (i, (program, memory)) in random_programs(0xbf58476d1ce4e5b9, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let mut memory_interpreted = memory.clone();
+        let mut processor_interpreted = profiling();
+        interpreted_with_processor(
+            &program,
+            &mut memory_interpreted,
+            &mut processor_interpreted,
+        );
+
+        let mut memory_compiled = memory.clone();
+        let mut processor_compiled = profiling();
+        compiled_with_processor(&program, &mut memory_compiled, &mut processor_compiled);
+
+        assert_eq!(memory_compiled, memory_interpreted, "program {}", i);
+        assert_eq!(
+            processor_compiled.memory_profile(),
+            processor_interpreted.memory_profile(),
+            "program {}",
+            i
+        );
+    }
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use aleven::{
+    disassemble_program, minimize_genome, minimize_program, parse_program, MachineOptions,
+    Processor, Program, Serializer,
+};
+use common::random_programs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+fn instruction_count(program: &Program) -> usize {
+    program
+        .functions()
+        .iter()
+        .map(|function| function.get_instructions().len())
+        .sum()
+}
+
+fn stackmachine_memory() -> Vec<u8> {
+    let mut memory = vec![0u8; 1024];
+    memory[..9].copy_from_slice(&[4, 0, 200, 0, 1, 1, 3, 1, 3]);
+    memory
+}
+
+#[test]
+fn test_minimize_stackmachine() {
+    let f = File::open("stackmachine.ale").unwrap();
+    let mut reader = BufReader::new(f);
+    let mut buffer = String::new();
+    reader.read_to_string(&mut buffer).unwrap();
+    let program = parse_program(&buffer).unwrap();
+
+    let writes_3 = |program: &Program| {
+        let mut memory = stackmachine_memory();
+        program.interpret(&mut memory);
+        memory[400] == 3
+    };
+    let minimized = minimize_program(&program, writes_3);
+    assert!(writes_3(&minimized));
+    // the stack machine still has to dispatch to its instructions
+    assert!(instruction_count(&minimized) < instruction_count(&program) / 2);
+}
+
+#[test]
+fn test_minimize_discrepancy() {
+    let run = |program: &Program, zero_register| {
+        let mut memory = vec![0u8; 64];
+        let mut processor = Processor::with_options(MachineOptions {
+            zero_register,
+            ..Default::default()
+        });
+        program.interpret_with_processor(&mut memory, &mut processor);
+        memory
+    };
+    let differ = |program: &Program| run(program, false) != run(program, true);
+    let (program, _) = random_programs(0x1d8e4e27c47d124f, 20)
+        .into_iter()
+        .find(|(program, _)| differ(program))
+        .unwrap();
+    let minimized = minimize_program(&program, differ);
+    assert!(differ(&minimized));
+    // no single instruction can be left out
+    let source = disassemble_program(&minimized);
+    let lines: Vec<_> = source.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if line.starts_with("    ") {
+            let mut without = lines.clone();
+            without.remove(i);
+            let without = parse_program(&without.join("\n")).unwrap();
+            assert!(!differ(&without), "{} can be left out", line.trim());
+        }
+    }
+}
+
+#[test]
+fn test_minimize_genome() {
+    let serializer = Serializer::new();
+    // genomes rarely store, so look at a register instead
+    let sets_r1 = |genome: &[u8], memory: &[u8]| {
+        let program = Program::from_instructions(&serializer.deserialize(genome));
+        let mut processor = Processor::new();
+        program.interpret_with_processor(&mut memory.to_vec(), &mut processor);
+        processor.registers()[1] != 0
+    };
+    let (genome, memory) = random_programs(0xff51afd7ed558ccd, 100)
+        .into_iter()
+        .map(|(program, memory)| {
+            let genome = serializer.serialize(program.functions()[1].get_instructions());
+            (genome, memory)
+        })
+        .find(|(genome, memory)| genome.len() > 50 && sets_r1(genome, memory))
+        .unwrap();
+    let minimized = minimize_genome(&genome, |genome| sets_r1(genome, &memory));
+    assert!(sets_r1(&minimized, &memory));
+    // no single byte can be left out
+    for i in 0..minimized.len() {
+        let mut without = minimized.clone();
+        without.remove(i);
+        assert!(!sets_r1(&without, &memory), "byte {} can be left out", i);
+    }
+}
+
+#[test]
+fn test_predicate_not_holding() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 1
+    }
+    ",
+    )
+    .unwrap();
+    assert_eq!(minimize_program(&program, |_| false), program);
+    assert_eq!(minimize_genome(&[1, 2, 3], |_| false), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_minimize_program_keeps_branches_forward() {
+    let program = parse_program(
+        "
+    func main {
+        target t1
+        r1 = addi r1 1
+        beq r0 r0 t1
+        sb r0 0 = r1
+        target t1
+    }
+    ",
+    )
+    .unwrap();
+    // removing the last target would turn the branch into a backward one,
+    // which never finishes when interpreted
+    let has_branch = |program: &Program| {
+        program.interpret(&mut [0u8; 64]);
+        disassemble_program(program).contains("beq")
+    };
+    let minimized = minimize_program(&program, has_branch);
+    assert_eq!(
+        disassemble_program(&minimized),
+        "func f0 {
+    beq r0 r0 t1
+    target t1
+}
+"
+    );
+}
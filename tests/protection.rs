@@ -0,0 +1,232 @@
+#[macro_use]
+mod backends;
+mod common;
+
+#[cfg(feature = "llvm")]
+use aleven::run::compiled_with_processor;
+#[cfg(feature = "cranelift")]
+use aleven::run::cranelift_compiled_with_processor;
+use aleven::run::{interpreted_with_processor, RunWithProcessor};
+use aleven::{
+    parse_program, MachineOptions, MemoryProtection, Permissions, Processor, Program, RunStatus,
+};
+use common::random_programs;
+
+backend_tests! {
+    (interpreted_with_processor, compiled_with_processor, cranelift_compiled_with_processor) =>
+        test_read_only_region,
+        test_halfword_across_region_boundary,
+        test_unreadable_region,
+        test_overlapping_regions,
+        test_violations_per_run,
+        test_protection_in_window;
+}
+
+fn protected(options: MachineOptions, regions: &[(usize, usize, Permissions)]) -> Processor {
+    let mut protection = MemoryProtection::new();
+    for (start, end, permissions) in regions {
+        protection.add_region(*start, *end, *permissions);
+    }
+    let mut processor = Processor::with_options(options);
+    processor.set_protection(protection);
+    processor
+}
+
+fn counting() -> MachineOptions {
+    MachineOptions {
+        count_violations: true,
+        ..Default::default()
+    }
+}
+
+fn store_everywhere() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = addi r0 -1
+        sb r0 0 = r1
+        sb r0 4 = r1
+        sb r0 7 = r1
+        sb r0 8 = r1
+        sh r0 1 = r1
+        sh r0 3 = r1
+        sh r0 5 = r1
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn test_read_only_region(run: RunWithProcessor) {
+    let mut memory = [0u8; 12];
+    memory[5] = 5;
+    let mut processor = protected(counting(), &[(4, 8, Permissions::READ_ONLY)]);
+    run(&store_everywhere(), &mut memory, &mut processor);
+    // the halfword at 6 and 7 is in the region, the one at 2 and 3 is not
+    assert_eq!(memory, [255, 0, 255, 255, 0, 5, 0, 0, 255, 0, 255, 255]);
+    assert_eq!(processor.violations(), 3);
+}
+
+fn test_halfword_across_region_boundary(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 -1
+        sh r0 1 = r1
+        r2 = lh r0 2
+        sh r0 3 = r2
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0u8; 8];
+    memory[4] = 1;
+    memory[5] = 2;
+    let mut processor = protected(counting(), &[(3, 4, Permissions::NONE)]);
+    run(&program, &mut memory, &mut processor);
+    // only byte 3 of the first halfword is forbidden, but the whole store is
+    assert_eq!(memory, [0, 0, 0, 0, 1, 2, 1, 2]);
+    assert_eq!(processor.violations(), 1);
+}
+
+fn test_unreadable_region(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 7
+        r1 = lbu r0 1
+        r2 = lbu r0 2
+        sb r0 3 = r1
+        sb r0 4 = r2
+        sb r0 1 = r2
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0, 10, 20, 0, 0, 0];
+    let mut processor = protected(
+        counting(),
+        &[(
+            1,
+            2,
+            Permissions {
+                read: false,
+                write: true,
+            },
+        )],
+    );
+    run(&program, &mut memory, &mut processor);
+    assert_eq!(memory, [0, 20, 20, 0, 20, 0]);
+    assert_eq!(processor.violations(), 1);
+}
+
+fn test_overlapping_regions(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lbu r0 2
+        r1 = addi r1 1
+        sb r0 2 = r1
+        sb r0 4 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0, 0, 9, 0, 0, 0];
+    let mut processor = protected(
+        counting(),
+        &[
+            (0, 4, Permissions::READ_ONLY),
+            (2, 6, Permissions::READ_WRITE),
+        ],
+    );
+    run(&program, &mut memory, &mut processor);
+    assert_eq!(memory, [0, 0, 9, 0, 10, 0]);
+    assert_eq!(processor.violations(), 1);
+}
+
+fn test_violations_per_run(run: RunWithProcessor) {
+    let regions = [(0, 4, Permissions::READ_ONLY)];
+    let mut memory = [0u8; 12];
+    let mut processor = protected(counting(), &regions);
+    run(&store_everywhere(), &mut memory, &mut processor);
+    run(&store_everywhere(), &mut memory, &mut processor);
+    assert_eq!(processor.violations(), 2);
+
+    let mut processor = protected(MachineOptions::default(), &regions);
+    run(&store_everywhere(), &mut memory, &mut processor);
+    assert_eq!(processor.violations(), 0);
+}
+
+fn test_protection_in_window(run: RunWithProcessor) {
+    let options = MachineOptions {
+        window_size: Some(8),
+        count_violations: true,
+        ..Default::default()
+    };
+    let mut memory = [0u8; 12];
+    let mut processor = protected(options, &[(0, 2, Permissions::READ_ONLY)]);
+    processor.set_base(6);
+    run(&store_everywhere(), &mut memory, &mut processor);
+    // the region stays where it is in memory, so it's at 6 and 7 in the window
+    assert_eq!(memory, [0, 0, 0, 0, 0, 0, 255, 0, 255, 255, 255, 0]);
+    assert_eq!(processor.violations(), 2);
+}
+
+#[test]
+fn test_violations_with_budget() {
+    let mut memory = [0u8; 12];
+    let mut processor = protected(counting(), &[(0, 12, Permissions::READ_ONLY)]);
+    let program = store_everywhere();
+    while program.interpret_with_budget(&mut memory, &mut processor, 2) == RunStatus::Paused {}
+    assert_eq!(processor.violations(), 7);
+    assert_eq!(memory, [0; 12]);
+}
+
+#[test]
+fn test_protected_random_programs() {
+    let runs: Vec<RunWithProcessor> = vec![
+        #[cfg(feature = "llvm")]
+        compiled_with_processor,
+        #[cfg(feature = "cranelift")]
+        cranelift_compiled_with_processor,
+    ];
+    for (i, (program, memory)) in random_programs(0x5851f42d4c957f2d, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let len = memory.len();
+        let regions = [
+            (i % len, i % len + 16, Permissions::READ_ONLY),
+            (len / 2, len / 2 + i % 8, Permissions::NONE),
+        ];
+
+        let mut memory_interpreted = memory.clone();
+        let mut processor_interpreted = protected(counting(), &regions);
+        interpreted_with_processor(
+            &program,
+            &mut memory_interpreted,
+            &mut processor_interpreted,
+        );
+
+        for run in &runs {
+            let mut memory_compiled = memory.clone();
+            let mut processor_compiled = protected(counting(), &regions);
+            run(&program, &mut memory_compiled, &mut processor_compiled);
+
+            assert_eq!(memory_compiled, memory_interpreted, "program {}", i);
+            assert_eq!(
+                processor_compiled.registers(),
+                processor_interpreted.registers(),
+                "program {}",
+                i
+            );
+            assert_eq!(
+                processor_compiled.violations(),
+                processor_interpreted.violations(),
+                "program {}",
+                i
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+#[macro_use]
+mod backends;
+mod common;
+
+#[cfg(feature = "llvm")]
+use aleven::run::compiled_with_seed;
+#[cfg(feature = "cranelift")]
+use aleven::run::cranelift_compiled_with_seed;
+use aleven::run::{interpreted_with_seed, RunWithSeed};
+use aleven::{next_random, parse_program, Processor, ProcessorState, Program, RunStatus};
+use common::random_programs;
+
+backend_tests! {
+    (interpreted_with_seed, compiled_with_seed, cranelift_compiled_with_seed) =>
+        test_rand_sequence,
+        test_rand_reproducible;
+}
+
+fn program() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = rand
+        sh r0 0 = r1
+        call more
+    }
+
+    repeat more 3 {
+        r2 = rand
+        sh r3 1 = r2
+        r3 = addi r3 1
+    }
+    ",
+    )
+    .unwrap()
+}
+
+fn test_rand_sequence(run: RunWithSeed) {
+    let seed = 0x1234_5678;
+    let mut expected_state = seed;
+    let expected: Vec<i16> = (0..4).map(|_| next_random(&mut expected_state)).collect();
+
+    let mut memory = [0u8; 8];
+    let mut rng_state = seed;
+    run(&program(), &mut memory, &mut rng_state);
+
+    let values: Vec<i16> = memory
+        .chunks(2)
+        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+        .collect();
+    assert_eq!(values, expected);
+    assert_eq!(rng_state, expected_state);
+}
+
+fn test_rand_reproducible(run: RunWithSeed) {
+    let mut memory_a = [0u8; 8];
+    let mut memory_b = [0u8; 8];
+    run(&program(), &mut memory_a, &mut 7);
+    run(&program(), &mut memory_b, &mut 7);
+    assert_eq!(memory_a, memory_b);
+
+    let mut memory_c = [0u8; 8];
+    run(&program(), &mut memory_c, &mut 8);
+    assert_ne!(memory_a, memory_c);
+}
+
+#[test]
+fn test_rand_state_in_snapshot() {
+    let program = program();
+    let mut memory_sliced = [0u8; 8];
+    let mut processor = Processor::new();
+    processor.set_rng_state(99);
+    let mut state = processor.state();
+    loop {
+        let mut processor = Processor::from_state(&state);
+        let status = program.interpret_with_budget(&mut memory_sliced, &mut processor, 1);
+        state = ProcessorState::deserialize(&processor.state().serialize()).unwrap();
+        if status == RunStatus::Finished {
+            break;
+        }
+    }
+
+    let mut memory = [0u8; 8];
+    let mut rng_state = 99;
+    interpreted_with_seed(&program, &mut memory, &mut rng_state);
+    assert_eq!(memory_sliced, memory);
+    assert_eq!(state.rng, rng_state);
+}
+
+#[test]
+fn test_random_programs_seeded_same_as_interpreter() {
+    let runs: Vec<RunWithSeed> = vec![
+        #[cfg(feature = "llvm")]
+        compiled_with_seed,
+        #[cfg(feature = "cranelift")]
+        cranelift_compiled_with_seed,
+    ];
+    for (index, (program, memory)) in random_programs(0x9e3779b97f4a7c15, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let seed = (index as u32).wrapping_mul(0x9e37_79b9);
+        let mut memory_interpreter = memory.clone();
+        let mut state_interpreter = seed;
+        interpreted_with_seed(&program, &mut memory_interpreter, &mut state_interpreter);
+
+        for run in &runs {
+            let mut memory_compiled = memory.clone();
+            let mut state_compiled = seed;
+            run(&program, &mut memory_compiled, &mut state_compiled);
+            assert_eq!(memory_compiled, memory_interpreter, "{:?}", program);
+            assert_eq!(state_compiled, state_interpreter);
+        }
+    }
+}
@@ -1,9 +1,40 @@
-use aleven::parse;
-use aleven::run::{run_interpreter_func, run_llvm_func, RunnerFunc};
+#[macro_use]
+mod backends;
+
+use aleven::run::{RunnerFunc, RunnerFuncWithOptions};
+use aleven::{parse, MachineOptions};
 use byteorder::{ByteOrder, LittleEndian};
-use parameterized::parameterized;
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
+backend_tests! {
+    (run_interpreter_func, run_llvm_func, run_cranelift_func) =>
+        test_add,
+        test_add_negative,
+        test_sub,
+        test_add_wrapping,
+        test_add_sh,
+        test_slt_less,
+        test_slt_less_negative,
+        test_slt_equal,
+        test_slt_greater,
+        test_sltu_less,
+        test_sltu_less_negative,
+        test_sltu_equal,
+        test_sltu_greater,
+        test_and,
+        test_or,
+        test_xor,
+        test_sll,
+        test_sll_shift_too_large,
+        test_srl,
+        test_srl_too_large,
+        test_srl_negative,
+        test_sra,
+        test_sra_negative;
+    (run_interpreter_func_with_options, run_llvm_func_with_options, run_cranelift_func_with_options) =>
+        test_zero_register_ignores_writes,
+        test_zero_register_off_by_default;
+}
+
 fn test_add(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -20,7 +51,6 @@ fn test_add(runner: RunnerFunc) {
     assert_eq!(memory[10], 77);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_add_negative(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -37,7 +67,6 @@ fn test_add_negative(runner: RunnerFunc) {
     assert_eq!(memory[10], 22);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sub(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -54,7 +83,6 @@ fn test_sub(runner: RunnerFunc) {
     assert_eq!(memory[10], 22);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_add_wrapping(runner: RunnerFunc) {
     let max = i16::MAX.to_string();
     let code = format!(
@@ -72,7 +100,6 @@ fn test_add_wrapping(runner: RunnerFunc) {
     assert_eq!(value, i16::MIN);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_add_sh(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -90,7 +117,6 @@ fn test_add_sh(runner: RunnerFunc) {
     assert_eq!(value, 255 * 2);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slt_less(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -107,7 +133,6 @@ fn test_slt_less(runner: RunnerFunc) {
     assert_eq!(memory[10], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slt_less_negative(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -124,7 +149,6 @@ fn test_slt_less_negative(runner: RunnerFunc) {
     assert_eq!(memory[10], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slt_equal(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -141,7 +165,6 @@ fn test_slt_equal(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_slt_greater(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -158,7 +181,6 @@ fn test_slt_greater(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sltu_less(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -175,7 +197,6 @@ fn test_sltu_less(runner: RunnerFunc) {
     assert_eq!(memory[10], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sltu_less_negative(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -192,7 +213,6 @@ fn test_sltu_less_negative(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sltu_equal(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -209,7 +229,6 @@ fn test_sltu_equal(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sltu_greater(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -226,7 +245,6 @@ fn test_sltu_greater(runner: RunnerFunc) {
     assert_eq!(memory[10], 0);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_and(runner: RunnerFunc) {
     let b1 = 0b1010101.to_string();
     let b2 = 0b1111110.to_string();
@@ -246,7 +264,6 @@ fn test_and(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b1010100);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_or(runner: RunnerFunc) {
     let b1 = 0b1010100.to_string();
     let b2 = 0b1111110.to_string();
@@ -266,7 +283,6 @@ fn test_or(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b1111110);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_xor(runner: RunnerFunc) {
     let b1 = 0b1111010.to_string();
     let b2 = 0b1010100.to_string();
@@ -286,7 +302,6 @@ fn test_xor(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b0101110);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sll(runner: RunnerFunc) {
     let b1 = 0b101.to_string();
 
@@ -305,7 +320,6 @@ fn test_sll(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b10100);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sll_shift_too_large(runner: RunnerFunc) {
     let b1 = 0b101.to_string();
 
@@ -324,7 +338,6 @@ fn test_sll_shift_too_large(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b101);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_srl(runner: RunnerFunc) {
     let b1 = 0b10100.to_string();
 
@@ -343,7 +356,6 @@ fn test_srl(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b101);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_srl_too_large(runner: RunnerFunc) {
     let b1 = 0b10100.to_string();
 
@@ -362,7 +374,6 @@ fn test_srl_too_large(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b10100);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_srl_negative(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -380,7 +391,6 @@ fn test_srl_negative(runner: RunnerFunc) {
     assert_eq!(value, 16379);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sra(runner: RunnerFunc) {
     let b1 = 0b10100.to_string();
 
@@ -399,7 +409,6 @@ fn test_sra(runner: RunnerFunc) {
     assert_eq!(memory[10], 0b101);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sra_negative(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -416,3 +425,45 @@ fn test_sra_negative(runner: RunnerFunc) {
     let value = LittleEndian::read_i16(&memory[20..]);
     assert_eq!(value, -5);
 }
+
+fn test_zero_register_ignores_writes(runner: RunnerFuncWithOptions) {
+    let instructions = parse(
+        "
+    r0 = addi r0 5
+    r1 = addi r0 3
+    r0 = lbu r1 0
+    r0 = add r1 r1
+    sb r0 10 = r1
+    sh r0 6 = r0
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    memory[3] = 100;
+    runner(
+        &instructions,
+        &mut memory,
+        MachineOptions {
+            zero_register: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(memory[10], 3);
+    assert_eq!(&memory[12..14], &[0, 0]);
+}
+
+fn test_zero_register_off_by_default(runner: RunnerFuncWithOptions) {
+    let instructions = parse(
+        "
+    r0 = addi r0 5
+    r1 = addi r0 3
+    sb r0 10 = r1
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    runner(&instructions, &mut memory, MachineOptions::default());
+    assert_eq!(memory[15], 8);
+}
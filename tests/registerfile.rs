@@ -0,0 +1,101 @@
+#[macro_use]
+mod backends;
+mod common;
+
+use aleven::parse_program;
+#[cfg(feature = "llvm")]
+use aleven::run::compiled_with_registers;
+#[cfg(feature = "cranelift")]
+use aleven::run::cranelift_compiled_with_registers;
+use aleven::run::{interpreted_with_registers, RunWithRegisters};
+use common::random_programs;
+
+backend_tests! {
+    (interpreted_with_registers, compiled_with_registers, cranelift_compiled_with_registers) =>
+        test_registers_in_and_out,
+        test_registers_as_address;
+}
+
+fn test_registers_in_and_out(run: RunWithRegisters) {
+    let program = parse_program(
+        "
+    func main {
+        r3 = add r1 r2
+        call double
+    }
+
+    func double {
+        r3 = add r3 r3
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 8];
+    let mut registers = [0i16; 32];
+    registers[1] = 5;
+    registers[2] = -7;
+    registers[31] = 1000;
+    run(&program, &mut memory, &mut registers);
+    assert_eq!(registers[1], 5);
+    assert_eq!(registers[2], -7);
+    assert_eq!(registers[3], -4);
+    assert_eq!(registers[31], 1000);
+    assert_eq!(memory, [0u8; 8]);
+}
+
+fn test_registers_as_address(run: RunWithRegisters) {
+    let program = parse_program(
+        "
+    func main {
+        r2 = lb r1 0
+        sb r1 1 = r2
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 8];
+    memory[4] = 42;
+    let mut registers = [0i16; 32];
+    registers[1] = 4;
+    run(&program, &mut memory, &mut registers);
+    assert_eq!(memory[5], 42);
+    assert_eq!(registers[2], 42);
+}
+
+#[test]
+fn test_random_programs_registers_same_for_all_backends() {
+    let runs: Vec<RunWithRegisters> = vec![
+        #[cfg(feature = "llvm")]
+        compiled_with_registers,
+        #[cfg(feature = "cranelift")]
+        cranelift_compiled_with_registers,
+    ];
+    for (index, (program, memory)) in random_programs(0xda942042e4dd58b5, 200)
+        .into_iter()
+        .enumerate()
+    {
+        let registers: [i16; 32] = std::array::from_fn(|r| {
+            (index as i16)
+                .wrapping_mul(7919)
+                .wrapping_add(r as i16 * 1031)
+        });
+
+        let mut memory_interpreter = memory.clone();
+        let mut registers_interpreter = registers;
+        interpreted_with_registers(
+            &program,
+            &mut memory_interpreter,
+            &mut registers_interpreter,
+        );
+
+        for run in &runs {
+            let mut memory_compiled = memory.clone();
+            let mut registers_compiled = registers;
+            run(&program, &mut memory_compiled, &mut registers_compiled);
+            assert_eq!(memory_compiled, memory_interpreter, "{:?}", program);
+            assert_eq!(registers_compiled, registers_interpreter, "{:?}", program);
+        }
+    }
+}
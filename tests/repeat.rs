@@ -1,8 +1,14 @@
+#[macro_use]
+mod backends;
+
 use aleven::parse_program;
-use aleven::run::{compiled, interpreted, Run};
-use parameterized::parameterized;
+use aleven::run::Run;
+
+backend_tests! {
+    (interpreted, compiled, cranelift_compiled) =>
+        test_repeat;
+}
 
-#[parameterized(run={compiled, interpreted})]
 fn test_repeat(run: Run) {
     let program = parse_program(
         "
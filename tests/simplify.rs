@@ -0,0 +1,200 @@
+mod common;
+
+use aleven::{disassemble, parse_program, Program, SimplifyReport};
+use common::{next_random, random_programs};
+
+fn run(program: &Program, memory: &[u8]) -> Vec<u8> {
+    let mut memory = memory.to_vec();
+    program.interpret(&mut memory);
+    memory
+}
+
+fn instructions(program: &Program, id: usize) -> String {
+    disassemble(program.functions()[id].get_instructions())
+}
+
+#[test]
+fn test_dead_writes() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 1
+        r2 = addi r0 2
+        r1 = addi r0 3
+        r3 = lb r2 0
+        sb r0 0 = r1
+        r4 = addi r0 4
+    }
+    ",
+    )
+    .unwrap();
+    let (simplified, _) = program.simplify();
+    assert_eq!(instructions(&simplified, 0), "r1 = addi r0 3\nsb r0 0 = r1");
+}
+
+#[test]
+fn test_no_ops() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lb r0 0
+        r1 = addi r1 0
+        r1 = andi r1 -1
+        r1 = or r1 r1
+        r2 = addi r1 0
+        sb r0 0 = r2
+    }
+    ",
+    )
+    .unwrap();
+    let (simplified, _) = program.simplify();
+    assert_eq!(
+        instructions(&simplified, 0),
+        "r1 = lb r0 0\nr2 = addi r1 0\nsb r0 0 = r2"
+    );
+}
+
+#[test]
+fn test_branches_and_targets() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lb r0 0
+        beq r1 r0 next
+        target next
+        target unused
+        bne r1 r0 skip
+        sb r0 1 = r1
+        target skip
+    }
+    ",
+    )
+    .unwrap();
+    let (simplified, _) = program.simplify();
+    assert_eq!(
+        instructions(&simplified, 0),
+        "r1 = lb r0 0\nbne r1 r0 t2\nsb r0 1 = r1\ntarget t2"
+    );
+}
+
+#[test]
+fn test_repeat_keeps_loop_carried_writes() {
+    let program = parse_program(
+        "
+    repeat main 3 {
+        sb r0 0 = r1
+        r1 = addi r1 1
+        r2 = addi r2 1
+    }
+    ",
+    )
+    .unwrap();
+    let (simplified, _) = program.simplify();
+    assert_eq!(instructions(&simplified, 0), "sb r0 0 = r1\nr1 = addi r1 1");
+    assert_eq!(run(&simplified, &[0]), run(&program, &[0]));
+}
+
+#[test]
+fn test_functions() {
+    let program = parse_program(
+        "
+    func main {
+        call empty
+        call used
+    }
+
+    func unused {
+        sb r0 0 = r1
+    }
+
+    repeat empty 3 {
+        r1 = addi r1 0
+    }
+
+    func used {
+        r1 = lb r0 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let (simplified, report) = program.simplify();
+    assert_eq!(simplified.functions().len(), 2);
+    assert_eq!(instructions(&simplified, 0), "call f1");
+    assert_eq!(instructions(&simplified, 1), "r1 = lb r0 1\nsb r0 0 = r1");
+    assert_eq!(report.functions_before, 4);
+    assert_eq!(report.functions_after, 2);
+    // every function also had a synthetic target
+    assert_eq!(report.instructions_before, 10);
+    assert_eq!(report.instructions_after, 3);
+    assert_eq!(report.removed_instructions(), 7);
+    assert_eq!(report.ratio(), 0.3);
+}
+
+#[test]
+fn test_simplify_is_stable() {
+    for (program, _) in random_programs(0x8ebc6af09c88c6e3, 100) {
+        let (simplified, _) = program.simplify();
+        let (twice, report) = simplified.simplify();
+        assert_eq!(twice, simplified);
+        assert_eq!(report.removed_instructions(), 0);
+    }
+}
+
+#[test]
+fn test_simplify_random_programs() {
+    let mut total = SimplifyReport {
+        functions_before: 0,
+        functions_after: 0,
+        instructions_before: 0,
+        instructions_after: 0,
+    };
+    let mut state = 43;
+    for (i, (program, memory)) in random_programs(0x8ebc6af09c88c6e3, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let (simplified, report) = program.simplify();
+        total.functions_before += report.functions_before;
+        total.functions_after += report.functions_after;
+        total.instructions_before += report.instructions_before;
+        total.instructions_after += report.instructions_after;
+        let memories = std::iter::once(memory.clone()).chain((0..4).map(|_| {
+            memory
+                .iter()
+                .map(|_| next_random(&mut state) as u8)
+                .collect()
+        }));
+        for memory in memories {
+            assert_eq!(
+                run(&simplified, &memory),
+                run(&program, &memory),
+                "program {}",
+                i
+            );
+        }
+    }
+    // random programs have a lot of dead code, of which a good deal goes
+    assert!(total.ratio() < 0.6, "{:?}", total);
+    assert!(
+        total.functions_after < total.functions_before,
+        "{:?}",
+        total
+    );
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn test_compile_simplified_random_programs() {
+    use aleven::run::compiled;
+
+    for (i, (program, memory)) in random_programs(0x589965cc75374cc3, 100)
+        .into_iter()
+        .enumerate()
+    {
+        let (simplified, _) = program.simplify();
+        let mut memory_compiled = memory.clone();
+        compiled(&simplified, &mut memory_compiled);
+        assert_eq!(memory_compiled, run(&program, &memory), "program {}", i);
+    }
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use aleven::{parse_program, Frame, MachineOptions, Processor, ProcessorState, RunStatus};
+use common::random_programs;
+
+#[test]
+fn test_pause_and_resume() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r1 1
+        call inner
+        sb r0 0 = r1
+    }
+
+    repeat inner 2 {
+        r1 = addi r1 10
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 4];
+    let mut processor = Processor::new();
+
+    // r1 = addi, call
+    let status = program.interpret_with_budget(&mut memory, &mut processor, 2);
+    assert_eq!(status, RunStatus::Paused);
+    assert_eq!(processor.registers()[1], 1);
+    assert_eq!(
+        processor.call_stack(),
+        &[
+            Frame {
+                function: 0,
+                pc: 2,
+                iteration: 0
+            },
+            Frame {
+                function: 1,
+                pc: 0,
+                iteration: 0
+            }
+        ]
+    );
+
+    // the first iteration of inner, which ends with a synthetic target
+    let status = program.interpret_with_budget(&mut memory, &mut processor, 2);
+    assert_eq!(status, RunStatus::Paused);
+    assert_eq!(processor.registers()[1], 11);
+    assert_eq!(
+        processor.call_stack().last(),
+        Some(&Frame {
+            function: 1,
+            pc: 0,
+            iteration: 1
+        })
+    );
+    assert_eq!(memory[0], 0);
+
+    let status = program.interpret_with_budget(&mut memory, &mut processor, 100);
+    assert_eq!(status, RunStatus::Finished);
+    assert!(!processor.is_paused());
+    assert_eq!(memory[0], 21);
+}
+
+#[test]
+fn test_full_run_discards_paused_run() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 4];
+    let mut processor = Processor::new();
+    let status = program.interpret_with_budget(&mut memory, &mut processor, 1);
+    assert_eq!(status, RunStatus::Paused);
+
+    program.interpret_with_processor(&mut memory, &mut processor);
+    assert!(!processor.is_paused());
+    assert_eq!(memory[0], 2);
+
+    // a new run, rather than the rest of the paused one
+    let status = program.interpret_with_budget(&mut memory, &mut processor, 100);
+    assert_eq!(status, RunStatus::Finished);
+    assert_eq!(memory[0], 3);
+}
+
+#[test]
+fn test_registers_kept_between_runs() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 4];
+    let mut processor = Processor::new();
+    for _ in 0..3 {
+        let status = program.interpret_with_budget(&mut memory, &mut processor, 100);
+        assert_eq!(status, RunStatus::Finished);
+    }
+    assert_eq!(memory[0], 3);
+
+    program.interpret_with_processor(&mut memory, &mut processor);
+    assert_eq!(memory[0], 4);
+}
+
+#[test]
+fn test_restore_from_serialized_state() {
+    let program = parse_program(
+        "
+    repeat main 3 {
+        r1 = addi r1 1
+        sb r1 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 8];
+    let mut processor = Processor::new();
+    processor.registers_mut()[2] = -5;
+    program.interpret_with_budget(&mut memory, &mut processor, 4);
+
+    let bytes = processor.state().serialize();
+    let state = ProcessorState::deserialize(&bytes).unwrap();
+    assert_eq!(state, processor.state());
+    assert_eq!(state.registers[2], -5);
+
+    let mut restored = Processor::from_state(&state);
+    assert_eq!(restored.pc(), 1);
+    program.interpret_with_budget(&mut memory, &mut restored, 100);
+    assert_eq!(&memory[1..4], &[1, 2, 3]);
+}
+
+#[test]
+fn test_restore_windowed_state() {
+    let program = parse_program(
+        "
+    repeat main 3 {
+        r1 = addi r1 1
+        sb r1 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let options = MachineOptions {
+        window_size: Some(4),
+        ..Default::default()
+    };
+
+    let mut memory = [0u8; 8];
+    let mut processor = Processor::with_options(options);
+    processor.set_base(4);
+    program.interpret_with_budget(&mut memory, &mut processor, 4);
+
+    let state = ProcessorState::deserialize(&processor.state().serialize()).unwrap();
+    assert_eq!(state.base, 4);
+
+    let mut restored = Processor::with_options(options);
+    restored.restore(&state);
+    assert_eq!(restored.base(), 4);
+    program.interpret_with_budget(&mut memory, &mut restored, 100);
+    assert_eq!(memory, [0, 0, 0, 0, 0, 1, 2, 3]);
+}
+
+#[test]
+fn test_serialize_base_beyond_4_gib() {
+    let mut processor = Processor::new();
+    processor.set_base(5 << 30);
+    let state = ProcessorState::deserialize(&processor.state().serialize()).unwrap();
+    assert_eq!(Processor::from_state(&state).base(), 5 << 30);
+}
+
+#[test]
+fn test_deserialize_invalid_state() {
+    assert_eq!(ProcessorState::deserialize(&[0; 10]), None);
+    let mut bytes = Processor::new().state().serialize();
+    bytes.push(0);
+    assert_eq!(ProcessorState::deserialize(&bytes), None);
+}
+
+#[test]
+fn test_state_not_fitting_program_is_discarded() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut processor = Processor::from_state(&ProcessorState {
+        registers: [0; 32],
+        rng: 0,
+        base: 0,
+        frames: vec![Frame {
+            function: 7,
+            pc: 0,
+            iteration: 0,
+        }],
+    });
+    let mut memory = [0u8; 4];
+    let status = program.interpret_with_budget(&mut memory, &mut processor, 100);
+    assert_eq!(status, RunStatus::Finished);
+    assert_eq!(memory[0], 1);
+}
+
+#[test]
+fn test_random_programs_in_slices_same_as_interpreter() {
+    for (index, (program, memory)) in random_programs(0x853c49e6748fea9b, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let mut memory_sliced = memory.clone();
+        let mut memory_interpreter = memory;
+
+        // snapshot and restore between every slice
+        let budget = 1 + index as u64 % 7;
+        let mut state = Processor::new().state();
+        loop {
+            let mut processor = Processor::from_state(&state);
+            let status = program.interpret_with_budget(&mut memory_sliced, &mut processor, budget);
+            state = ProcessorState::deserialize(&processor.state().serialize()).unwrap();
+            if status == RunStatus::Finished {
+                break;
+            }
+        }
+
+        let mut processor = Processor::new();
+        program.interpret_with_processor(&mut memory_interpreter, &mut processor);
+
+        assert_eq!(memory_sliced, memory_interpreter, "{:?}", program);
+        assert_eq!(&state.registers, processor.registers());
+    }
+}
@@ -1,17 +1,28 @@
-use aleven::parse_program;
-use aleven::run::{compiled, interpreted, Run};
-use parameterized::parameterized;
+#[macro_use]
+mod backends;
+
+use aleven::run::Run;
+use aleven::{parse_program, Program};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
-#[parameterized(run={compiled, interpreted})]
-fn test_stackmachine(run: Run) {
+backend_tests! {
+    (interpreted, compiled, cranelift_compiled) =>
+        test_stackmachine,
+        test_stackmachine_dispatches_once;
+}
+
+fn stackmachine() -> Program {
     let f = File::open("stackmachine.ale").unwrap();
     let mut reader = BufReader::new(f);
     let mut buffer = String::new();
     reader.read_to_string(&mut buffer).unwrap();
-    let program = parse_program(&buffer).unwrap();
+    parse_program(&buffer).unwrap()
+}
+
+fn test_stackmachine(run: Run) {
+    let program = stackmachine();
 
     let mut memory = [0u8; 1024];
     memory[0] = 4;
@@ -31,3 +42,27 @@ fn test_stackmachine(run: Run) {
     // 400 as 16 bit numbers, little endian
     assert_eq!(memory[400], 3);
 }
+
+fn test_stackmachine_dispatches_once(run: Run) {
+    let program = stackmachine();
+
+    // the last add of each program leaves registers a later case could match
+    for (instructions, sum) in [
+        // 2 over 1, which eq would match after add if opcodes were counted
+        // up from the registers add leaves behind
+        (&[1, 1, 1, 3, 3][..], 3),
+        // 4, which eq would match if the opcode were read from r1
+        (&[0, 1, 1, 3, 1, 3, 1, 3, 3][..], 4),
+    ] {
+        let mut memory = [0u8; 1024];
+        memory[0] = 4;
+        memory[2] = 200;
+        memory[4..4 + instructions.len()].copy_from_slice(instructions);
+
+        run(&program, &mut memory);
+
+        assert_eq!(memory[400], sum, "{:?}", instructions);
+        // the 0 instructions after the program push zeros
+        assert_eq!(memory[402], 0, "{:?}", instructions);
+    }
+}
@@ -1,8 +1,16 @@
+#[macro_use]
+mod backends;
+
 use aleven::parse;
-use aleven::run::{run_interpreter_func, run_llvm_func, RunnerFunc};
-use parameterized::parameterized;
+use aleven::run::RunnerFunc;
+
+backend_tests! {
+    (run_interpreter_func, run_llvm_func, run_cranelift_func) =>
+        test_sb_out_of_bounds,
+        test_sh_aligns,
+        test_sh_out_of_bounds;
+}
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sb_out_of_bounds(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -18,7 +26,6 @@ fn test_sb_out_of_bounds(runner: RunnerFunc) {
     assert_eq!(memory, expected);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sh_aligns(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -35,7 +42,6 @@ fn test_sh_aligns(runner: RunnerFunc) {
     assert_eq!(memory[23], 1);
 }
 
-#[parameterized(runner={run_llvm_func, run_interpreter_func})]
 fn test_sh_out_of_bounds(runner: RunnerFunc) {
     let instructions = parse(
         "
@@ -2,7 +2,7 @@
 // use aleven::run::{compiled, interpreted, Run};
 // use parameterized::parameterized;
 
-// #[parameterized(run={compiled, interpreted})]
+// #[parameterized(run={compiled, interpreted, cranelift_compiled})]
 // fn test_switch(run: Run) {
 //     let program = parse_program(
 //         "
@@ -51,7 +51,7 @@
 //     assert_eq!(memory[10], 3);
 // }
 
-// #[parameterized(run={compiled, interpreted})]
+// #[parameterized(run={compiled, interpreted, cranelift_compiled})]
 // fn test_switch_more_than_amount_wraps(run: Run) {
 //     let program = parse_program(
 //         "
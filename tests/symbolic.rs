@@ -0,0 +1,260 @@
+mod common;
+
+use aleven::{parse_program, Program, SymbolicExecution};
+use common::random_programs;
+use rustc_hash::FxHashMap;
+
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+fn parse_sexps(text: &str) -> Vec<Sexp> {
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let text = text
+        .lines()
+        .filter(|line| !line.starts_with(';'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let spaced = text.replace('(', " ( ").replace(')', " ) ");
+    for token in spaced.split_whitespace() {
+        match token {
+            "(" => stack.push(Vec::new()),
+            ")" => {
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Sexp::List(list));
+            }
+            atom => stack.last_mut().unwrap().push(Sexp::Atom(atom.to_string())),
+        }
+    }
+    assert_eq!(stack.len(), 1, "unbalanced parentheses");
+    stack.pop().unwrap()
+}
+
+fn atom(sexp: &Sexp) -> &str {
+    match sexp {
+        Sexp::Atom(atom) => atom,
+        Sexp::List(_) => panic!("expected an atom"),
+    }
+}
+
+// a value along with its width in bits, where booleans have a width of 0
+type Value = (u64, u32);
+
+fn mask(width: u32) -> u64 {
+    (1 << width.max(1)) - 1
+}
+
+fn signed((value, width): Value) -> i64 {
+    ((value << (64 - width)) as i64) >> (64 - width)
+}
+
+// evaluate an expression of the subset of SMT-LIB2 the symbolic execution
+// writes out
+fn evaluate(sexp: &Sexp, env: &FxHashMap<String, Value>) -> Value {
+    let list = match sexp {
+        Sexp::Atom(atom) => {
+            return match atom.as_str() {
+                "true" => (1, 0),
+                "false" => (0, 0),
+                _ if atom.starts_with("#x") => (
+                    u64::from_str_radix(&atom[2..], 16).unwrap(),
+                    4 * (atom.len() as u32 - 2),
+                ),
+                _ if atom.starts_with("#b") => (
+                    u64::from_str_radix(&atom[2..], 2).unwrap(),
+                    atom.len() as u32 - 2,
+                ),
+                name => *env
+                    .get(name)
+                    .unwrap_or_else(|| panic!("{} is undefined", name)),
+            }
+        }
+        Sexp::List(list) => list,
+    };
+    let args: Vec<Value> = list[1..].iter().map(|arg| evaluate(arg, env)).collect();
+    let (a, width) = args[0];
+    let b = args.get(1).map(|arg| arg.0).unwrap_or(0);
+    if let Sexp::List(indexed) = &list[0] {
+        let index = |i: usize| atom(&indexed[i]).parse::<u32>().unwrap();
+        return match atom(&indexed[1]) {
+            "extract" => (
+                (a >> index(3)) & mask(index(2) - index(3) + 1),
+                index(2) - index(3) + 1,
+            ),
+            "sign_extend" => (
+                signed(args[0]) as u64 & mask(width + index(2)),
+                width + index(2),
+            ),
+            "zero_extend" => (a, width + index(2)),
+            other => panic!("unknown operation {}", other),
+        };
+    }
+    let boolean = |value: bool| (value as u64, 0);
+    match atom(&list[0]) {
+        "bvadd" => (a.wrapping_add(b) & mask(width), width),
+        "bvsub" => (a.wrapping_sub(b) & mask(width), width),
+        "bvmul" => (a.wrapping_mul(b) & mask(width), width),
+        "bvand" | "and" => (a & b, width),
+        "bvor" | "or" => (a | b, width),
+        "bvxor" => (a ^ b, width),
+        "not" => (a ^ 1, 0),
+        "bvshl" => (
+            if b < width as u64 {
+                (a << b) & mask(width)
+            } else {
+                0
+            },
+            width,
+        ),
+        "bvlshr" => (if b < width as u64 { a >> b } else { 0 }, width),
+        "bvashr" => ((signed(args[0]) >> b.min(63)) as u64 & mask(width), width),
+        "=" => boolean(a == b),
+        "bvslt" => boolean(signed(args[0]) < signed(args[1])),
+        "bvult" => boolean(a < b),
+        "ite" => {
+            if a != 0 {
+                args[1]
+            } else {
+                args[2]
+            }
+        }
+        "concat" => ((a << args[1].1) | b, width + args[1].1),
+        other => panic!("unknown operation {}", other),
+    }
+}
+
+// run the SMT-LIB2 text on memory, giving the memory after the run along with
+// whether each assertion holds
+fn run_smt2(text: &str, memory: &[u8]) -> (Vec<u8>, Vec<bool>) {
+    let mut env: FxHashMap<String, Value> = FxHashMap::default();
+    for (index, byte) in memory.iter().enumerate() {
+        env.insert(format!("in_{}", index), (*byte as u64, 8));
+    }
+    let mut assertions = Vec::new();
+    for sexp in parse_sexps(text) {
+        let Sexp::List(list) = sexp else {
+            panic!("expected a command");
+        };
+        match atom(&list[0]) {
+            "define-fun" => {
+                let value = evaluate(&list[4], &env);
+                env.insert(atom(&list[1]).to_string(), value);
+            }
+            "assert" => assertions.push(evaluate(&list[1], &env).0 != 0),
+            _ => {}
+        }
+    }
+    let outputs = (0..memory.len())
+        .map(|index| env[&format!("out_{}", index)].0 as u8)
+        .collect();
+    (outputs, assertions)
+}
+
+fn interpret(program: &Program, memory: &[u8]) -> Vec<u8> {
+    let mut memory = memory.to_vec();
+    program.interpret(&mut memory);
+    memory
+}
+
+#[test]
+fn test_constants_are_folded() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 40
+        r1 = addi r1 2
+        sb r0 10 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let text = SymbolicExecution::new(&program, 16).to_smt2(&[]);
+    assert!(text.contains("(declare-const in_15 (_ BitVec 8))"));
+    assert!(text.contains("(define-fun out_10 () (_ BitVec 8) #x2a)"));
+    assert!(text.contains("(define-fun out_3 () (_ BitVec 8) in_3)"));
+    assert!(!text.contains("(define-fun t"));
+}
+
+#[test]
+fn test_query() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lbu r0 0
+        r1 = addi r1 5
+        sb r0 10 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let text = SymbolicExecution::new(&program, 16).to_smt2(&["(= out_10 #x2a)"]);
+    assert!(text.contains("(assert (= out_10 #x2a))\n(check-sat)\n(get-value (in_0 in_1"));
+
+    let mut memory = [0u8; 16];
+    memory[0] = 37;
+    let (outputs, assertions) = run_smt2(&text, &memory);
+    assert_eq!(outputs, interpret(&program, &memory));
+    assert_eq!(assertions, vec![true]);
+    memory[0] = 38;
+    assert_eq!(run_smt2(&text, &memory).1, vec![false]);
+}
+
+#[test]
+fn test_branches_calls_and_repeat() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lb r0 0
+        r2 = addi r0 3
+        blt r1 r2 small
+        call big
+        beq r0 r0 end
+        target small
+        call small
+        target end
+        r5 = rand
+        sh r0 8 = r5
+    }
+
+    repeat big 4 {
+        r6 = lbu r0 1
+        sb r6 10 = r1
+        r3 = addi r3 1
+    }
+
+    func small {
+        r4 = addi r0 -1
+        sh r0 10 = r4
+        r5 = rand
+    }
+    ",
+    )
+    .unwrap();
+    let symbolic = SymbolicExecution::new(&program, 32);
+    let text = symbolic.to_smt2(&[]);
+    for (first, second) in [(7, 0), (2, 0), (-5i8 as u8, 3), (100, 20), (9, 200)] {
+        let mut memory = [0u8; 32];
+        memory[0] = first;
+        memory[1] = second;
+        let expected = interpret(&program, &memory);
+        assert_eq!(symbolic.evaluate(&memory), expected);
+        assert_eq!(run_smt2(&text, &memory).0, expected);
+    }
+}
+
+#[test]
+fn test_random_programs() {
+    for (i, (program, memory)) in random_programs(0x27d4eb2f165667c5, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let symbolic = SymbolicExecution::new(&program, memory.len());
+        let expected = interpret(&program, &memory);
+        assert_eq!(symbolic.evaluate(&memory), expected, "program {}", i);
+        if i < 30 {
+            let text = symbolic.to_smt2(&[]);
+            assert_eq!(run_smt2(&text, &memory).0, expected, "program {}", i);
+        }
+    }
+}
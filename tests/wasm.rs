@@ -0,0 +1,235 @@
+mod common;
+
+use aleven::run::{interpreted, Run};
+use aleven::{compile_wasm, parse_program, MachineOptions, Program};
+use common::random_programs;
+use parameterized::parameterized;
+use std::fs;
+use wasmi::{Engine, Linker, Module, Store};
+
+const MEMORY_PTR: usize = 1000;
+
+fn run_wasm(program: &Program, memory: &mut [u8]) {
+    run_wasm_with_options(program, memory, MachineOptions::default());
+}
+
+fn run_wasm_with_options(program: &Program, memory: &mut [u8], options: MachineOptions) {
+    let wasm = compile_wasm(program, memory.len() as u32, options).unwrap();
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm[..]).unwrap();
+    let mut store = Store::new(&engine, ());
+    let linker = <Linker<()>>::new(&engine);
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    let wasm_memory = instance.get_memory(&store, "memory").unwrap();
+    wasm_memory.write(&mut store, MEMORY_PTR, memory).unwrap();
+    let run = instance.get_typed_func::<i32, ()>(&store, "run").unwrap();
+    run.call(&mut store, MEMORY_PTR as i32).unwrap();
+    wasm_memory.read(&store, MEMORY_PTR, memory).unwrap();
+}
+
+#[parameterized(run={run_wasm, interpreted})]
+fn test_stackmachine(run: Run) {
+    let program = parse_program(&fs::read_to_string("stackmachine.ale").unwrap()).unwrap();
+
+    let mut memory = [0u8; 1024];
+    memory[0] = 4;
+    memory[2] = 200;
+    memory[4] = 1;
+    memory[5] = 1;
+    memory[6] = 3; // add
+    memory[7] = 1;
+    memory[8] = 3;
+
+    run(&program, &mut memory);
+
+    assert_eq!(memory[400], 3);
+}
+
+#[parameterized(run={run_wasm, interpreted})]
+fn test_branches_and_repeat(run: Run) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lb r0 0
+        r2 = addi r0 3
+        blt r1 r2 small
+        call big
+        beq r0 r0 end
+        target small
+        call small
+        target end
+    }
+
+    repeat big 4 {
+        sb r3 10 = r1
+        r3 = addi r3 1
+    }
+
+    func small {
+        r4 = addi r0 -1
+        sh r0 10 = r4
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    memory[0] = 7;
+    run(&program, &mut memory);
+    assert_eq!(&memory[10..15], &[7, 7, 7, 7, 0]);
+
+    let mut memory = [0u8; 64];
+    memory[0] = 2;
+    run(&program, &mut memory);
+    assert_eq!(&memory[20..22], &[255, 255]);
+}
+
+#[parameterized(run={run_wasm, interpreted})]
+fn test_out_of_bounds(run: Run) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 -1
+        sb r1 0 = r1
+        sh r1 0 = r1
+        sb r0 64 = r1
+        sh r0 32 = r1
+        r2 = lb r0 64
+        r3 = lh r0 32
+        sb r0 0 = r2
+        sb r0 1 = r3
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [1u8; 64];
+    run(&program, &mut memory);
+    assert_eq!(memory[0], 0);
+    assert_eq!(memory[1], 0);
+    assert!(memory[2..].iter().all(|value| *value == 1));
+}
+
+// i32.shl takes the amount as unsigned, so the interpreter has to as well
+#[parameterized(run={run_wasm, interpreted})]
+fn test_slli_negative(run: Run) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r1 5
+        r2 = slli r1 -2
+        sb r0 10 = r2
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    run(&program, &mut memory);
+    assert_eq!(memory[10], 5);
+}
+
+// a function that calls itself would recurse until the wasm stack overflows,
+// so the call has to be cleaned up wherever the function is
+#[parameterized(run={run_wasm, interpreted})]
+fn test_recursion_in_called_function(run: Run) {
+    let program = parse_program(
+        "
+    func main {
+        call inner
+    }
+
+    func inner {
+        r1 = addi r1 1
+        sb r0 0 = r1
+        call inner
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    run(&program, &mut memory);
+    assert_eq!(memory[0], 1);
+}
+
+#[test]
+fn test_random_programs_same_as_interpreter() {
+    for (program, memory) in random_programs(0x2545f4914f6cdd1d, 500)
+        .into_iter()
+        .filter(|(program, _)| !program.calls_host_functions())
+    {
+        let mut memory_wasm = memory.clone();
+        let mut memory_interpreter = memory;
+
+        run_wasm(&program, &mut memory_wasm);
+        program.interpret(&mut memory_interpreter);
+
+        assert_eq!(memory_wasm, memory_interpreter, "{:?}", program);
+    }
+}
+
+#[test]
+fn test_ecall_rejected() {
+    let program = parse_program("func main {\n    ecall 0\n}").unwrap();
+    assert!(compile_wasm(&program, 64, MachineOptions::default()).is_err());
+}
+
+#[test]
+fn test_zero_register() {
+    let program = parse_program(
+        "
+    func main {
+        r0 = addi r0 5
+        r1 = addi r0 3
+        r0 = lbu r1 0
+        r0 = add r1 r1
+        sb r0 10 = r1
+        sh r0 6 = r0
+    }
+    ",
+    )
+    .unwrap();
+
+    let mut memory = [0u8; 64];
+    memory[3] = 100;
+    run_wasm_with_options(
+        &program,
+        &mut memory,
+        MachineOptions {
+            zero_register: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(memory[10], 3);
+    assert_eq!(&memory[12..14], &[0, 0]);
+}
+
+#[test]
+fn test_unsupported_options_rejected() {
+    let program = parse_program("func main {\n    r1 = addi r0 1\n}").unwrap();
+    for options in [
+        MachineOptions {
+            window_size: Some(16),
+            ..Default::default()
+        },
+        MachineOptions {
+            count_violations: true,
+            ..Default::default()
+        },
+        MachineOptions {
+            profile_memory: true,
+            ..Default::default()
+        },
+        MachineOptions {
+            profile_execution: true,
+            ..Default::default()
+        },
+    ] {
+        assert!(compile_wasm(&program, 64, options).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+#[macro_use]
+mod backends;
+mod common;
+
+#[cfg(feature = "llvm")]
+use aleven::run::compiled_with_processor;
+#[cfg(feature = "cranelift")]
+use aleven::run::cranelift_compiled_with_processor;
+use aleven::run::{interpreted_with_processor, RunWithProcessor};
+use aleven::{parse_program, MachineOptions, Processor, Program, World};
+use common::random_programs;
+
+backend_tests! {
+    (interpreted_with_processor, compiled_with_processor, cranelift_compiled_with_processor) =>
+        test_window_relative_to_base,
+        test_window_wraps_around,
+        test_outside_window_ignored,
+        test_window_larger_than_memory,
+        test_window_in_large_memory,
+        test_base_beyond_memory;
+}
+
+fn windowed(window_size: u16, base: usize) -> Processor {
+    let mut processor = Processor::with_options(MachineOptions {
+        window_size: Some(window_size),
+        ..Default::default()
+    });
+    processor.set_base(base);
+    processor
+}
+
+fn test_window_relative_to_base(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lbu r0 1
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0u8; 16];
+    memory[5] = 10;
+    run(&program, &mut memory, &mut windowed(8, 4));
+    assert_eq!(memory[4], 11);
+    assert_eq!(memory.iter().filter(|byte| **byte != 0).count(), 2);
+}
+
+fn test_base_beyond_memory(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lbu r0 1
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+    // the base wraps around the end of memory, even if it's beyond 4 GiB
+    for (base, start) in [((1 << 32) + 4, 8), (usize::MAX, 3)] {
+        let mut memory = [0u8; 12];
+        memory[(start + 1) % 12] = 10;
+        run(&program, &mut memory, &mut windowed(8, base));
+        assert_eq!(memory[start], 11, "base {}", base);
+    }
+}
+
+fn test_window_wraps_around(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 258
+        sh r0 0 = r1
+        r2 = lh r0 0
+        r2 = addi r2 1
+        sh r0 1 = r2
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0u8; 16];
+    run(&program, &mut memory, &mut windowed(8, 15));
+    // the halfword at window address 0 straddles the end of memory
+    assert_eq!(memory[15], 2);
+    assert_eq!(memory[0], 1);
+    assert_eq!(&memory[1..3], &[3, 1]);
+}
+
+fn test_outside_window_ignored(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 -1
+        sb r0 3 = r1
+        sb r0 4 = r1
+        sh r0 2 = r1
+        r2 = addi r0 7
+        r2 = lbu r0 4
+        sb r0 0 = r2
+        r3 = addi r0 8
+        r3 = lh r0 2
+        sb r0 1 = r3
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0u8; 16];
+    memory[2] = 5;
+    memory[3] = 5;
+    memory[6] = 99;
+    run(&program, &mut memory, &mut windowed(4, 2));
+    assert_eq!(memory, [0, 0, 0, 0, 0, 255, 99, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+fn test_window_larger_than_memory(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r0 1
+        sb r0 0 = r1
+        sb r0 5 = r1
+        sb r0 10 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let mut memory = [0u8; 4];
+    run(&program, &mut memory, &mut windowed(12, 1));
+    assert_eq!(memory, [0, 1, 1, 1]);
+}
+
+fn test_window_in_large_memory(run: RunWithProcessor) {
+    let program = parse_program(
+        "
+    func main {
+        r1 = lbu r0 0
+        r1 = addi r1 1
+        sb r0 1 = r1
+        r2 = addi r0 258
+        sh r0 2 = r2
+    }
+    ",
+    )
+    .unwrap();
+    // memory sizes that don't fit in 16 bits
+    for memory_size in [65536, 70000] {
+        let mut memory = vec![0u8; memory_size];
+        memory[memory_size - 1] = 10;
+        run(&program, &mut memory, &mut windowed(8, memory_size - 1));
+        assert_eq!(memory[0], 11);
+        assert_eq!(&memory[3..5], &[2, 1]);
+        assert_eq!(memory.iter().filter(|byte| **byte != 0).count(), 4);
+    }
+}
+
+#[test]
+fn test_windowed_random_programs() {
+    let runs: Vec<RunWithProcessor> = vec![
+        #[cfg(feature = "llvm")]
+        compiled_with_processor,
+        #[cfg(feature = "cranelift")]
+        cranelift_compiled_with_processor,
+    ];
+    for (i, (program, memory)) in random_programs(0xd1342543de82ef95, 300)
+        .into_iter()
+        .enumerate()
+    {
+        let window_size = 64 + (i * 7) % memory.len();
+        let base = (i * 13) % memory.len();
+
+        let mut memory_interpreted = memory.clone();
+        let mut processor_interpreted = windowed(window_size as u16, base);
+        interpreted_with_processor(
+            &program,
+            &mut memory_interpreted,
+            &mut processor_interpreted,
+        );
+
+        for run in &runs {
+            let mut memory_compiled = memory.clone();
+            let mut processor_compiled = windowed(window_size as u16, base);
+            run(&program, &mut memory_compiled, &mut processor_compiled);
+            assert_eq!(memory_compiled, memory_interpreted, "program {}", i);
+            assert_eq!(
+                processor_compiled.registers(),
+                processor_interpreted.registers(),
+                "program {}",
+                i
+            );
+        }
+    }
+}
+
+fn increment() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = lbu r0 0
+        r1 = addi r1 1
+        sb r0 0 = r1
+        r2 = lbu r0 4
+        r2 = addi r2 1
+        sb r0 4 = r2
+    }
+    ",
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_world_windows() {
+    let mut world = World::new(vec![0; 16]);
+    world.add_interpreted(increment(), windowed(8, 0), 0, 100);
+    // this window wraps around, so that its address 4 is world address 0
+    world.add_interpreted(increment(), windowed(8, 0), 12, 100);
+    world.run(2);
+    assert_eq!(world.memory()[0], 4);
+    assert_eq!(world.memory()[4], 2);
+    assert_eq!(world.memory()[12], 2);
+    assert_eq!(world.agents()[1].processor().base(), 12);
+}
+
+#[test]
+fn test_large_world_windows() {
+    let runs: Vec<RunWithProcessor> = vec![
+        #[cfg(feature = "llvm")]
+        compiled_with_processor,
+        #[cfg(feature = "cranelift")]
+        cranelift_compiled_with_processor,
+    ];
+    let agents = 1 + runs.len();
+    let memory_size = 70000;
+    let mut world = World::new(vec![0; memory_size]);
+    world.add_interpreted(increment(), windowed(8, 0), memory_size - 4, 100);
+    for (i, run) in runs.into_iter().enumerate() {
+        world.add_compiled(
+            move |memory, processor| run(&increment(), memory, processor),
+            memory_size as u32,
+            windowed(8, 0),
+            memory_size - 3 + i,
+        );
+    }
+    world.run(3);
+    // each agent increments the byte at its base, and the byte 4 further on
+    // at the start of the world
+    let memory = world.memory();
+    assert!(memory[memory_size - 4..memory_size - 4 + agents]
+        .iter()
+        .all(|byte| *byte == 3));
+    assert!(memory[0..agents].iter().all(|byte| *byte == 3));
+    assert_eq!(memory.iter().filter(|byte| **byte != 0).count(), 2 * agents);
+}
@@ -0,0 +1,154 @@
+use aleven::{parse_program, Processor, Program, World};
+#[cfg(all(feature = "llvm", feature = "cranelift"))]
+use aleven::{CodeGen, CraneliftCodeGen, Function, FunctionValueCache};
+#[cfg(all(feature = "llvm", feature = "cranelift"))]
+use inkwell::context::Context;
+
+fn increment() -> Program {
+    parse_program(
+        "
+    func main {
+        r1 = lbu r0 0
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_whole_runs_in_a_turn() {
+    let mut world = World::new(vec![0; 16]);
+    world.add_interpreted(increment(), Processor::new(), 0, 100);
+    world.add_interpreted(increment(), Processor::new(), 0, 100);
+    world.step();
+    assert_eq!(world.memory()[0], 2);
+    assert_eq!(world.agents()[0].runs(), 1);
+    assert_eq!(world.agents()[1].runs(), 1);
+}
+
+#[test]
+fn test_interleaved_by_quantum() {
+    let mut world = World::new(vec![0; 16]);
+    world.add_interpreted(increment(), Processor::new(), 0, 1);
+    world.add_interpreted(increment(), Processor::new(), 0, 1);
+
+    // both agents load the value before either stores it
+    world.run(3);
+    assert_eq!(world.memory()[0], 1);
+    assert!(world.agents()[0].processor().is_paused());
+
+    // the synthetic target at the end of the function
+    world.step();
+    assert!(!world.agents()[0].processor().is_paused());
+    assert_eq!(world.agents()[0].runs(), 1);
+
+    world.run(4);
+    assert_eq!(world.memory()[0], 2);
+}
+
+#[test]
+fn test_bases() {
+    let mut world = World::new(vec![0; 16]);
+    world.add_interpreted(increment(), Processor::new(), 0, 100);
+    world.add_interpreted(increment(), Processor::new(), 10, 100);
+    world.add_interpreted(increment(), Processor::new(), 100, 100);
+    world.run(3);
+    assert_eq!(world.memory()[0], 3);
+    assert_eq!(world.memory()[10], 3);
+    assert_eq!(world.agents()[2].runs(), 3);
+}
+
+#[test]
+fn test_registers_kept_between_runs() {
+    let program = parse_program(
+        "
+    func main {
+        r1 = addi r1 1
+        sb r0 0 = r1
+    }
+    ",
+    )
+    .unwrap();
+    let mut world = World::new(vec![0; 4]);
+    world.add_interpreted(program, Processor::new(), 0, 1);
+    world.run(9);
+    assert_eq!(world.agents()[0].runs(), 3);
+    assert_eq!(world.memory()[0], 3);
+}
+
+#[test]
+fn test_compiled_memory_size() {
+    let mut world = World::new(vec![0; 16]);
+    world.add_compiled(
+        |memory, _processor| {
+            assert_eq!(memory.len(), 8);
+            memory[0] += 1;
+        },
+        8,
+        Processor::new(),
+        4,
+    );
+    world.run(2);
+    assert_eq!(world.memory()[4], 2);
+    assert_eq!(world.agents()[0].runs(), 2);
+
+    // from here there are fewer than 8 bytes left, so the agent can't run
+    world.agents_mut()[0].set_base(12);
+    world.run(2);
+    assert_eq!(world.memory()[12], 0);
+    assert_eq!(world.agents()[0].runs(), 2);
+}
+
+#[cfg(all(feature = "llvm", feature = "cranelift"))]
+#[test]
+fn test_compiled_and_interpreted_agents() {
+    let program = parse_program(
+        "
+    func main {
+        r2 = rand
+        r1 = addi r1 1
+        sb r0 0 = r1
+        sh r0 1 = r2
+    }
+    ",
+    )
+    .unwrap();
+    let memory_size = 64;
+
+    let context = Context::create();
+    let codegen = CodeGen::new(&context);
+    let llvm_func = program.compile(0, &codegen, memory_size - 8, &mut FunctionValueCache::new());
+    let mut cranelift_codegen = CraneliftCodeGen::new();
+    let cranelift_func = cranelift_codegen.compile_program(&program, memory_size - 16);
+
+    let mut world = World::new(vec![0; memory_size as usize]);
+    world.add_interpreted(program.clone(), Processor::new(), 0, 1000);
+    world.add_compiled(
+        move |memory, processor| Function::run_with_processor(&llvm_func, memory, processor, &[]),
+        memory_size - 8,
+        Processor::new(),
+        8,
+    );
+    world.add_compiled(
+        move |memory, processor| cranelift_func.run_with_processor(memory, processor, &[]),
+        memory_size - 16,
+        Processor::new(),
+        16,
+    );
+    world.run(5);
+
+    let memory = world.memory();
+    assert_eq!(memory[0], 5);
+    assert_eq!(&memory[0..8], &memory[8..16]);
+    assert_eq!(&memory[0..8], &memory[16..24]);
+    for agent in world.agents() {
+        assert_eq!(agent.runs(), 5);
+        assert_eq!(agent.processor().registers()[1], 5);
+        assert_eq!(
+            agent.processor().rng_state(),
+            world.agents()[0].processor().rng_state()
+        );
+    }
+}